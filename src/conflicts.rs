@@ -0,0 +1,401 @@
+//! Showing the conflicts in a file with git conflict markers.
+//!
+//! A file with conflict markers usually doesn't parse, so diffing it
+//! directly produces lots of error nodes. Instead, we split each
+//! conflict into its two sides and diff two versions of the whole
+//! file: one using our side of the conflict and one using theirs.
+//! Other conflicts use our side in both versions, so each diff only
+//! shows a single conflict.
+//!
+//! Conflicts in the diff3 and zdiff3 styles also include the common
+//! ancestor. For these, we also show our side to the base, and the
+//! base to their side.
+
+use std::path::Path;
+
+use crate::{
+    exit_codes::EXIT_BAD_ARGUMENTS,
+    files::read_or_die,
+    options::{DiffOptions, DisplayOptions, FileArgument},
+    parse::guess_language::Language,
+    summary::DiffResult,
+};
+
+/// The length of a conflict marker, e.g. `<<<<<<<`.
+const MARKER_LEN: usize = 7;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Conflict<'a> {
+    /// The name of our side, usually `HEAD`.
+    ours_label: &'a str,
+    /// The name of their side, usually a branch or commit.
+    theirs_label: &'a str,
+    /// The name of the common ancestor, usually a commit.
+    base_label: &'a str,
+    ours: &'a str,
+    /// The common ancestor, if the conflict uses the diff3 or zdiff3
+    /// style.
+    base: Option<&'a str>,
+    theirs: &'a str,
+}
+
+/// One of the versions in a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Base,
+    Theirs,
+}
+
+impl<'a> Conflict<'a> {
+    /// The text of `side`. If there's no base, our side is used
+    /// instead.
+    fn text(&self, side: Side) -> &'a str {
+        match side {
+            Side::Ours => self.ours,
+            Side::Base => self.base.unwrap_or(self.ours),
+            Side::Theirs => self.theirs,
+        }
+    }
+
+    /// The name of `side`, e.g. `HEAD`.
+    fn label(&self, side: Side) -> &'a str {
+        let (label, default) = match side {
+            Side::Ours => (self.ours_label, "ours"),
+            Side::Base => (self.base_label, "base"),
+            Side::Theirs => (self.theirs_label, "theirs"),
+        };
+        if label.is_empty() {
+            default
+        } else {
+            label
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Conflict(Conflict<'a>),
+}
+
+/// If `line` is a conflict marker made of `marker_char`, return the
+/// label after it.
+fn marker_label(line: &str, marker_char: char) -> Option<&str> {
+    let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+    let marker_len = line.len() - line.trim_start_matches(marker_char).len();
+    if marker_len != MARKER_LEN {
+        return None;
+    }
+
+    let label = &line[MARKER_LEN..];
+    if label.is_empty() || label.starts_with(' ') {
+        Some(label.trim())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Text,
+    Ours,
+    /// The common ancestor, shown by diff3 and zdiff3 conflict
+    /// styles.
+    Base,
+    Theirs,
+}
+
+/// Split `src` into text and conflicts.
+fn parse_segments(src: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = vec![];
+    let mut state = State::Text;
+
+    let mut text_start = 0;
+    let mut ours_label = "";
+    let mut ours = (0, 0);
+    let mut base_label = "";
+    let mut base = None;
+    let mut theirs_start = 0;
+
+    let mut offset = 0;
+    for (line_num, line) in src.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+
+        match state {
+            State::Text => {
+                if let Some(label) = marker_label(line, '<') {
+                    segments.push(Segment::Text(&src[text_start..line_start]));
+                    ours_label = label;
+                    ours = (offset, offset);
+                    base_label = "";
+                    base = None;
+                    state = State::Ours;
+                }
+            }
+            State::Ours | State::Base => {
+                let base_marker = if state == State::Ours {
+                    marker_label(line, '|')
+                } else {
+                    None
+                };
+                if let Some(label) = base_marker {
+                    ours.1 = line_start;
+                    base_label = label;
+                    base = Some((offset, offset));
+                    state = State::Base;
+                } else if marker_label(line, '=') == Some("") {
+                    match &mut base {
+                        Some((_, base_end)) => *base_end = line_start,
+                        None => ours.1 = line_start,
+                    }
+                    theirs_start = offset;
+                    state = State::Theirs;
+                } else if marker_label(line, '>').is_some() || marker_label(line, '<').is_some() {
+                    return Err(format!("Expected ======= before line {}.", line_num + 1));
+                }
+            }
+            State::Theirs => {
+                if let Some(theirs_label) = marker_label(line, '>') {
+                    segments.push(Segment::Conflict(Conflict {
+                        ours_label,
+                        theirs_label,
+                        base_label,
+                        ours: &src[ours.0..ours.1],
+                        base: base.map(|(start, end)| &src[start..end]),
+                        theirs: &src[theirs_start..line_start],
+                    }));
+                    text_start = offset;
+                    state = State::Text;
+                } else if marker_label(line, '<').is_some() || marker_label(line, '=') == Some("") {
+                    return Err(format!("Expected >>>>>>> before line {}.", line_num + 1));
+                }
+            }
+        }
+    }
+
+    if state != State::Text {
+        return Err("Conflict is missing its closing >>>>>>> marker.".into());
+    }
+    segments.push(Segment::Text(&src[text_start..]));
+    Ok(segments)
+}
+
+/// Build the versions of the file for the conflict at
+/// `conflict_index`: one with `lhs_side` and one with `rhs_side`.
+/// Other conflicts use our side in both.
+fn resolve(
+    segments: &[Segment],
+    conflict_index: usize,
+    lhs_side: Side,
+    rhs_side: Side,
+) -> (String, String) {
+    let mut lhs = String::new();
+    let mut rhs = String::new();
+
+    let mut i = 0;
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => {
+                lhs.push_str(text);
+                rhs.push_str(text);
+            }
+            Segment::Conflict(conflict) => {
+                if i == conflict_index {
+                    lhs.push_str(conflict.text(lhs_side));
+                    rhs.push_str(conflict.text(rhs_side));
+                } else {
+                    lhs.push_str(conflict.ours);
+                    rhs.push_str(conflict.ours);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    (lhs, rhs)
+}
+
+/// Diff both sides of every conflict in the file at `path`. Conflicts
+/// with a base are also diffed from our side to the base, and from
+/// the base to their side.
+fn diff_conflicts(
+    path: &Path,
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<Language>,
+) -> Vec<DiffResult> {
+    let bytes = read_or_die(path);
+    let src = String::from_utf8_lossy(&bytes);
+    let segments = match parse_segments(&src) {
+        Ok(segments) => segments,
+        Err(e) => {
+            eprintln!(
+                "error: Invalid conflict markers in {}: {}",
+                path.display(),
+                e
+            );
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    };
+
+    let conflicts: Vec<&Conflict> = segments
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Conflict(conflict) => Some(conflict),
+            Segment::Text(_) => None,
+        })
+        .collect();
+
+    let file_argument = FileArgument::NamedPath(path.into());
+    let mut res = vec![];
+    for (i, conflict) in conflicts.iter().enumerate() {
+        let side_pairs: &[(Side, Side)] = if conflict.base.is_some() {
+            &[
+                (Side::Ours, Side::Theirs),
+                (Side::Ours, Side::Base),
+                (Side::Base, Side::Theirs),
+            ]
+        } else {
+            &[(Side::Ours, Side::Theirs)]
+        };
+
+        for &(lhs_side, rhs_side) in side_pairs {
+            let (lhs_src, rhs_src) = resolve(&segments, i, lhs_side, rhs_side);
+            // Diff with the real path, so we can guess the language.
+            let mut diff_result = crate::diff_file_content(
+                &file_argument.display(),
+                &file_argument.display(),
+                &file_argument,
+                &file_argument,
+                lhs_src.as_bytes(),
+                rhs_src.as_bytes(),
+                display_options,
+                diff_options,
+                language_override,
+            );
+
+            let display_path = format!(
+                "{} (conflict {}/{}: {} → {})",
+                file_argument.display(),
+                i + 1,
+                conflicts.len(),
+                conflict.label(lhs_side),
+                conflict.label(rhs_side),
+            );
+            diff_result.lhs_display_path = display_path.clone();
+            diff_result.rhs_display_path = display_path;
+            res.push(diff_result);
+        }
+    }
+
+    res
+}
+
+/// Show a diff between our side and their side of each conflict in
+/// the file at `path`, followed by diffs against the base when the
+/// conflict has one.
+pub fn show_conflicts(
+    path: &Path,
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<Language>,
+) {
+    let diff_results = diff_conflicts(path, display_options, diff_options, language_override);
+    if diff_results.is_empty() {
+        println!("No conflicts found in {}.", path.display());
+        return;
+    }
+
+    if display_options.interactive {
+        crate::view_diff_results(&diff_results, display_options);
+    } else {
+        for diff_result in &diff_results {
+            crate::print_diff_result(display_options, diff_result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SRC: &str = "a = 1
+<<<<<<< HEAD
+b = 2
+||||||| base
+b = 0
+=======
+b = 3
+>>>>>>> feature
+c = 4
+<<<<<<< HEAD
+d = 5
+=======
+>>>>>>> other
+";
+
+    #[test]
+    fn test_parse_segments() {
+        let segments = parse_segments(SRC).unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("a = 1\n"),
+                Segment::Conflict(Conflict {
+                    ours_label: "HEAD",
+                    theirs_label: "feature",
+                    base_label: "base",
+                    ours: "b = 2\n",
+                    base: Some("b = 0\n"),
+                    theirs: "b = 3\n",
+                }),
+                Segment::Text("c = 4\n"),
+                Segment::Conflict(Conflict {
+                    ours_label: "HEAD",
+                    theirs_label: "other",
+                    base_label: "",
+                    ours: "d = 5\n",
+                    base: None,
+                    theirs: "",
+                }),
+                Segment::Text(""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_segments_unclosed() {
+        assert!(parse_segments("<<<<<<< HEAD\nx\n=======\ny\n").is_err());
+        assert!(parse_segments("<<<<<<< HEAD\nx\n>>>>>>> main\n").is_err());
+    }
+
+    #[test]
+    fn test_marker_label() {
+        assert_eq!(marker_label("<<<<<<< HEAD\n", '<'), Some("HEAD"));
+        assert_eq!(marker_label("=======\r\n", '='), Some(""));
+        assert_eq!(marker_label("========\n", '='), None);
+        assert_eq!(marker_label("<<<<<<<HEAD\n", '<'), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let segments = parse_segments(SRC).unwrap();
+        assert_eq!(
+            resolve(&segments, 1, Side::Ours, Side::Theirs),
+            (
+                "a = 1\nb = 2\nc = 4\nd = 5\n".to_owned(),
+                "a = 1\nb = 2\nc = 4\n".to_owned()
+            )
+        );
+        assert_eq!(
+            resolve(&segments, 0, Side::Base, Side::Theirs),
+            (
+                "a = 1\nb = 0\nc = 4\nd = 5\n".to_owned(),
+                "a = 1\nb = 3\nc = 4\nd = 5\n".to_owned()
+            )
+        );
+    }
+}
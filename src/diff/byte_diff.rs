@@ -0,0 +1,242 @@
+//! Finding the bytes that changed between two binary files.
+
+use std::ops::Range;
+
+use crate::diff::myers_diff;
+
+/// Chunks end where the rolling hash of the preceding bytes has
+/// these bits unset, so chunks are around 32 bytes on average.
+const CHUNK_BOUNDARY_MASK: u64 = 0b11111 << 59;
+const MIN_CHUNK_SIZE: usize = 8;
+const MAX_CHUNK_SIZE: usize = 128;
+
+/// The largest changed region, in bytes on each side, that we diff
+/// byte by byte. Larger regions are usually unrelated content, and
+/// diffing them is slow.
+const MAX_REGION_SIZE: usize = 1024;
+
+/// The largest file, in bytes, that we find byte changes for. The
+/// diff is quadratic when files have few chunks in common, so larger
+/// files are only summarised.
+pub const MAX_BYTE_DIFF_SIZE: usize = 64 * 1024;
+
+/// A run of bytes that were removed from the LHS and replaced with
+/// bytes in the RHS. Either range may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteChange {
+    pub lhs: Range<usize>,
+    pub rhs: Range<usize>,
+}
+
+/// Convert a diff of items into the byte ranges that changed, where
+/// `len` is the number of bytes in each item.
+fn byte_changes_from_diff<T>(
+    diff: Vec<myers_diff::DiffResult<&T>>,
+    len: impl Fn(&T) -> usize,
+) -> Vec<ByteChange> {
+    let mut res = vec![];
+    let mut current: Option<ByteChange> = None;
+    let mut lhs_offset = 0;
+    let mut rhs_offset = 0;
+
+    for item in diff {
+        match item {
+            myers_diff::DiffResult::Both(lhs_item, rhs_item) => {
+                if let Some(change) = current.take() {
+                    res.push(change);
+                }
+                lhs_offset += len(lhs_item);
+                rhs_offset += len(rhs_item);
+            }
+            myers_diff::DiffResult::Left(lhs_item) => {
+                let change = current.get_or_insert(ByteChange {
+                    lhs: lhs_offset..lhs_offset,
+                    rhs: rhs_offset..rhs_offset,
+                });
+                lhs_offset += len(lhs_item);
+                change.lhs.end = lhs_offset;
+            }
+            myers_diff::DiffResult::Right(rhs_item) => {
+                let change = current.get_or_insert(ByteChange {
+                    lhs: lhs_offset..lhs_offset,
+                    rhs: rhs_offset..rhs_offset,
+                });
+                rhs_offset += len(rhs_item);
+                change.rhs.end = rhs_offset;
+            }
+        }
+    }
+    if let Some(change) = current {
+        res.push(change);
+    }
+
+    res
+}
+
+/// A pseudo-random value for `byte`, used in the rolling hash of
+/// `chunks`. This is the splitmix64 finaliser.
+fn gear(byte: u8) -> u64 {
+    let mut z = u64::from(byte).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Split `bytes` into chunks whose boundaries depend on the 64 bytes
+/// before them, using a gear hash as in FastCDC. Inserting or
+/// removing bytes only changes the chunks near the edit, unlike
+/// chunks at fixed offsets.
+fn chunks(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut res = vec![];
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear(*byte));
+
+        let len = i + 1 - start;
+        if (len >= MIN_CHUNK_SIZE && hash & CHUNK_BOUNDARY_MASK == 0) || len >= MAX_CHUNK_SIZE {
+            res.push(&bytes[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < bytes.len() {
+        res.push(&bytes[start..]);
+    }
+    res
+}
+
+/// Find the changed bytes between `lhs` and `rhs`, in order, or
+/// `None` if either file is larger than `MAX_BYTE_DIFF_SIZE`.
+///
+/// We first diff chunks, which quickly skips the regions that are
+/// unchanged. We then diff each small changed region byte by byte,
+/// so insertions and deletions are aligned. Large changed regions
+/// are shown as replaced entirely.
+pub fn byte_changes(lhs: &[u8], rhs: &[u8]) -> Option<Vec<ByteChange>> {
+    if lhs.len() > MAX_BYTE_DIFF_SIZE || rhs.len() > MAX_BYTE_DIFF_SIZE {
+        return None;
+    }
+
+    let lhs_chunks = chunks(lhs);
+    let rhs_chunks = chunks(rhs);
+    let chunk_changes = byte_changes_from_diff(
+        myers_diff::slice_by_hash(&lhs_chunks, &rhs_chunks),
+        |chunk| chunk.len(),
+    );
+
+    let mut res = vec![];
+    for chunk_change in chunk_changes {
+        if chunk_change.lhs.len() > MAX_REGION_SIZE || chunk_change.rhs.len() > MAX_REGION_SIZE {
+            res.push(chunk_change);
+            continue;
+        }
+
+        let lhs_start = chunk_change.lhs.start;
+        let rhs_start = chunk_change.rhs.start;
+        let region_changes = byte_changes_from_diff(
+            myers_diff::slice(&lhs[chunk_change.lhs], &rhs[chunk_change.rhs]),
+            |_| 1,
+        );
+        res.extend(region_changes.into_iter().map(|change| ByteChange {
+            lhs: lhs_start + change.lhs.start..lhs_start + change.lhs.end,
+            rhs: rhs_start + change.rhs.start..rhs_start + change.rhs.end,
+        }));
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_byte_changes() {
+        let lhs: Vec<u8> = (0..64).collect();
+        let mut rhs = lhs.clone();
+        rhs[20] = 0xff;
+        assert_eq!(
+            byte_changes(&lhs, &rhs),
+            Some(vec![ByteChange {
+                lhs: 20..21,
+                rhs: 20..21
+            }])
+        );
+    }
+
+    #[test]
+    fn test_byte_changes_unaligned_insertion() {
+        let lhs: Vec<u8> = (0..=255).cycle().take(1025).collect();
+        let mut rhs = lhs.clone();
+        rhs.insert(5, 0xff);
+        assert_eq!(
+            byte_changes(&lhs, &rhs),
+            Some(vec![ByteChange {
+                lhs: 5..5,
+                rhs: 5..6
+            }])
+        );
+    }
+
+    /// Pseudo-random bytes from a linear congruential generator.
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u32 = 1;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks() {
+        let bytes = random_bytes(10_000);
+        let chunks = chunks(&bytes);
+        assert!(chunks
+            .iter()
+            .all(|chunk| !chunk.is_empty() && chunk.len() <= MAX_CHUNK_SIZE));
+        assert_eq!(chunks.concat(), bytes);
+    }
+
+    #[test]
+    fn test_byte_changes_insertion_large_file() {
+        let lhs = random_bytes(MAX_BYTE_DIFF_SIZE - 1);
+        let mut rhs = lhs.clone();
+        rhs.insert(5, 0xff);
+        rhs.remove(50_000);
+        assert_eq!(
+            byte_changes(&lhs, &rhs),
+            Some(vec![
+                ByteChange {
+                    lhs: 5..5,
+                    rhs: 5..6
+                },
+                ByteChange {
+                    lhs: 49_999..50_000,
+                    rhs: 50_000..50_000
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_byte_changes_insertion() {
+        let lhs: Vec<u8> = (0..64).collect();
+        let mut rhs = lhs.clone();
+        rhs.splice(16..16, (100..116).collect::<Vec<u8>>());
+        assert_eq!(
+            byte_changes(&lhs, &rhs),
+            Some(vec![ByteChange {
+                lhs: 16..16,
+                rhs: 16..32
+            }])
+        );
+    }
+
+    #[test]
+    fn test_byte_changes_too_large() {
+        let lhs = vec![0; MAX_BYTE_DIFF_SIZE + 1];
+        assert_eq!(byte_changes(&lhs, &[1]), None);
+    }
+}
@@ -10,6 +10,8 @@ use crate::parse::syntax::Syntax;
 pub enum ChangeKind<'a> {
     Unchanged(&'a Syntax<'a>),
     ReplacedComment(&'a Syntax<'a>, &'a Syntax<'a>),
+    /// A string or identifier that is similar to the one it replaced.
+    ReplacedAtom(&'a Syntax<'a>, &'a Syntax<'a>),
     Novel,
 }
 
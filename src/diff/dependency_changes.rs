@@ -0,0 +1,229 @@
+//! Comparing the dependencies in two versions of a lockfile or
+//! manifest.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::parse::lockfile::Dependency;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyChange {
+    Added {
+        name: String,
+        version: String,
+    },
+    Removed {
+        name: String,
+        version: String,
+    },
+    Updated {
+        name: String,
+        old_version: String,
+        new_version: String,
+    },
+}
+
+impl DependencyChange {
+    fn name(&self) -> &str {
+        match self {
+            DependencyChange::Added { name, .. }
+            | DependencyChange::Removed { name, .. }
+            | DependencyChange::Updated { name, .. } => name,
+        }
+    }
+}
+
+fn versions_by_name(dependencies: &[Dependency]) -> BTreeMap<&str, BTreeSet<&str>> {
+    let mut res: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for dependency in dependencies {
+        res.entry(&dependency.name)
+            .or_default()
+            .insert(&dependency.version);
+    }
+    res
+}
+
+/// Find the dependencies that were added, removed or updated, in
+/// order of name.
+pub fn find_dependency_changes(lhs: &[Dependency], rhs: &[Dependency]) -> Vec<DependencyChange> {
+    let lhs_versions = versions_by_name(lhs);
+    let rhs_versions = versions_by_name(rhs);
+    let empty = BTreeSet::new();
+
+    let names: BTreeSet<&str> = lhs_versions
+        .keys()
+        .chain(rhs_versions.keys())
+        .copied()
+        .collect();
+
+    let mut res = vec![];
+    for name in names {
+        let lhs_versions = lhs_versions.get(name).unwrap_or(&empty);
+        let rhs_versions = rhs_versions.get(name).unwrap_or(&empty);
+
+        // Lockfiles can contain several versions of a package. Pair
+        // up the old and new versions in order, so upgrading one of
+        // them is shown as an update.
+        let mut removed = lhs_versions.difference(rhs_versions);
+        let mut added = rhs_versions.difference(lhs_versions);
+        loop {
+            let change = match (removed.next(), added.next()) {
+                (Some(old_version), Some(new_version)) => DependencyChange::Updated {
+                    name: name.to_owned(),
+                    old_version: (*old_version).to_owned(),
+                    new_version: (*new_version).to_owned(),
+                },
+                (Some(version), None) => DependencyChange::Removed {
+                    name: name.to_owned(),
+                    version: (*version).to_owned(),
+                },
+                (None, Some(version)) => DependencyChange::Added {
+                    name: name.to_owned(),
+                    version: (*version).to_owned(),
+                },
+                (None, None) => break,
+            };
+            res.push(change);
+        }
+    }
+
+    res
+}
+
+/// Describe dependency changes as a summary sentence followed by a
+/// table with a row for each change.
+pub fn describe_dependency_changes(changes: &[DependencyChange]) -> Vec<String> {
+    if changes.is_empty() {
+        return vec![];
+    }
+
+    let count = |f: fn(&DependencyChange) -> bool| changes.iter().filter(|c| f(c)).count();
+    let mut counts = vec![];
+    for (num, description) in &[
+        (
+            count(|c| matches!(c, DependencyChange::Updated { .. })),
+            "updated",
+        ),
+        (
+            count(|c| matches!(c, DependencyChange::Added { .. })),
+            "added",
+        ),
+        (
+            count(|c| matches!(c, DependencyChange::Removed { .. })),
+            "removed",
+        ),
+    ] {
+        if *num > 0 {
+            counts.push(format!("{} {}", num, description));
+        }
+    }
+
+    let mut res = vec![format!(
+        "{} {} changed: {}.",
+        changes.len(),
+        if changes.len() == 1 {
+            "dependency"
+        } else {
+            "dependencies"
+        },
+        counts.join(", ")
+    )];
+
+    let name_width = changes
+        .iter()
+        .map(|change| change.name().chars().count())
+        .max()
+        .unwrap_or(0);
+    for change in changes {
+        let (kind, versions) = match change {
+            DependencyChange::Added { version, .. } => ("Added", version.clone()),
+            DependencyChange::Removed { version, .. } => ("Removed", version.clone()),
+            DependencyChange::Updated {
+                old_version,
+                new_version,
+                ..
+            } => ("Updated", format!("{} → {}", old_version, new_version)),
+        };
+        res.push(format!(
+            "  {:<7}  {:<width$}  {}",
+            kind,
+            change.name(),
+            versions,
+            width = name_width
+        ));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn dependencies(pairs: &[(&str, &str)]) -> Vec<Dependency> {
+        pairs
+            .iter()
+            .map(|(name, version)| Dependency {
+                name: (*name).to_owned(),
+                version: (*version).to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_dependency_changes() {
+        let lhs = dependencies(&[
+            ("serde", "1.0.150"),
+            ("foo", "0.1.0"),
+            ("syn", "1.0.0"),
+            ("syn", "2.0.0"),
+        ]);
+        let rhs = dependencies(&[
+            ("serde", "1.0.152"),
+            ("tokio", "1.24.0"),
+            ("syn", "1.0.0"),
+            ("syn", "2.0.1"),
+        ]);
+
+        assert_eq!(
+            find_dependency_changes(&lhs, &rhs),
+            vec![
+                DependencyChange::Removed {
+                    name: "foo".into(),
+                    version: "0.1.0".into()
+                },
+                DependencyChange::Updated {
+                    name: "serde".into(),
+                    old_version: "1.0.150".into(),
+                    new_version: "1.0.152".into()
+                },
+                DependencyChange::Updated {
+                    name: "syn".into(),
+                    old_version: "2.0.0".into(),
+                    new_version: "2.0.1".into()
+                },
+                DependencyChange::Added {
+                    name: "tokio".into(),
+                    version: "1.24.0".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_dependency_changes() {
+        let changes = find_dependency_changes(
+            &dependencies(&[("serde", "1.0.150"), ("foo", "0.1.0")]),
+            &dependencies(&[("serde", "1.0.152"), ("tokio", "1.24.0")]),
+        );
+        assert_eq!(
+            describe_dependency_changes(&changes),
+            vec![
+                "3 dependencies changed: 1 updated, 1 added, 1 removed.",
+                "  Removed  foo    0.1.0",
+                "  Updated  serde  1.0.150 → 1.0.152",
+                "  Added    tokio  1.24.0",
+            ]
+        );
+    }
+}
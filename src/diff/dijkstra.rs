@@ -666,6 +666,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn replace_similar_string() {
+        let arena = Arena::new();
+
+        let lhs = vec![Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "\"the quick brown fox\"",
+            AtomKind::String,
+        )];
+
+        let rhs = vec![Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "\"the quick brown cat\"",
+            AtomKind::String,
+        )];
+        init_all_info(&lhs, &rhs);
+
+        let start = Vertex::new(lhs.first().copied(), rhs.first().copied());
+        let vertex_arena = Bump::new();
+        let route = shortest_path(start, &vertex_arena, 0, DEFAULT_GRAPH_LIMIT).unwrap();
+
+        let actions = route.iter().map(|(action, _)| *action).collect_vec();
+        assert_eq!(
+            actions,
+            vec![ReplacedAtom {
+                levenshtein_pct: 86
+            }]
+        );
+    }
+
+    /// Regression test based on sample_files/json_before.json, where
+    /// the key `"bar"` was paired with the unrelated `"zab"`.
+    #[test]
+    fn mark_syntax_dissimilar_strings() {
+        let arena = Arena::new();
+        let lhs = Syntax::new_atom(&arena, pos_helper(1), "\"bar\"", AtomKind::String);
+        let rhs = Syntax::new_atom(&arena, pos_helper(1), "\"zab\"", AtomKind::String);
+        init_all_info(&[lhs], &[rhs]);
+
+        let mut change_map = ChangeMap::default();
+        mark_syntax(Some(lhs), Some(rhs), &mut change_map, DEFAULT_GRAPH_LIMIT).unwrap();
+        assert_eq!(change_map.get(lhs), Some(ChangeKind::Novel));
+        assert_eq!(change_map.get(rhs), Some(ChangeKind::Novel));
+    }
+
+    #[test]
+    fn mark_syntax_edited_comment() {
+        let arena = Arena::new();
+        let lhs = Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "the quick brown fox",
+            AtomKind::Comment,
+        );
+        let rhs = Syntax::new_atom(
+            &arena,
+            pos_helper(1),
+            "the slow brown dog",
+            AtomKind::Comment,
+        );
+        init_all_info(&[lhs], &[rhs]);
+
+        // These comments are only 63% similar, but we still highlight
+        // the changed words rather than the whole comment.
+        let mut change_map = ChangeMap::default();
+        mark_syntax(Some(lhs), Some(rhs), &mut change_map, DEFAULT_GRAPH_LIMIT).unwrap();
+        assert_eq!(
+            change_map.get(lhs),
+            Some(ChangeKind::ReplacedComment(lhs, rhs))
+        );
+        assert_eq!(
+            change_map.get(rhs),
+            Some(ChangeKind::ReplacedComment(rhs, lhs))
+        );
+    }
+
     #[test]
     fn mark_syntax_equal_atoms() {
         let arena = Arena::new();
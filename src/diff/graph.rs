@@ -15,7 +15,7 @@ use crate::{
         changes::{insert_deep_unchanged, ChangeKind, ChangeMap},
         stack::Stack,
     },
-    parse::syntax::{AtomKind, Syntax, SyntaxId},
+    parse::syntax::{is_identifier, AtomKind, Syntax, SyntaxId},
 };
 use Edge::*;
 
@@ -283,6 +283,9 @@ pub enum Edge {
     ReplacedComment {
         levenshtein_pct: u8,
     },
+    ReplacedAtom {
+        levenshtein_pct: u8,
+    },
     NovelAtomLHS {
         contiguous: bool,
         probably_punctuation: bool,
@@ -303,6 +306,16 @@ pub enum Edge {
 
 const NOT_CONTIGUOUS_PENALTY: u32 = 50;
 
+/// Replaced strings and identifiers that are less similar than this
+/// are shown as entirely novel. A replacement costs much less than a
+/// novel atom on each side, so it must be strict: otherwise unrelated
+/// atoms that share a few characters are paired up.
+const REPLACED_ATOM_MIN_SIMILARITY_PCT: u8 = 70;
+
+/// Replaced comments that are less similar than this are shown as
+/// entirely novel, rather than highlighting the changed words.
+const REPLACED_COMMENT_MIN_SIMILARITY_PCT: u8 = 40;
+
 impl Edge {
     pub fn cost(self) -> u32 {
         match self {
@@ -313,6 +326,8 @@ impl Edge {
 
             // Replacing a comment is better than treating it as novel.
             ReplacedComment { levenshtein_pct } => 150 + u32::from(100 - levenshtein_pct),
+            // Likewise for strings and identifiers with small changes.
+            ReplacedAtom { levenshtein_pct } => 150 + u32::from(100 - levenshtein_pct),
 
             // Otherwise, we've added/removed a node.
             NovelAtomLHS {
@@ -580,22 +595,48 @@ pub fn get_set_neighbours<'syn, 'b>(
         if let (
             Syntax::Atom {
                 content: lhs_content,
-                kind: AtomKind::Comment,
+                kind: lhs_kind,
                 ..
             },
             Syntax::Atom {
                 content: rhs_content,
-                kind: AtomKind::Comment,
+                kind: rhs_kind,
                 ..
             },
         ) = (lhs_syntax, rhs_syntax)
         {
-            // Both sides are comments and their content is reasonably
-            // similar.
-            if lhs_content != rhs_content {
-                let levenshtein_pct =
-                    (normalized_levenshtein(lhs_content, rhs_content) * 100.0).round() as u8;
+            let levenshtein_pct =
+                || (normalized_levenshtein(lhs_content, rhs_content) * 100.0).round() as u8;
+            let is_replaceable = match (lhs_kind, rhs_kind) {
+                (AtomKind::String, AtomKind::String) => true,
+                (AtomKind::Normal, AtomKind::Normal) => {
+                    is_identifier(lhs_content) && is_identifier(rhs_content)
+                }
+                _ => false,
+            };
+
+            let edge = if lhs_content == rhs_content {
+                None
+            } else if *lhs_kind == AtomKind::Comment && *rhs_kind == AtomKind::Comment {
+                // Both sides are comments and their content is
+                // reasonably similar.
+                Some(ReplacedComment {
+                    levenshtein_pct: levenshtein_pct(),
+                })
+            } else if is_replaceable {
+                // Strings and identifiers are only replaced when
+                // they're similar, so unrelated atoms stay novel.
+                let levenshtein_pct = levenshtein_pct();
+                if levenshtein_pct >= REPLACED_ATOM_MIN_SIMILARITY_PCT {
+                    Some(ReplacedAtom { levenshtein_pct })
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
 
+            if let Some(edge) = edge {
                 let (lhs_syntax, rhs_syntax, lhs_parent_id, rhs_parent_id, parents) =
                     pop_all_parents(
                         lhs_syntax.next_sibling(),
@@ -605,7 +646,7 @@ pub fn get_set_neighbours<'syn, 'b>(
                         &v.parents,
                     );
                 res.push((
-                    ReplacedComment { levenshtein_pct },
+                    edge,
                     allocate_if_new(
                         Vertex {
                             neighbours: RefCell::new(None),
@@ -802,7 +843,7 @@ pub fn populate_change_map<'a, 'b>(
                 let lhs = v.lhs_syntax.unwrap();
                 let rhs = v.rhs_syntax.unwrap();
 
-                if *levenshtein_pct > 40 {
+                if *levenshtein_pct >= REPLACED_COMMENT_MIN_SIMILARITY_PCT {
                     change_map.insert(lhs, ChangeKind::ReplacedComment(lhs, rhs));
                     change_map.insert(rhs, ChangeKind::ReplacedComment(rhs, lhs));
                 } else {
@@ -810,6 +851,12 @@ pub fn populate_change_map<'a, 'b>(
                     change_map.insert(rhs, ChangeKind::Novel);
                 }
             }
+            ReplacedAtom { .. } => {
+                let lhs = v.lhs_syntax.unwrap();
+                let rhs = v.rhs_syntax.unwrap();
+                change_map.insert(lhs, ChangeKind::ReplacedAtom(lhs, rhs));
+                change_map.insert(rhs, ChangeKind::ReplacedAtom(rhs, lhs));
+            }
             NovelAtomLHS { .. } | EnterNovelDelimiterLHS { .. } => {
                 let lhs = v.lhs_syntax.unwrap();
                 change_map.insert(lhs, ChangeKind::Novel);
@@ -1,7 +1,11 @@
+pub mod byte_diff;
 pub mod changes;
+pub mod dependency_changes;
 pub mod dijkstra;
 mod graph;
 pub mod myers_diff;
+pub mod renames;
 pub mod sliders;
 mod stack;
+pub mod table_changes;
 pub mod unchanged;
@@ -0,0 +1,472 @@
+//! Detecting identifiers that have been renamed consistently.
+//!
+//! Renaming a variable produces a novel atom on each side for every
+//! occurrence. If every occurrence of `foo` has been replaced with
+//! `bar`, we can report the rename once instead.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    diff::changes::{ChangeKind, ChangeMap},
+    lines::LineNumber,
+    parse::syntax::{is_identifier, MatchKind, MatchedPos, Syntax, SyntaxId},
+    positions::SingleLineSpan,
+};
+
+/// The outermost list containing a node, such as the function or
+/// class it's in, or `None` for top-level nodes.
+type Scope = Option<SyntaxId>;
+
+/// Renames with fewer occurrences than this are shown as ordinary
+/// changes.
+const MIN_OCCURRENCES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub old_name: String,
+    pub new_name: String,
+    pub occurrences: usize,
+}
+
+impl Rename {
+    pub fn describe(&self) -> String {
+        format!(
+            "Renamed `{}` → `{}` ({} occurrences).",
+            self.old_name, self.new_name, self.occurrences
+        )
+    }
+}
+
+fn identifier_content<'a>(node: &'a Syntax<'a>) -> Option<&'a str> {
+    match node {
+        Syntax::Atom { content, .. } if is_identifier(content) => Some(content),
+        _ => None,
+    }
+}
+
+fn enclosing_scope(node: &Syntax) -> Scope {
+    let mut scope = node.parent()?;
+    while let Some(parent) = scope.parent() {
+        scope = parent;
+    }
+    Some(scope.id())
+}
+
+/// Find the identifiers in `nodes` that are unchanged, and the scope
+/// of each.
+fn unchanged_identifiers<'a>(
+    nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    res: &mut FxHashSet<(Scope, &'a str)>,
+) {
+    for node in nodes {
+        match node {
+            Syntax::List { children, .. } => unchanged_identifiers(children, change_map, res),
+            Syntax::Atom { .. } => {
+                if let (Some(name), Some(ChangeKind::Unchanged(_))) =
+                    (identifier_content(node), change_map.get(node))
+                {
+                    res.insert((enclosing_scope(node), name));
+                }
+            }
+        }
+    }
+}
+
+/// The node after `node` with the same parent.
+fn following_sibling<'a>(node: &'a Syntax<'a>, roots: &[&'a Syntax<'a>]) -> Option<&'a Syntax<'a>> {
+    let siblings = match node.parent() {
+        Some(Syntax::List { children, .. }) => &children[..],
+        _ => roots,
+    };
+    let i = siblings.iter().position(|n| n.id() == node.id())?;
+    siblings.get(i + 1).copied()
+}
+
+/// Find the RHS node that occupies the same place as the `i`th node
+/// of `siblings`: the node after the unchanged node that precedes
+/// it, or the first of `opposite_siblings` if it's the first node.
+fn counterpart<'a>(
+    siblings: &[&'a Syntax<'a>],
+    i: usize,
+    opposite_siblings: Option<&[&'a Syntax<'a>]>,
+    rhs_roots: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+) -> Option<&'a Syntax<'a>> {
+    if i > 0 {
+        match change_map.get(siblings[i - 1]) {
+            Some(ChangeKind::Unchanged(prev_opposite)) => {
+                following_sibling(prev_opposite, rhs_roots)
+            }
+            _ => None,
+        }
+    } else {
+        opposite_siblings.and_then(|siblings| siblings.first().copied())
+    }
+}
+
+/// Find pairs of changed identifiers that occupy the same place in
+/// the LHS and RHS. `opposite_siblings` are the RHS nodes
+/// corresponding to `nodes`, if their parents are unchanged.
+fn substitutions<'a>(
+    nodes: &[&'a Syntax<'a>],
+    opposite_siblings: Option<&[&'a Syntax<'a>]>,
+    rhs_roots: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+    res: &mut Vec<(&'a Syntax<'a>, &'a Syntax<'a>)>,
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        match node {
+            Syntax::List { children, .. } => {
+                let opposite_children = match change_map.get(node) {
+                    Some(ChangeKind::Unchanged(Syntax::List { children, .. })) => {
+                        Some(&children[..])
+                    }
+                    _ => None,
+                };
+                substitutions(children, opposite_children, rhs_roots, change_map, res);
+            }
+            Syntax::Atom { .. } => {
+                let opposite = match change_map.get(node) {
+                    Some(ChangeKind::ReplacedAtom(_, opposite)) => Some(opposite),
+                    Some(ChangeKind::Novel) => {
+                        counterpart(nodes, i, opposite_siblings, rhs_roots, change_map)
+                            .filter(|opposite| change_map.get(opposite) == Some(ChangeKind::Novel))
+                    }
+                    _ => None,
+                };
+
+                if let Some(opposite) = opposite {
+                    if identifier_content(node).is_some() && identifier_content(opposite).is_some()
+                    {
+                        res.push((node, opposite));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Find identifiers that have been replaced by the same new name
+/// everywhere they occur in their scope. Returns the renames, and
+/// the positions of the renamed atoms on each side.
+pub fn find_renames<'a>(
+    lhs_roots: &[&'a Syntax<'a>],
+    rhs_roots: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+) -> (Vec<Rename>, Vec<SingleLineSpan>, Vec<SingleLineSpan>) {
+    let mut pairs = vec![];
+    substitutions(
+        lhs_roots,
+        Some(rhs_roots),
+        rhs_roots,
+        change_map,
+        &mut pairs,
+    );
+
+    let mut unchanged = FxHashSet::default();
+    unchanged_identifiers(lhs_roots, change_map, &mut unchanged);
+
+    let mut new_names: FxHashMap<(Scope, &str), FxHashSet<&str>> = FxHashMap::default();
+    let mut old_names: FxHashMap<(Scope, &str), FxHashSet<&str>> = FxHashMap::default();
+    for (lhs, rhs) in &pairs {
+        let scope = enclosing_scope(lhs);
+        let old_name = identifier_content(lhs).unwrap();
+        let new_name = identifier_content(rhs).unwrap();
+        new_names
+            .entry((scope, old_name))
+            .or_default()
+            .insert(new_name);
+        old_names
+            .entry((scope, new_name))
+            .or_default()
+            .insert(old_name);
+    }
+
+    // A rename is only consistent if it's one-to-one within its
+    // scope, and no occurrences of the old name are left.
+    let is_consistent = |scope: Scope, old_name: &str, new_name: &str| {
+        old_name != new_name
+            && !unchanged.contains(&(scope, old_name))
+            && new_names[&(scope, old_name)].len() == 1
+            && old_names[&(scope, new_name)].len() == 1
+    };
+
+    let mut occurrences: FxHashMap<(Scope, &str), usize> = FxHashMap::default();
+    for (lhs, rhs) in &pairs {
+        let scope = enclosing_scope(lhs);
+        let old_name = identifier_content(lhs).unwrap();
+        let new_name = identifier_content(rhs).unwrap();
+        if is_consistent(scope, old_name, new_name) {
+            *occurrences.entry((scope, old_name)).or_default() += 1;
+        }
+    }
+
+    let mut renames: Vec<Rename> = vec![];
+    let mut lhs_spans = vec![];
+    let mut rhs_spans = vec![];
+    for (lhs, rhs) in &pairs {
+        let scope = enclosing_scope(lhs);
+        let old_name = identifier_content(lhs).unwrap();
+        let new_name = identifier_content(rhs).unwrap();
+        let num_occurrences = occurrences.get(&(scope, old_name)).copied().unwrap_or(0);
+        if num_occurrences < MIN_OCCURRENCES {
+            continue;
+        }
+
+        // The same rename in several scopes is reported once.
+        match renames
+            .iter_mut()
+            .find(|r| r.old_name == old_name && r.new_name == new_name)
+        {
+            Some(rename) => rename.occurrences += 1,
+            None => renames.push(Rename {
+                old_name: old_name.to_owned(),
+                new_name: new_name.to_owned(),
+                occurrences: 1,
+            }),
+        }
+
+        if let (
+            Syntax::Atom {
+                position: lhs_position,
+                ..
+            },
+            Syntax::Atom {
+                position: rhs_position,
+                ..
+            },
+        ) = (lhs, rhs)
+        {
+            lhs_spans.extend(lhs_position.iter().copied());
+            rhs_spans.extend(rhs_position.iter().copied());
+        }
+    }
+
+    (renames, lhs_spans, rhs_spans)
+}
+
+/// Mark novel positions inside renamed atoms, so they're shown less
+/// prominently than other changes.
+pub fn mark_renamed(positions: &mut [MatchedPos], renamed_spans: &[SingleLineSpan]) {
+    let mut spans_by_line: FxHashMap<LineNumber, Vec<&SingleLineSpan>> = FxHashMap::default();
+    for span in renamed_spans {
+        spans_by_line.entry(span.line).or_default().push(span);
+    }
+
+    for mp in positions {
+        let highlight = match mp.kind {
+            MatchKind::Novel { highlight } | MatchKind::NovelWord { highlight } => highlight,
+            _ => continue,
+        };
+
+        let is_renamed = match spans_by_line.get(&mp.pos.line) {
+            Some(spans) => spans
+                .iter()
+                .any(|span| span.start_col <= mp.pos.start_col && mp.pos.end_col <= span.end_col),
+            None => false,
+        };
+        if is_renamed {
+            mp.kind = MatchKind::Renamed { highlight };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff::{dijkstra::mark_syntax, unchanged::mark_unchanged},
+        parse::syntax::{init_all_info, init_next_prev, AtomKind},
+    };
+    use pretty_assertions::assert_eq;
+    use typed_arena::Arena;
+
+    fn atom<'a>(arena: &'a Arena<Syntax<'a>>, content: &str, col: u32) -> &'a Syntax<'a> {
+        Syntax::new_atom(
+            arena,
+            vec![SingleLineSpan {
+                line: 0.into(),
+                start_col: col,
+                end_col: col + content.len() as u32,
+            }],
+            content,
+            AtomKind::Normal,
+        )
+    }
+
+    fn list<'a>(
+        arena: &'a Arena<Syntax<'a>>,
+        open_col: u32,
+        children: Vec<&'a Syntax<'a>>,
+        close_col: u32,
+    ) -> &'a Syntax<'a> {
+        let pos = |col: u32| {
+            vec![SingleLineSpan {
+                line: 0.into(),
+                start_col: col,
+                end_col: col + 1,
+            }]
+        };
+        Syntax::new_list(arena, "(", pos(open_col), children, ")", pos(close_col))
+    }
+
+    fn renames<'a>(lhs: &[&'a Syntax<'a>], rhs: &[&'a Syntax<'a>]) -> Vec<Rename> {
+        init_all_info(lhs, rhs);
+
+        let mut change_map = ChangeMap::default();
+        for (lhs_section, rhs_section) in mark_unchanged(lhs, rhs, &mut change_map) {
+            init_next_prev(&lhs_section);
+            init_next_prev(&rhs_section);
+            mark_syntax(
+                lhs_section.first().copied(),
+                rhs_section.first().copied(),
+                &mut change_map,
+                crate::options::DEFAULT_GRAPH_LIMIT,
+            )
+            .unwrap();
+        }
+
+        find_renames(lhs, rhs, &change_map).0
+    }
+
+    #[test]
+    fn test_find_renames() {
+        let arena = Arena::new();
+
+        // foo + foo * x
+        let lhs = vec![
+            atom(&arena, "foo", 0),
+            atom(&arena, "+", 4),
+            atom(&arena, "foo", 6),
+            atom(&arena, "*", 10),
+            atom(&arena, "x", 12),
+        ];
+        // bar + bar * x
+        let rhs = vec![
+            atom(&arena, "bar", 0),
+            atom(&arena, "+", 4),
+            atom(&arena, "bar", 6),
+            atom(&arena, "*", 10),
+            atom(&arena, "x", 12),
+        ];
+        init_all_info(&lhs, &rhs);
+
+        let mut change_map = ChangeMap::default();
+        for (lhs_section, rhs_section) in mark_unchanged(&lhs, &rhs, &mut change_map) {
+            init_next_prev(&lhs_section);
+            init_next_prev(&rhs_section);
+            mark_syntax(
+                lhs_section.first().copied(),
+                rhs_section.first().copied(),
+                &mut change_map,
+                crate::options::DEFAULT_GRAPH_LIMIT,
+            )
+            .unwrap();
+        }
+
+        let (renames, lhs_spans, rhs_spans) = find_renames(&lhs, &rhs, &change_map);
+        assert_eq!(
+            renames,
+            vec![Rename {
+                old_name: "foo".into(),
+                new_name: "bar".into(),
+                occurrences: 2,
+            }]
+        );
+        assert_eq!(lhs_spans.len(), 2);
+        assert_eq!(rhs_spans.len(), 2);
+    }
+
+    #[test]
+    fn test_find_renames_old_name_remains() {
+        let arena = Arena::new();
+
+        // foo + foo * foo
+        let lhs = vec![
+            atom(&arena, "foo", 0),
+            atom(&arena, "+", 4),
+            atom(&arena, "foo", 6),
+            atom(&arena, "*", 10),
+            atom(&arena, "foo", 12),
+        ];
+        // bar + bar * foo
+        let rhs = vec![
+            atom(&arena, "bar", 0),
+            atom(&arena, "+", 4),
+            atom(&arena, "bar", 6),
+            atom(&arena, "*", 10),
+            atom(&arena, "foo", 12),
+        ];
+
+        assert_eq!(renames(&lhs, &rhs), vec![]);
+    }
+
+    #[test]
+    fn test_find_renames_by_scope() {
+        let arena = Arena::new();
+
+        // (foo + foo) (foo + foo)
+        let lhs = vec![
+            list(
+                &arena,
+                0,
+                vec![
+                    atom(&arena, "foo", 1),
+                    atom(&arena, "+", 5),
+                    atom(&arena, "foo", 7),
+                ],
+                10,
+            ),
+            list(
+                &arena,
+                12,
+                vec![
+                    atom(&arena, "foo", 13),
+                    atom(&arena, "+", 17),
+                    atom(&arena, "foo", 19),
+                ],
+                22,
+            ),
+        ];
+        // (bar + bar) (baz + baz)
+        let rhs = vec![
+            list(
+                &arena,
+                0,
+                vec![
+                    atom(&arena, "bar", 1),
+                    atom(&arena, "+", 5),
+                    atom(&arena, "bar", 7),
+                ],
+                10,
+            ),
+            list(
+                &arena,
+                12,
+                vec![
+                    atom(&arena, "baz", 13),
+                    atom(&arena, "+", 17),
+                    atom(&arena, "baz", 19),
+                ],
+                22,
+            ),
+        ];
+
+        assert_eq!(
+            renames(&lhs, &rhs),
+            vec![
+                Rename {
+                    old_name: "foo".into(),
+                    new_name: "bar".into(),
+                    occurrences: 2,
+                },
+                Rename {
+                    old_name: "foo".into(),
+                    new_name: "baz".into(),
+                    occurrences: 2,
+                },
+            ]
+        );
+    }
+}
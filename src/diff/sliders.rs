@@ -59,7 +59,7 @@ fn prefer_outer_delimiter(language: guess_language::Language) -> bool {
         EmacsLisp | Clojure | CommonLisp | Janet => true,
         // JSON and TOML are like Lisp: the outer delimiter in an array object
         // is the most relevant.
-        Json | Toml | Hcl => true,
+        Json | Toml | Hcl | Ini => true,
         // It's probably the case that outer delimiters
         // (e.g. grouping) are used more frequently than inner
         // delimiters in SQL. `(foo = 1 OR bar = 2)` is more likely
@@ -134,7 +134,7 @@ fn fix_nested_slider_prefer_outer<'a>(node: &'a Syntax<'a>, change_map: &mut Cha
                     }
                 }
             }
-            ReplacedComment(_, _) => {}
+            ReplacedComment(_, _) | ReplacedAtom(_, _) => {}
             Novel => {}
         }
 
@@ -154,7 +154,7 @@ fn fix_nested_slider_prefer_inner<'a>(node: &'a Syntax<'a>, change_map: &mut Cha
             .expect("Changes should be set before slider correction")
         {
             Unchanged(_) => {}
-            ReplacedComment(_, _) => {}
+            ReplacedComment(_, _) | ReplacedAtom(_, _) => {}
             Novel => {
                 let mut found_unchanged = vec![];
                 unchanged_descendants(children, &mut found_unchanged, change_map);
@@ -188,7 +188,7 @@ fn unchanged_descendants<'a>(
             Unchanged(_) => {
                 found.push(node);
             }
-            Novel | ReplacedComment(_, _) => {
+            Novel | ReplacedComment(_, _) | ReplacedAtom(_, _) => {
                 if let List { children, .. } = node {
                     unchanged_descendants(children, found, change_map);
                 }
@@ -347,7 +347,7 @@ fn novel_regions_after_unchanged<'a>(
                     region = Some(r);
                 }
             }
-            ReplacedComment(_, _) => {
+            ReplacedComment(_, _) | ReplacedAtom(_, _) => {
                 // Could have just finished a novel region.
                 if let Some(region) = region {
                     regions.push(region);
@@ -395,7 +395,7 @@ fn novel_regions_before_unchanged<'a>(
                 r.push(i);
                 region = Some(r);
             }
-            ReplacedComment(_, _) => {
+            ReplacedComment(_, _) | ReplacedAtom(_, _) => {
                 region = None;
             }
         }
@@ -749,7 +749,7 @@ mod tests {
     #[test]
     fn test_slider_two_steps() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs = parse(&arena, "A B", &config, false);
         let rhs = parse(&arena, "A B X\n A B", &config, false);
@@ -774,7 +774,7 @@ mod tests {
     #[test]
     fn test_slider_partially_unchanged() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs = parse(&arena, "(A B) X \n (A B)", &config, false);
         let rhs = parse(&arena, "((novel) A B)", &config, false);
@@ -0,0 +1,301 @@
+//! Describing changes to CSV and TSV files by row and column.
+//!
+//! The first row of a table is treated as a header. Changed cells are
+//! reported by the name of their column, so `price` changing from 10
+//! to 12 is described as "Row 42, column `price`: 10 → 12." rather
+//! than a changed line.
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    diff::changes::{ChangeKind, ChangeMap},
+    parse::{csv::Row, syntax::Syntax},
+};
+
+/// If there are more changed cells than this, only the first ones
+/// are described.
+const MAX_CELL_CHANGES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableChange {
+    ColumnsAdded(Vec<String>),
+    ColumnsRemoved(Vec<String>),
+    /// The columns present on both sides, in their old and new order.
+    ColumnsReordered(Vec<String>, Vec<String>),
+    Cell {
+        /// The 1-indexed row on the RHS, counting the header as row
+        /// 1.
+        row: usize,
+        column: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl TableChange {
+    pub fn describe(&self) -> String {
+        match self {
+            TableChange::ColumnsAdded(names) => {
+                format!("Added {}: {}.", pluralize("column", names), quoted(names))
+            }
+            TableChange::ColumnsRemoved(names) => {
+                format!("Removed {}: {}.", pluralize("column", names), quoted(names))
+            }
+            TableChange::ColumnsReordered(old, new) => {
+                format!("Columns reordered: {} → {}.", quoted(old), quoted(new))
+            }
+            TableChange::Cell {
+                row,
+                column,
+                old,
+                new,
+            } => format!(
+                "Row {}, column `{}`: {} → {}.",
+                row,
+                column,
+                cell_value(old),
+                cell_value(new)
+            ),
+        }
+    }
+}
+
+fn pluralize(word: &str, items: &[String]) -> String {
+    if items.len() == 1 {
+        word.to_owned()
+    } else {
+        format!("{}s", word)
+    }
+}
+
+fn quoted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn cell_value(value: &str) -> String {
+    if value.is_empty() {
+        "(empty)".to_owned()
+    } else {
+        value.replace('\n', "\\n")
+    }
+}
+
+/// Describe table changes as sentences, omitting cell changes after
+/// the first `MAX_CELL_CHANGES`.
+pub fn describe_table_changes(changes: &[TableChange]) -> Vec<String> {
+    let mut res = vec![];
+    let mut cell_changes = 0;
+    for change in changes {
+        if matches!(change, TableChange::Cell { .. }) {
+            cell_changes += 1;
+            if cell_changes > MAX_CELL_CHANGES {
+                continue;
+            }
+        }
+        res.push(change.describe());
+    }
+
+    if cell_changes > MAX_CELL_CHANGES {
+        res.push(format!(
+            "...and {} more cell changes.",
+            cell_changes - MAX_CELL_CHANGES
+        ));
+    }
+    res
+}
+
+fn column_names(rows: &[Row]) -> Vec<String> {
+    match rows.first() {
+        Some(header) => header.cells.iter().map(|cell| cell.value.clone()).collect(),
+        None => vec![],
+    }
+}
+
+/// Compare the columns in the headers of two tables.
+fn column_changes(lhs_columns: &[String], rhs_columns: &[String]) -> Vec<TableChange> {
+    let mut res = vec![];
+
+    let added: Vec<String> = rhs_columns
+        .iter()
+        .filter(|name| !lhs_columns.contains(name))
+        .cloned()
+        .collect();
+    if !added.is_empty() {
+        res.push(TableChange::ColumnsAdded(added));
+    }
+
+    let removed: Vec<String> = lhs_columns
+        .iter()
+        .filter(|name| !rhs_columns.contains(name))
+        .cloned()
+        .collect();
+    if !removed.is_empty() {
+        res.push(TableChange::ColumnsRemoved(removed));
+    }
+
+    let lhs_common: Vec<String> = lhs_columns
+        .iter()
+        .filter(|name| rhs_columns.contains(name))
+        .cloned()
+        .collect();
+    let rhs_common: Vec<String> = rhs_columns
+        .iter()
+        .filter(|name| lhs_columns.contains(name))
+        .cloned()
+        .collect();
+    if lhs_common != rhs_common {
+        res.push(TableChange::ColumnsReordered(lhs_common, rhs_common));
+    }
+
+    res
+}
+
+/// Find the changes between two tables. `lhs_nodes` and `rhs_nodes`
+/// are the syntax nodes for `lhs_rows` and `rhs_rows`, and rows are
+/// paired up using the unchanged rows in `change_map`.
+pub fn find_table_changes<'a>(
+    lhs_rows: &[Row],
+    rhs_rows: &[Row],
+    lhs_nodes: &[&'a Syntax<'a>],
+    rhs_nodes: &[&'a Syntax<'a>],
+    change_map: &ChangeMap<'a>,
+) -> Vec<TableChange> {
+    let lhs_columns = column_names(lhs_rows);
+    let rhs_columns = column_names(rhs_rows);
+    let mut res = column_changes(&lhs_columns, &rhs_columns);
+
+    let rhs_row_indexes: FxHashMap<_, usize> = rhs_nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id(), i))
+        .collect();
+
+    // Cells are compared by column name, so cells that have moved
+    // with a reordered column aren't changes.
+    let mut common_columns = vec![];
+    for (rhs_i, name) in rhs_columns.iter().enumerate() {
+        if rhs_columns[..rhs_i].contains(name) {
+            continue;
+        }
+        if let Some(lhs_i) = lhs_columns.iter().position(|n| n == name) {
+            common_columns.push((name, lhs_i, rhs_i));
+        }
+    }
+
+    let mut cell_changes = vec![];
+    for (lhs_row, lhs_node) in lhs_rows.iter().zip(lhs_nodes).skip(1) {
+        let rhs_i = match change_map.get(lhs_node) {
+            Some(ChangeKind::Unchanged(opposite)) => match rhs_row_indexes.get(&opposite.id()) {
+                Some(&rhs_i) if rhs_i > 0 => rhs_i,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let rhs_row = &rhs_rows[rhs_i];
+
+        for (name, lhs_column, rhs_column) in &common_columns {
+            let old = lhs_row
+                .cells
+                .get(*lhs_column)
+                .map_or("", |cell| cell.value.as_str());
+            let new = rhs_row
+                .cells
+                .get(*rhs_column)
+                .map_or("", |cell| cell.value.as_str());
+            if old != new {
+                cell_changes.push(TableChange::Cell {
+                    row: rhs_i + 1,
+                    column: (*name).clone(),
+                    old: old.to_owned(),
+                    new: new.to_owned(),
+                });
+            }
+        }
+    }
+
+    // Report changes in the order they appear in the new file.
+    cell_changes.sort_by_key(|change| match change {
+        TableChange::Cell { row, .. } => *row,
+        _ => 0,
+    });
+    res.extend(cell_changes);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::csv::{parse_rows, to_syntax};
+    use crate::parse::syntax::init_all_info;
+    use pretty_assertions::assert_eq;
+    use typed_arena::Arena;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| (*name).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_column_changes() {
+        assert_eq!(
+            column_changes(
+                &names(&["id", "name", "price"]),
+                &names(&["price", "id", "sku"])
+            ),
+            vec![
+                TableChange::ColumnsAdded(names(&["sku"])),
+                TableChange::ColumnsRemoved(names(&["name"])),
+                TableChange::ColumnsReordered(names(&["id", "price"]), names(&["price", "id"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_cell_change() {
+        let change = TableChange::Cell {
+            row: 42,
+            column: "price".into(),
+            old: "10".into(),
+            new: "12".into(),
+        };
+        assert_eq!(change.describe(), "Row 42, column `price`: 10 → 12.");
+    }
+
+    #[test]
+    fn test_find_table_changes_reordered_columns() {
+        let lhs_src = "id,name,price\n1,apple,10\n2,pear,5";
+        let rhs_src = "id,price,name\n1,12,apple\n2,5,pear";
+        let lhs_rows = parse_rows(lhs_src, ',');
+        let rhs_rows = parse_rows(rhs_src, ',');
+
+        let arena = Arena::new();
+        let lhs = to_syntax(&arena, lhs_src, &lhs_rows);
+        let rhs = to_syntax(&arena, rhs_src, &rhs_rows);
+        init_all_info(&lhs, &rhs);
+
+        // Pair up the rows in order, as the tree diff would.
+        let mut change_map = ChangeMap::default();
+        for (lhs_node, rhs_node) in lhs.iter().zip(&rhs) {
+            change_map.insert(lhs_node, ChangeKind::Unchanged(rhs_node));
+        }
+
+        assert_eq!(
+            find_table_changes(&lhs_rows, &rhs_rows, &lhs, &rhs, &change_map),
+            vec![
+                TableChange::ColumnsReordered(
+                    names(&["id", "name", "price"]),
+                    names(&["id", "price", "name"])
+                ),
+                TableChange::Cell {
+                    row: 2,
+                    column: "price".into(),
+                    old: "10".into(),
+                    new: "12".into(),
+                },
+            ]
+        );
+    }
+}
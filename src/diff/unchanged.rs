@@ -453,7 +453,7 @@ mod tests {
     #[test]
     fn test_shrink_unchanged_at_start() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(&arena, "unchanged A B", &config, false);
         let rhs_nodes = parse(&arena, "unchanged X", &config, false);
@@ -479,7 +479,7 @@ mod tests {
     #[test]
     fn test_shrink_unchanged_at_end() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(&arena, "A B unchanged", &config, false);
         let rhs_nodes = parse(&arena, "X unchanged", &config, false);
@@ -505,7 +505,7 @@ mod tests {
     #[test]
     fn test_shrink_unchanged_nested() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -540,7 +540,7 @@ mod tests {
     #[test]
     fn test_split_unchanged_toplevel_at_start() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         // Make sure that the initial unchanged node exceeds TINY_TREE_THRESHOLD.
         let lhs_nodes = parse(
@@ -579,7 +579,7 @@ mod tests {
     #[test]
     fn test_split_unchanged_toplevel_at_end() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -617,7 +617,7 @@ mod tests {
     #[test]
     fn test_split_preserves_outer_delimiters() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(&arena, "(A)", &config, false);
         let rhs_nodes = parse(&arena, "(B)", &config, false);
@@ -644,7 +644,7 @@ mod tests {
     #[test]
     fn test_split_unchanged_middle() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -683,7 +683,7 @@ mod tests {
     #[test]
     fn test_split_unchanged_multiple() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -711,7 +711,7 @@ mod tests {
     #[test]
     fn test_split_unchanged_outer_delimiter() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -740,7 +740,7 @@ mod tests {
     #[test]
     fn test_split_mostly_unchanged_toplevel() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(
             &arena,
@@ -763,7 +763,7 @@ mod tests {
     #[test]
     fn test_count_common_unique() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         // There are two subtrees that are unique on both sides and
         // shared between the two sides here:
@@ -790,7 +790,7 @@ mod tests {
     #[test]
     fn test_similar_with_common_grandchildren() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(&arena, "((novel-lhs 1 2 3 4 5)) x", &config, false);
         let rhs_nodes = parse(&arena, "((novel-rhs 1 2 3 4 5)) y", &config, false);
@@ -804,7 +804,7 @@ mod tests {
     #[test]
     fn test_similar_ignore_delimiter() {
         let arena = Arena::new();
-        let config = from_language(guess_language::Language::EmacsLisp);
+        let config = from_language(guess_language::Language::EmacsLisp).unwrap();
 
         let lhs_nodes = parse(&arena, "(novel-lhs 1 2 3 4 5) x", &config, false);
         let rhs_nodes = parse(&arena, "[novel-rhs 1 2 3 4 5] y", &config, false);
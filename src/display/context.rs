@@ -152,9 +152,10 @@ fn matched_lines_from_unchanged(
                     opposite_pos.first().map(|p| p.line)
                 }
             }
-            MatchKind::Novel { .. } | MatchKind::NovelWord { .. } | MatchKind::Ignored { .. } => {
-                None
-            }
+            MatchKind::Novel { .. }
+            | MatchKind::NovelWord { .. }
+            | MatchKind::Ignored { .. }
+            | MatchKind::Renamed { .. } => None,
         };
 
         let should_insert = match highest_line {
@@ -351,7 +352,10 @@ pub fn opposite_positions(mps: &[MatchedPos]) -> FxHashMap<LineNumber, HashSet<L
                     opposite_lines.insert(opposite_span.line);
                 }
             }
-            MatchKind::Novel { .. } | MatchKind::NovelWord { .. } | MatchKind::Ignored { .. } => {}
+            MatchKind::Novel { .. }
+            | MatchKind::NovelWord { .. }
+            | MatchKind::Ignored { .. }
+            | MatchKind::Renamed { .. } => {}
         }
     }
 
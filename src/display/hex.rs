@@ -0,0 +1,261 @@
+//! Hex dump display for binary files.
+//!
+//! Each row shows an offset, 16 bytes in hex and the same bytes as
+//! ASCII. Changed rows are shown as removed LHS rows followed by added
+//! RHS rows, like the inline display, with the changed bytes
+//! highlighted.
+
+use std::{cmp::min, ops::Range};
+
+use owo_colors::{OwoColorize, Style};
+
+use crate::{
+    constants::Side,
+    diff::byte_diff::ByteChange,
+    display::style::{self, apply_line_number_color, novel_style},
+    options::DisplayOptions,
+};
+
+const BYTES_PER_ROW: usize = 16;
+
+/// The rows to show for a group of nearby changes.
+#[derive(Debug, PartialEq, Eq)]
+struct RowHunk {
+    lhs_rows: Range<usize>,
+    rhs_rows: Range<usize>,
+    changes: Vec<ByteChange>,
+}
+
+fn num_rows(bytes: &[u8]) -> usize {
+    (bytes.len() + BYTES_PER_ROW - 1) / BYTES_PER_ROW
+}
+
+/// The rows containing `bytes`. If `bytes` is empty, this is the row
+/// where content was inserted or deleted, so the other side can be
+/// compared with it.
+fn rows_for_bytes(bytes: &Range<usize>, num_rows: usize) -> Range<usize> {
+    let start = bytes.start / BYTES_PER_ROW;
+    let end = if bytes.is_empty() {
+        start + 1
+    } else {
+        (bytes.end + BYTES_PER_ROW - 1) / BYTES_PER_ROW
+    };
+    min(start, num_rows)..min(end, num_rows)
+}
+
+/// Group `changes` into hunks, merging changes whose rows are close
+/// enough that their context would overlap.
+fn row_hunks(
+    changes: &[ByteChange],
+    lhs_num_rows: usize,
+    rhs_num_rows: usize,
+    num_context_rows: usize,
+) -> Vec<RowHunk> {
+    let mut res: Vec<RowHunk> = vec![];
+    for change in changes {
+        let lhs_rows = rows_for_bytes(&change.lhs, lhs_num_rows);
+        let rhs_rows = rows_for_bytes(&change.rhs, rhs_num_rows);
+
+        if let Some(hunk) = res.last_mut() {
+            if lhs_rows.start <= hunk.lhs_rows.end + 2 * num_context_rows
+                || rhs_rows.start <= hunk.rhs_rows.end + 2 * num_context_rows
+            {
+                hunk.lhs_rows.end = lhs_rows.end.max(hunk.lhs_rows.end);
+                hunk.rhs_rows.end = rhs_rows.end.max(hunk.rhs_rows.end);
+                hunk.changes.push(change.clone());
+                continue;
+            }
+        }
+
+        res.push(RowHunk {
+            lhs_rows,
+            rhs_rows,
+            changes: vec![change.clone()],
+        });
+    }
+    res
+}
+
+/// Format row `row` of `bytes`, highlighting any bytes on this side
+/// of `changes`. Rows with no `changes` are context.
+fn format_row(
+    bytes: &[u8],
+    row: usize,
+    changes: &[ByteChange],
+    side: Side,
+    display_options: &DisplayOptions,
+) -> String {
+    let is_novel = |offset: &usize| {
+        changes.iter().any(|change| match side {
+            Side::Left => change.lhs.contains(offset),
+            Side::Right => change.rhs.contains(offset),
+        })
+    };
+    let highlight = |s: String, offset: usize| {
+        if display_options.use_color && is_novel(&offset) {
+            s.style(novel_style(Style::new(), side, &display_options.theme).bold())
+                .to_string()
+        } else {
+            s
+        }
+    };
+
+    let start = row * BYTES_PER_ROW;
+    let end = min(start + BYTES_PER_ROW, bytes.len());
+
+    let mut hex = String::new();
+    let mut ascii = String::new();
+    for i in 0..BYTES_PER_ROW {
+        // Add an extra space between the two halves of the row.
+        if i == BYTES_PER_ROW / 2 {
+            hex.push(' ');
+        }
+
+        let offset = start + i;
+        match bytes.get(offset) {
+            Some(byte) if offset < end => {
+                hex.push_str(&highlight(format!("{:02x}", byte), offset));
+                let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                };
+                ascii.push_str(&highlight(c.to_string(), offset));
+            }
+            _ => hex.push_str("  "),
+        }
+        hex.push(' ');
+    }
+
+    format!(
+        "{} {} |{}|\n",
+        apply_line_number_color(
+            &format!("{:08x} ", start),
+            !changes.is_empty(),
+            side,
+            display_options
+        ),
+        hex,
+        ascii
+    )
+}
+
+/// Display the changed bytes between `lhs` and `rhs` as a hex dump,
+/// returning the output lines for each hunk. Every line includes its
+/// trailing newline.
+pub fn lines_by_hunk(
+    lhs: &[u8],
+    rhs: &[u8],
+    changes: &[ByteChange],
+    display_options: &DisplayOptions,
+    lhs_display_path: &str,
+    rhs_display_path: &str,
+) -> Vec<Vec<String>> {
+    let num_context_rows = display_options.num_context_lines as usize;
+    let hunks = row_hunks(changes, num_rows(lhs), num_rows(rhs), num_context_rows);
+
+    let mut res = Vec::with_capacity(hunks.len());
+    for (i, hunk) in hunks.iter().enumerate() {
+        let mut lines = vec![format!(
+            "{}\n",
+            style::header(
+                lhs_display_path,
+                rhs_display_path,
+                i + 1,
+                hunks.len(),
+                "binary",
+                display_options
+            )
+        )];
+
+        let before_rows = hunk.lhs_rows.start.saturating_sub(num_context_rows)..hunk.lhs_rows.start;
+        for row in before_rows {
+            lines.push(format_row(lhs, row, &[], Side::Left, display_options));
+        }
+        for row in hunk.lhs_rows.clone() {
+            lines.push(format_row(
+                lhs,
+                row,
+                &hunk.changes,
+                Side::Left,
+                display_options,
+            ));
+        }
+        for row in hunk.rhs_rows.clone() {
+            lines.push(format_row(
+                rhs,
+                row,
+                &hunk.changes,
+                Side::Right,
+                display_options,
+            ));
+        }
+        let after_rows =
+            hunk.rhs_rows.end..min(hunk.rhs_rows.end + num_context_rows, num_rows(rhs));
+        for row in after_rows {
+            lines.push(format_row(rhs, row, &[], Side::Right, display_options));
+        }
+
+        lines.push("\n".into());
+        res.push(lines);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_row() {
+        let display_options = DisplayOptions {
+            use_color: false,
+            ..DisplayOptions::default()
+        };
+        let bytes = b"0123456789abcdefHi\x00";
+        assert_eq!(
+            format_row(
+                bytes,
+                1,
+                &[ByteChange {
+                    lhs: 0..0,
+                    rhs: 17..18
+                }],
+                Side::Right,
+                &display_options
+            ),
+            "00000010+ 48 69 00                                          |Hi.|\n"
+        );
+    }
+
+    #[test]
+    fn test_row_hunks() {
+        let changes = vec![
+            ByteChange {
+                lhs: 0x10..0x12,
+                rhs: 0x10..0x10,
+            },
+            ByteChange {
+                lhs: 0x200..0x200,
+                rhs: 0x1fe..0x1ff,
+            },
+        ];
+        assert_eq!(
+            row_hunks(&changes, 0x30, 0x30, 3),
+            vec![
+                RowHunk {
+                    lhs_rows: 1..2,
+                    rhs_rows: 1..2,
+                    changes: vec![changes[0].clone()],
+                },
+                RowHunk {
+                    lhs_rows: 0x20..0x21,
+                    rhs_rows: 0x1f..0x20,
+                    changes: vec![changes[1].clone()],
+                },
+            ]
+        );
+    }
+}
@@ -4,13 +4,15 @@ use crate::{
     constants::Side,
     display::context::{calculate_after_context, calculate_before_context, opposite_positions},
     display::hunks::Hunk,
-    display::style::{self, apply_colors, apply_line_number_color},
-    lines::{format_line_num, split_on_newlines, MaxLine},
+    display::style::{self, apply_colors, apply_line_number_color, apply_markers},
+    lines::{format_line_num, MaxLine},
     options::DisplayOptions,
     parse::{guess_language::Language, syntax::MatchedPos},
 };
 
-pub fn print(
+/// Display `hunks` as a unified diff, returning the output lines for
+/// each hunk. Every line includes its trailing newline.
+pub fn lines_by_hunk(
     lhs_src: &str,
     rhs_src: &str,
     display_options: &DisplayOptions,
@@ -21,7 +23,7 @@ pub fn print(
     rhs_display_path: &str,
     display_language: &str,
     language: Option<Language>,
-) {
+) -> Vec<Vec<String>> {
     let (lhs_colored_lines, rhs_colored_lines) = if display_options.use_color {
         (
             apply_colors(
@@ -29,7 +31,7 @@ pub fn print(
                 Side::Left,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 lhs_positions,
             ),
             apply_colors(
@@ -37,20 +39,14 @@ pub fn print(
                 Side::Right,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 rhs_positions,
             ),
         )
     } else {
         (
-            split_on_newlines(lhs_src)
-                .iter()
-                .map(|s| format!("{}\n", s))
-                .collect(),
-            split_on_newlines(rhs_src)
-                .iter()
-                .map(|s| format!("{}\n", s))
-                .collect(),
+            apply_markers(lhs_src, Side::Left, lhs_positions),
+            apply_markers(rhs_src, Side::Right, rhs_positions),
         )
     };
 
@@ -66,9 +62,10 @@ pub fn print(
     let opposite_to_lhs = opposite_positions(lhs_positions);
     let opposite_to_rhs = opposite_positions(rhs_positions);
 
+    let mut res = Vec::with_capacity(hunks.len());
     for (i, hunk) in hunks.iter().enumerate() {
-        println!(
-            "{}",
+        let mut lines = vec![format!(
+            "{}\n",
             style::header(
                 lhs_display_path,
                 rhs_display_path,
@@ -77,7 +74,7 @@ pub fn print(
                 display_language,
                 display_options
             )
-        );
+        )];
 
         let hunk_lines = hunk.lines.clone();
 
@@ -95,7 +92,7 @@ pub fn print(
 
         for (lhs_line, _) in before_lines {
             if let Some(lhs_line) = lhs_line {
-                print!(
+                lines.push(format!(
                     "{}   {}",
                     apply_line_number_color(
                         &format_line_num(lhs_line),
@@ -104,13 +101,13 @@ pub fn print(
                         display_options,
                     ),
                     lhs_colored_lines[lhs_line.as_usize()]
-                );
+                ));
             }
         }
 
         for (lhs_line, _) in &hunk_lines {
             if let Some(lhs_line) = lhs_line {
-                print!(
+                lines.push(format!(
                     "{}   {}",
                     apply_line_number_color(
                         &format_line_num(*lhs_line),
@@ -119,12 +116,12 @@ pub fn print(
                         display_options,
                     ),
                     lhs_colored_lines[lhs_line.as_usize()]
-                );
+                ));
             }
         }
         for (_, rhs_line) in &hunk_lines {
             if let Some(rhs_line) = rhs_line {
-                print!(
+                lines.push(format!(
                     "   {}{}",
                     apply_line_number_color(
                         &format_line_num(*rhs_line),
@@ -133,13 +130,13 @@ pub fn print(
                         display_options,
                     ),
                     rhs_colored_lines[rhs_line.as_usize()]
-                );
+                ));
             }
         }
 
         for (_, rhs_line) in &after_lines {
             if let Some(rhs_line) = rhs_line {
-                print!(
+                lines.push(format!(
                     "   {}{}",
                     apply_line_number_color(
                         &format_line_num(*rhs_line),
@@ -148,9 +145,12 @@ pub fn print(
                         display_options,
                     ),
                     rhs_colored_lines[rhs_line.as_usize()]
-                );
+                ));
             }
         }
-        println!();
+        lines.push("\n".into());
+        res.push(lines);
     }
+
+    res
 }
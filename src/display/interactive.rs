@@ -0,0 +1,466 @@
+//! A full-screen terminal viewer for browsing diffs, with keys to
+//! move between hunks and files.
+
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use crate::{
+    options::{DisplayMode, DisplayOptions},
+    summary::DiffResult,
+};
+
+/// How many extra lines of context to show when the user expands
+/// context.
+const EXTRA_CONTEXT_LINES: u32 = 10;
+
+const HELP: &str = "n/p hunk  N/P file  s layout  c context  / search  q quit";
+
+/// The rendered output of every file, split into terminal lines.
+#[derive(Debug, Default)]
+struct Document {
+    lines: Vec<String>,
+    /// The index in `lines` where each hunk starts.
+    hunk_starts: Vec<usize>,
+    /// The index in `lines` where each file starts.
+    file_starts: Vec<usize>,
+    /// The name shown for each file, in the same order as `file_starts`.
+    file_names: Vec<String>,
+}
+
+impl Document {
+    fn new(diff_results: &[DiffResult], display_options: &DisplayOptions) -> Self {
+        let mut document = Document::default();
+
+        for diff_result in diff_results {
+            let hunks = crate::render_diff_result(display_options, diff_result);
+            if hunks.is_empty() {
+                continue;
+            }
+
+            document.file_starts.push(document.lines.len());
+            document
+                .file_names
+                .push(diff_result.rhs_display_path.clone());
+
+            for hunk_lines in hunks {
+                document.hunk_starts.push(document.lines.len());
+                for hunk_line in hunk_lines {
+                    // Headers for renamed files span two lines.
+                    for line in hunk_line.lines() {
+                        document.lines.push(line.to_owned());
+                    }
+                }
+            }
+        }
+
+        document
+    }
+}
+
+/// The first item in `starts` after `current`.
+fn next_start(starts: &[usize], current: usize) -> Option<usize> {
+    starts.iter().copied().find(|start| *start > current)
+}
+
+/// The last item in `starts` before `current`.
+fn prev_start(starts: &[usize], current: usize) -> Option<usize> {
+    starts.iter().rev().copied().find(|start| *start < current)
+}
+
+/// The one-indexed position of the item in `starts` that contains
+/// line `current`, or 0 if `current` is before every item.
+fn containing_index(starts: &[usize], current: usize) -> usize {
+    starts.iter().filter(|start| **start <= current).count()
+}
+
+/// Remove ANSI escape sequences from `s`, so we can search the text
+/// that the user sees.
+fn strip_ansi(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Skip parameters until the final byte of the sequence.
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            res.push(c);
+        }
+    }
+
+    res
+}
+
+/// Find the next line after `current` (or before, if `forward` is
+/// false) that contains `query`.
+fn find_line(lines: &[String], query: &str, current: usize, forward: bool) -> Option<usize> {
+    let matches = |i: &usize| strip_ansi(&lines[*i]).contains(query);
+
+    if forward {
+        (current + 1..lines.len()).find(matches)
+    } else {
+        (0..current).rev().find(matches)
+    }
+}
+
+struct Viewer<'a> {
+    diff_results: &'a [DiffResult],
+    display_options: DisplayOptions,
+    /// The number of context lines the user asked for on the command
+    /// line.
+    default_context_lines: u32,
+    /// The display mode the user asked for on the command line.
+    default_display_mode: DisplayMode,
+    document: Document,
+    /// The index of the first line shown on screen.
+    top: usize,
+    /// The number of rows available for showing the diff.
+    height: usize,
+    width: usize,
+    /// The most recent search.
+    search: Option<String>,
+    search_forward: bool,
+    /// The search that the user is currently typing.
+    prompt: Option<String>,
+    message: Option<String>,
+}
+
+impl<'a> Viewer<'a> {
+    fn new(
+        diff_results: &'a [DiffResult],
+        display_options: &DisplayOptions,
+        columns: u16,
+        rows: u16,
+    ) -> Self {
+        let mut display_options = display_options.clone();
+        display_options.display_width = columns as usize;
+
+        let document = Document::new(diff_results, &display_options);
+        Self {
+            diff_results,
+            default_context_lines: display_options.num_context_lines,
+            default_display_mode: display_options.display_mode,
+            display_options,
+            document,
+            top: 0,
+            // Reserve the last row for the status line.
+            height: (rows as usize).saturating_sub(1),
+            width: columns as usize,
+            search: None,
+            search_forward: true,
+            prompt: None,
+            message: None,
+        }
+    }
+
+    /// Render the diff results again, after the display options have
+    /// changed, staying on the current hunk.
+    fn rerender(&mut self) {
+        let hunk_i = containing_index(&self.document.hunk_starts, self.top);
+        self.document = Document::new(self.diff_results, &self.display_options);
+
+        let top = match hunk_i.checked_sub(1) {
+            Some(i) => self.document.hunk_starts.get(i).copied().unwrap_or(0),
+            None => 0,
+        };
+        self.scroll_to(top);
+    }
+
+    fn resize(&mut self, columns: u16, rows: u16) {
+        self.width = columns as usize;
+        self.height = (rows as usize).saturating_sub(1);
+        self.display_options.display_width = columns as usize;
+        self.rerender();
+    }
+
+    fn max_top(&self) -> usize {
+        self.document.lines.len().saturating_sub(self.height)
+    }
+
+    fn scroll_to(&mut self, line: usize) {
+        self.top = std::cmp::min(line, self.max_top());
+    }
+
+    fn scroll_by(&mut self, lines: isize) {
+        let top = self.top as isize + lines;
+        self.scroll_to(std::cmp::max(top, 0) as usize);
+    }
+
+    fn find(&mut self, forward: bool) {
+        let query = match &self.search {
+            Some(query) => query.clone(),
+            None => return,
+        };
+
+        match find_line(&self.document.lines, &query, self.top, forward) {
+            Some(line) => self.scroll_to(line),
+            None => self.message = Some(format!("Pattern not found: {}", query)),
+        }
+    }
+
+    /// Update the viewer for this key press. Returns false if the
+    /// user wants to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(prompt) = &mut self.prompt {
+            match key.code {
+                KeyCode::Enter => {
+                    // An empty search repeats the previous one, like less.
+                    if !prompt.is_empty() {
+                        self.search = Some(prompt.clone());
+                    }
+                    self.prompt = None;
+                    self.find(self.search_forward);
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Char(c) => prompt.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        self.message = None;
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return !matches!(key.code, KeyCode::Char('c'));
+        }
+
+        let page = std::cmp::max(self.height, 1) as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => self.scroll_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.scroll_by(-1),
+            KeyCode::Char(' ') | KeyCode::PageDown => self.scroll_by(page),
+            KeyCode::Char('b') | KeyCode::PageUp => self.scroll_by(-page),
+            KeyCode::Char('g') | KeyCode::Home => self.scroll_to(0),
+            KeyCode::Char('G') | KeyCode::End => self.scroll_to(self.max_top()),
+            KeyCode::Char('n') => {
+                if let Some(line) = next_start(&self.document.hunk_starts, self.top) {
+                    self.scroll_to(line);
+                }
+            }
+            KeyCode::Char('p') => {
+                if let Some(line) = prev_start(&self.document.hunk_starts, self.top) {
+                    self.scroll_to(line);
+                }
+            }
+            KeyCode::Char('N') => {
+                if let Some(line) = next_start(&self.document.file_starts, self.top) {
+                    self.scroll_to(line);
+                }
+            }
+            KeyCode::Char('P') => {
+                if let Some(line) = prev_start(&self.document.file_starts, self.top) {
+                    self.scroll_to(line);
+                }
+            }
+            KeyCode::Char('s') => {
+                self.display_options.display_mode = match self.display_options.display_mode {
+                    DisplayMode::Inline | DisplayMode::WordDiff => {
+                        match self.default_display_mode {
+                            DisplayMode::Inline | DisplayMode::WordDiff => DisplayMode::SideBySide,
+                            mode => mode,
+                        }
+                    }
+                    DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth => {
+                        match self.default_display_mode {
+                            DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth => {
+                                DisplayMode::Inline
+                            }
+                            mode => mode,
+                        }
+                    }
+                };
+                self.rerender();
+            }
+            KeyCode::Char('c') => {
+                self.display_options.num_context_lines =
+                    if self.display_options.num_context_lines == self.default_context_lines {
+                        self.default_context_lines + EXTRA_CONTEXT_LINES
+                    } else {
+                        self.default_context_lines
+                    };
+                self.rerender();
+            }
+            KeyCode::Char('/') => {
+                self.search_forward = true;
+                self.prompt = Some(String::new());
+            }
+            KeyCode::Char('?') => {
+                self.search_forward = false;
+                self.prompt = Some(String::new());
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn status_line(&self) -> String {
+        if let Some(prompt) = &self.prompt {
+            return format!("{}{}", if self.search_forward { "/" } else { "?" }, prompt);
+        }
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+
+        let file_i = containing_index(&self.document.file_starts, self.top);
+        let file_name = match file_i.checked_sub(1) {
+            Some(i) => self.document.file_names[i].as_str(),
+            None => "",
+        };
+        let layout = match self.display_options.display_mode {
+            DisplayMode::Inline => "inline",
+            DisplayMode::WordDiff => "word-diff",
+            DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth => "side-by-side",
+        };
+
+        format!(
+            "{} (file {}/{}, hunk {}/{}, {}, context {})  {}",
+            file_name,
+            file_i,
+            self.document.file_starts.len(),
+            containing_index(&self.document.hunk_starts, self.top),
+            self.document.hunk_starts.len(),
+            layout,
+            self.display_options.num_context_lines,
+            HELP
+        )
+    }
+
+    fn draw(&self, stdout: &mut impl Write) -> io::Result<()> {
+        for row in 0..self.height {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            if let Some(line) = self.document.lines.get(self.top + row) {
+                queue!(stdout, Print(line), SetAttribute(Attribute::Reset))?;
+            }
+        }
+
+        let status: String = self.status_line().chars().take(self.width).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, self.height as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            SetAttribute(Attribute::Reverse),
+            Print(status),
+            SetAttribute(Attribute::Reset)
+        )?;
+
+        stdout.flush()
+    }
+}
+
+/// Restores the terminal when the viewer exits, even if it panics.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(
+            io::stdout(),
+            terminal::EnableLineWrap,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Show `diff_results` in a full-screen viewer until the user quits.
+pub fn run(diff_results: &[DiffResult], display_options: &DisplayOptions) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let mut viewer = Viewer::new(diff_results, display_options, columns, rows);
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    // Long lines are truncated rather than wrapped, so every line of
+    // the document occupies exactly one row.
+    execute!(
+        stdout,
+        terminal::EnterAlternateScreen,
+        cursor::Hide,
+        terminal::DisableLineWrap
+    )?;
+
+    loop {
+        viewer.draw(&mut stdout)?;
+
+        let keep_running = match event::read()? {
+            Event::Key(key) => viewer.handle_key(key),
+            Event::Resize(columns, rows) => {
+                viewer.resize(columns, rows);
+                true
+            }
+            _ => true,
+        };
+        if !keep_running {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_and_prev_start() {
+        let starts = [0, 10, 25];
+
+        assert_eq!(next_start(&starts, 0), Some(10));
+        assert_eq!(next_start(&starts, 12), Some(25));
+        assert_eq!(next_start(&starts, 25), None);
+
+        assert_eq!(prev_start(&starts, 12), Some(10));
+        assert_eq!(prev_start(&starts, 10), Some(0));
+        assert_eq!(prev_start(&starts, 0), None);
+    }
+
+    #[test]
+    fn test_containing_index() {
+        let starts = [2, 10];
+
+        assert_eq!(containing_index(&starts, 0), 0);
+        assert_eq!(containing_index(&starts, 2), 1);
+        assert_eq!(containing_index(&starts, 11), 2);
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[1;31mfoo\x1b[0m bar"), "foo bar");
+    }
+
+    #[test]
+    fn test_find_line() {
+        let lines = vec![
+            "foo".to_owned(),
+            "\x1b[32mbar\x1b[0m".to_owned(),
+            "baz".to_owned(),
+            "bar".to_owned(),
+        ];
+
+        assert_eq!(find_line(&lines, "bar", 0, true), Some(1));
+        assert_eq!(find_line(&lines, "bar", 1, true), Some(3));
+        assert_eq!(find_line(&lines, "bar", 3, false), Some(1));
+        assert_eq!(find_line(&lines, "qux", 0, true), None);
+    }
+}
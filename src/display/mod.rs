@@ -1,5 +1,10 @@
 pub mod context;
+pub mod hex;
 pub mod hunks;
 pub mod inline;
+pub mod interactive;
 pub mod side_by_side;
 pub mod style;
+pub mod theme;
+pub mod whitespace;
+pub mod word_diff;
@@ -12,9 +12,10 @@ use crate::{
     display::context::all_matched_lines_filled,
     display::hunks::{matched_lines_indexes_for_hunk, Hunk},
     display::style::{
-        self, apply_colors, apply_line_number_color, color_positions, novel_style, split_and_apply,
-        BackgroundColor,
+        self, apply_colors, apply_line_number_color, apply_markers, color_positions, novel_style,
+        split_and_apply,
     },
+    display::theme::Theme,
     lines::{codepoint_len, format_line_num, split_on_newlines, LineNumber},
     options::{DisplayMode, DisplayOptions},
     parse::{
@@ -92,7 +93,7 @@ fn display_single_column(
 
     let mut style = Style::new();
     if display_options.use_color {
-        style = novel_style(Style::new(), side, display_options.background_color);
+        style = novel_style(Style::new(), side, &display_options.theme);
     }
 
     for (i, line) in src_lines.iter().enumerate() {
@@ -252,7 +253,7 @@ pub fn lines_with_novel(
 /// Calculate positions of highlights on both sides. This includes
 /// both syntax highlighting and added/removed content highlighting.
 fn highlight_positions(
-    background: BackgroundColor,
+    theme: &Theme,
     syntax_highlight: bool,
     language: Option<Language>,
     lhs_mps: &[MatchedPos],
@@ -261,8 +262,7 @@ fn highlight_positions(
     FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>>,
     FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>>,
 ) {
-    let lhs_positions =
-        color_positions(Side::Left, background, syntax_highlight, language, lhs_mps);
+    let lhs_positions = color_positions(Side::Left, theme, syntax_highlight, language, lhs_mps);
     // Preallocate the hashmap assuming the average line will have 2 items on it.
     let mut lhs_styles: FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>> = FxHashMap::default();
     for (span, style) in lhs_positions {
@@ -270,8 +270,7 @@ fn highlight_positions(
         styles.push((span, style));
     }
 
-    let rhs_positions =
-        color_positions(Side::Right, background, syntax_highlight, language, rhs_mps);
+    let rhs_positions = color_positions(Side::Right, theme, syntax_highlight, language, rhs_mps);
     let mut rhs_styles: FxHashMap<LineNumber, Vec<(SingleLineSpan, Style)>> = FxHashMap::default();
     for (span, style) in rhs_positions {
         let styles = rhs_styles.entry(span.line).or_insert_with(Vec::new);
@@ -305,7 +304,9 @@ fn highlight_as_novel(
     false
 }
 
-pub fn print(
+/// Display `hunks` in two columns, returning the output lines for
+/// each hunk. Every line includes its trailing newline.
+pub fn lines_by_hunk(
     hunks: &[Hunk],
     display_options: &DisplayOptions,
     lhs_display_path: &str,
@@ -316,7 +317,7 @@ pub fn print(
     rhs_src: &str,
     lhs_mps: &[MatchedPos],
     rhs_mps: &[MatchedPos],
-) {
+) -> Vec<Vec<String>> {
     let (lhs_colored_lines, rhs_colored_lines) = if display_options.use_color {
         (
             apply_colors(
@@ -324,7 +325,7 @@ pub fn print(
                 Side::Left,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 lhs_mps,
             ),
             apply_colors(
@@ -332,56 +333,46 @@ pub fn print(
                 Side::Right,
                 display_options.syntax_highlight,
                 language,
-                display_options.background_color,
+                &display_options.theme,
                 rhs_mps,
             ),
         )
     } else {
         (
-            split_on_newlines(lhs_src)
-                .iter()
-                .map(|s| format!("{}\n", s))
-                .collect(),
-            split_on_newlines(rhs_src)
-                .iter()
-                .map(|s| format!("{}\n", s))
-                .collect(),
+            apply_markers(lhs_src, Side::Left, lhs_mps),
+            apply_markers(rhs_src, Side::Right, rhs_mps),
         )
     };
 
     if lhs_src.is_empty() {
-        for line in display_single_column(
+        let mut lines = display_single_column(
             lhs_display_path,
             rhs_display_path,
             lang_name,
             &rhs_colored_lines,
             Side::Right,
             display_options,
-        ) {
-            print!("{}", line);
-        }
-        println!();
-        return;
+        );
+        lines.push("\n".into());
+        return vec![lines];
     }
     if rhs_src.is_empty() {
-        for line in display_single_column(
+        let mut lines = display_single_column(
             lhs_display_path,
             rhs_display_path,
             lang_name,
             &lhs_colored_lines,
             Side::Left,
             display_options,
-        ) {
-            print!("{}", line);
-        }
-        println!();
-        return;
+        );
+        lines.push("\n".into());
+        return vec![lines];
     }
 
     // TODO: this is largely duplicating the `apply_colors` logic.
     let (lhs_highlights, rhs_highlights) = if display_options.use_color {
         highlight_positions(
-            display_options.background_color,
+            &display_options.theme,
             display_options.syntax_highlight,
             language,
             lhs_mps,
@@ -398,12 +389,30 @@ pub fn print(
 
     let lhs_lines = split_on_newlines(lhs_src);
     let rhs_lines = split_on_newlines(rhs_src);
+
+    // Without colour, novel content is shown with textual markers, so
+    // wrap the marked lines rather than the original source.
+    let (lhs_wrap_lines, rhs_wrap_lines): (Vec<&str>, Vec<&str>) = if display_options.use_color {
+        (lhs_lines.clone(), rhs_lines.clone())
+    } else {
+        (
+            lhs_colored_lines
+                .iter()
+                .map(|line| line.strip_suffix('\n').unwrap_or(line))
+                .collect(),
+            rhs_colored_lines
+                .iter()
+                .map(|line| line.strip_suffix('\n').unwrap_or(line))
+                .collect(),
+        )
+    };
     let matched_lines = all_matched_lines_filled(lhs_mps, rhs_mps, &lhs_lines, &rhs_lines);
     let mut matched_lines_to_print = &matched_lines[..];
 
+    let mut res = Vec::with_capacity(hunks.len());
     for (i, hunk) in hunks.iter().enumerate() {
-        println!(
-            "{}",
+        let mut hunk_lines = vec![format!(
+            "{}\n",
             style::header(
                 lhs_display_path,
                 rhs_display_path,
@@ -412,7 +421,7 @@ pub fn print(
                 lang_name,
                 display_options
             )
-        );
+        )];
 
         let (start_i, end_i) = matched_lines_indexes_for_hunk(
             matched_lines_to_print,
@@ -471,19 +480,22 @@ pub fn print(
                     Some(rhs_line_num) => {
                         let rhs_line = &rhs_colored_lines[rhs_line_num.as_usize()];
                         if same_lines {
-                            print!("{}{}", display_rhs_line_num, rhs_line);
+                            hunk_lines.push(format!("{}{}", display_rhs_line_num, rhs_line));
                         } else {
-                            print!(
+                            hunk_lines.push(format!(
                                 "{}{}{}",
                                 display_lhs_line_num, display_rhs_line_num, rhs_line
-                            );
+                            ));
                         }
                     }
                     None => {
                         // We didn't have any changed RHS lines in the
                         // hunk, but we had some contextual lines that
                         // only occurred on the LHS (e.g. extra newlines).
-                        println!("{}{}", display_lhs_line_num, display_rhs_line_num);
+                        hunk_lines.push(format!(
+                            "{}{}\n",
+                            display_lhs_line_num, display_rhs_line_num
+                        ));
                     }
                 }
             } else if no_rhs_changes && !show_both {
@@ -491,22 +503,25 @@ pub fn print(
                     Some(lhs_line_num) => {
                         let lhs_line = &lhs_colored_lines[lhs_line_num.as_usize()];
                         if same_lines {
-                            print!("{}{}", display_lhs_line_num, lhs_line);
+                            hunk_lines.push(format!("{}{}", display_lhs_line_num, lhs_line));
                         } else {
-                            print!(
+                            hunk_lines.push(format!(
                                 "{}{}{}",
                                 display_lhs_line_num, display_rhs_line_num, lhs_line
-                            );
+                            ));
                         }
                     }
                     None => {
-                        println!("{}{}", display_lhs_line_num, display_rhs_line_num);
+                        hunk_lines.push(format!(
+                            "{}{}\n",
+                            display_lhs_line_num, display_rhs_line_num
+                        ));
                     }
                 }
             } else {
                 let lhs_line = match lhs_line_num {
                     Some(lhs_line_num) => split_and_apply(
-                        lhs_lines[lhs_line_num.as_usize()],
+                        lhs_wrap_lines[lhs_line_num.as_usize()],
                         source_dims.content_width,
                         display_options.tab_width,
                         display_options.use_color,
@@ -517,7 +532,7 @@ pub fn print(
                 };
                 let rhs_line = match rhs_line_num {
                     Some(rhs_line_num) => split_and_apply(
-                        rhs_lines[rhs_line_num.as_usize()],
+                        rhs_wrap_lines[rhs_line_num.as_usize()],
                         source_dims.content_width,
                         display_options.tab_width,
                         display_options.use_color,
@@ -575,7 +590,10 @@ pub fn print(
                         s
                     };
 
-                    println!("{}{}{}{}{}", lhs_num, lhs_line, SPACER, rhs_num, rhs_line);
+                    hunk_lines.push(format!(
+                        "{}{}{}{}{}\n",
+                        lhs_num, lhs_line, SPACER, rhs_num, rhs_line
+                    ));
                 }
             }
 
@@ -586,8 +604,11 @@ pub fn print(
                 prev_rhs_line_num = *rhs_line_num;
             }
         }
-        println!();
+        hunk_lines.push("\n".into());
+        res.push(hunk_lines);
     }
+
+    res
 }
 
 #[cfg(test)]
@@ -713,7 +734,7 @@ mod tests {
         }];
 
         // Simple smoke test.
-        print(
+        lines_by_hunk(
             &hunks,
             &DisplayOptions::default(),
             "foo-old.el",
@@ -2,6 +2,7 @@
 
 use crate::{
     constants::Side,
+    display::theme::Theme,
     lines::{byte_len, split_on_newlines, LineNumber},
     options::DisplayOptions,
     parse::{
@@ -10,7 +11,7 @@ use crate::{
     },
     positions::SingleLineSpan,
 };
-use owo_colors::{OwoColorize, Style};
+use owo_colors::{DynColors, OwoColorize, Style};
 use rustc_hash::FxHashMap;
 use std::cmp::{max, min};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
@@ -310,23 +311,21 @@ fn style_lines(lines: &[&str], styles: &[(SingleLineSpan, Style)]) -> Vec<String
     res
 }
 
-pub fn novel_style(style: Style, side: Side, background: BackgroundColor) -> Style {
-    if background.is_dark() {
-        match side {
-            Side::Left => style.bright_red(),
-            Side::Right => style.bright_green(),
-        }
-    } else {
-        match side {
-            Side::Left => style.red(),
-            Side::Right => style.green(),
-        }
+/// Set the foreground colour of `style`, if the theme has one.
+fn with_color(style: Style, color: Option<DynColors>) -> Style {
+    match color {
+        Some(color) => style.color(color),
+        None => style,
     }
 }
 
+pub fn novel_style(style: Style, side: Side, theme: &Theme) -> Style {
+    with_color(style, theme.side(side).novel)
+}
+
 pub fn color_positions(
     side: Side,
-    background: BackgroundColor,
+    theme: &Theme,
     syntax_highlight: bool,
     language: Option<Language>,
     positions: &[MatchedPos],
@@ -337,34 +336,20 @@ pub fn color_positions(
         match pos.kind {
             MatchKind::UnchangedToken { highlight, .. } | MatchKind::Ignored { highlight } => {
                 if syntax_highlight {
-                    if let TokenKind::Atom(atom_kind) = highlight {
-                        match atom_kind {
-                            AtomKind::String => {
-                                style = if background.is_dark() {
-                                    style.bright_magenta()
-                                } else {
-                                    style.magenta()
-                                };
-                            }
-                            AtomKind::Comment => {
-                                style = style.italic();
-                                style = if background.is_dark() {
-                                    style.bright_blue()
-                                } else {
-                                    style.blue()
-                                };
-                            }
-                            AtomKind::Keyword | AtomKind::Type => {
-                                style = style.bold();
-                            }
-                            AtomKind::TreeSitterError => style = style.purple(),
-                            AtomKind::Normal => {}
+                    style = with_color(style, theme.side(side).token_color(highlight));
+                    match highlight {
+                        TokenKind::Atom(AtomKind::Comment) => {
+                            style = style.italic();
                         }
+                        TokenKind::Atom(AtomKind::Keyword) | TokenKind::Atom(AtomKind::Type) => {
+                            style = style.bold();
+                        }
+                        _ => {}
                     }
                 }
             }
             MatchKind::Novel { highlight, .. } => {
-                style = novel_style(style, side, background);
+                style = novel_style(style, side, theme);
                 if syntax_highlight
                     && matches!(
                         highlight,
@@ -380,7 +365,7 @@ pub fn color_positions(
                 }
             }
             MatchKind::NovelWord { highlight } => {
-                style = novel_style(style, side, background).bold();
+                style = novel_style(style, side, theme).bold();
 
                 // Underline novel words inside comments in code, but
                 // don't apply it to every single line in plaintext.
@@ -393,11 +378,16 @@ pub fn color_positions(
                 }
             }
             MatchKind::NovelLinePart { highlight, .. } => {
-                style = novel_style(style, side, background);
+                style = novel_style(style, side, theme);
                 if syntax_highlight && matches!(highlight, TokenKind::Atom(AtomKind::Comment)) {
                     style = style.italic();
                 }
             }
+            MatchKind::Renamed { .. } => {
+                // Renames are reported once in the header, so they
+                // shouldn't distract from other changes.
+                style = novel_style(style, side, theme).dimmed();
+            }
         };
         styles.push((pos.pos, style));
     }
@@ -409,27 +399,114 @@ pub fn apply_colors(
     side: Side,
     syntax_highlight: bool,
     language: Option<Language>,
-    background: BackgroundColor,
+    theme: &Theme,
     positions: &[MatchedPos],
 ) -> Vec<String> {
-    let styles = color_positions(side, background, syntax_highlight, language, positions);
+    let styles = color_positions(side, theme, syntax_highlight, language, positions);
     let lines = split_on_newlines(s);
     style_lines(&lines, &styles)
 }
 
-fn apply_header_color(
-    s: &str,
-    use_color: bool,
-    background: BackgroundColor,
-    hunk_num: usize,
-) -> String {
+/// The text shown around novel content when colour is disabled.
+fn novel_markers(side: Side) -> (&'static str, &'static str) {
+    match side {
+        Side::Left => ("[-", "-]"),
+        Side::Right => ("{+", "+}"),
+    }
+}
+
+/// Wrap novel content in `s` with textual markers, for output without
+/// colour: `[-removed-]` on the LHS and `{+added+}` on the RHS. Return
+/// a vec of the marked lines, including trailing newlines.
+///
+/// Adjacent novel spans are marked together if there's only
+/// whitespace between them.
+pub fn apply_markers(s: &str, side: Side, positions: &[MatchedPos]) -> Vec<String> {
+    let (open, close) = novel_markers(side);
+    let lines = split_on_newlines(s);
+    let ranges_by_line = novel_ranges(&lines, positions);
+
+    let mut res = Vec::with_capacity(lines.len());
+    for (line, ranges) in lines.iter().zip(ranges_by_line) {
+        let mut marked_line = String::with_capacity(line.len());
+        let mut i = 0;
+        for (start, end) in ranges {
+            marked_line.push_str(substring_by_byte(line, i, start));
+            marked_line.push_str(open);
+            marked_line.push_str(substring_by_byte(line, start, end));
+            marked_line.push_str(close);
+            i = end;
+        }
+        marked_line.push_str(substring_by_byte(line, i, byte_len(line)));
+        marked_line.push('\n');
+        res.push(marked_line);
+    }
+
+    res
+}
+
+/// The byte ranges of novel content on each of `lines`, sorted and
+/// non-overlapping. Ranges separated only by whitespace are merged.
+pub fn novel_ranges(lines: &[&str], positions: &[MatchedPos]) -> Vec<Vec<(usize, usize)>> {
+    let mut spans_by_line: FxHashMap<LineNumber, Vec<SingleLineSpan>> = FxHashMap::default();
+    for mp in positions {
+        // Words that occur on both sides of a changed line aren't
+        // novel themselves, so don't mark them.
+        if matches!(
+            mp.kind,
+            MatchKind::Novel { .. } | MatchKind::NovelWord { .. } | MatchKind::Renamed { .. }
+        ) {
+            spans_by_line.entry(mp.pos.line).or_default().push(mp.pos);
+        }
+    }
+
+    let mut res = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let mut spans = spans_by_line.remove(&(i as u32).into()).unwrap_or_default();
+        spans.sort_by_key(|span| span.start_col);
+
+        let line_bytes = byte_len(line);
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for span in spans {
+            let start = min(span.start_col as usize, line_bytes);
+            let end = min(span.end_col as usize, line_bytes);
+            if start >= end {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some((_, prev_end))
+                    if *prev_end >= start
+                        || substring_by_byte(line, *prev_end, start).trim().is_empty() =>
+                {
+                    *prev_end = max(*prev_end, end);
+                }
+                _ => ranges.push((start, end)),
+            }
+        }
+        res.push(ranges);
+    }
+
+    res
+}
+
+/// Highlight a novel fragment of a line: with colour if enabled,
+/// otherwise with textual markers.
+pub fn mark_novel(s: &str, side: Side, display_options: &DisplayOptions) -> String {
+    if display_options.use_color {
+        s.style(novel_style(Style::new(), side, &display_options.theme))
+            .to_string()
+    } else {
+        let (open, close) = novel_markers(side);
+        format!("{}{}{}", open, s, close)
+    }
+}
+
+fn apply_header_color(s: &str, use_color: bool, theme: &Theme, hunk_num: usize) -> String {
     if use_color {
-        if hunk_num != 1 {
-            s.to_string()
-        } else if background.is_dark() {
-            s.bright_yellow().to_string()
-        } else {
-            s.yellow().to_string()
+        match theme.header {
+            Some(color) if hunk_num == 1 => s.color(color).to_string(),
+            _ => s.to_string(),
         }
         .bold()
         .to_string()
@@ -453,14 +530,25 @@ pub(crate) fn apply_line_number_color(
             // For changed lines, show the line number as red/green
             // and bold. This works well for syntactic diffs, where
             // most content is not bold.
-            style = novel_style(style, side, display_options.background_color).bold();
+            style = novel_style(style, side, &display_options.theme).bold();
         } else {
             // For unchanged lines, dim the line numbers so it's
             // clearly separate from the content.
-            style = style.dimmed()
+            style = with_color(style.dimmed(), display_options.theme.side(side).line_number);
         }
 
         s.style(style).to_string()
+    } else if is_novel {
+        // Without colour, use the padding after the line number to
+        // show whether the line has changes.
+        let marker = match side {
+            Side::Left => '-',
+            Side::Right => '+',
+        };
+        match s.strip_suffix(' ') {
+            Some(line_num) => format!("{}{}", line_num, marker),
+            None => s.to_string(),
+        }
     } else {
         s.to_string()
     }
@@ -483,13 +571,13 @@ pub fn header(
     let rhs_path_pretty = apply_header_color(
         rhs_display_path,
         display_options.use_color,
-        display_options.background_color,
+        &display_options.theme,
         hunk_num,
     );
     let lhs_path_pretty = apply_header_color(
         lhs_display_path,
         display_options.use_color,
-        display_options.background_color,
+        &display_options.theme,
         hunk_num,
     );
     if hunk_num == 1 && lhs_display_path != rhs_display_path && display_options.in_vcs {
@@ -558,6 +646,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_markers() {
+        let positions = [
+            MatchedPos {
+                kind: MatchKind::Novel {
+                    highlight: TokenKind::Atom(AtomKind::Normal),
+                },
+                pos: SingleLineSpan {
+                    line: 0.into(),
+                    start_col: 4,
+                    end_col: 7,
+                },
+            },
+            MatchedPos {
+                kind: MatchKind::Novel {
+                    highlight: TokenKind::Delimiter,
+                },
+                pos: SingleLineSpan {
+                    line: 0.into(),
+                    start_col: 8,
+                    end_col: 9,
+                },
+            },
+        ];
+
+        assert_eq!(
+            apply_markers("foo bar (\nbaz", Side::Right, &positions),
+            vec!["foo {+bar (+}\n", "baz\n"]
+        );
+    }
+
+    #[test]
+    fn test_line_number_marker_without_color() {
+        let display_options = DisplayOptions {
+            use_color: false,
+            ..DisplayOptions::default()
+        };
+
+        assert_eq!(
+            apply_line_number_color("12 ", true, Side::Left, &display_options),
+            "12-"
+        );
+        assert_eq!(
+            apply_line_number_color("12 ", false, Side::Left, &display_options),
+            "12 "
+        );
+    }
+
     #[test]
     fn test_split_and_apply_missing() {
         let res = split_and_apply("foo", 3, TAB_WIDTH, true, &[], Side::Left);
@@ -0,0 +1,299 @@
+//! Colour themes, which choose the colours used for each kind of
+//! token and each side of the diff.
+
+use owo_colors::{AnsiColors, DynColors, XtermColors};
+
+use crate::{
+    constants::Side,
+    display::style::BackgroundColor,
+    parse::syntax::{AtomKind, TokenKind},
+};
+
+pub const THEME_NAMES: &[&str] = &["default", "blue-orange", "blue-yellow"];
+
+/// The colours used for one side of the diff. `None` means the
+/// terminal's default colour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideColors {
+    pub novel: Option<DynColors>,
+    pub normal: Option<DynColors>,
+    pub string: Option<DynColors>,
+    pub type_: Option<DynColors>,
+    pub comment: Option<DynColors>,
+    pub keyword: Option<DynColors>,
+    pub delimiter: Option<DynColors>,
+    pub error: Option<DynColors>,
+    pub line_number: Option<DynColors>,
+}
+
+impl SideColors {
+    /// The colour for unchanged tokens of kind `highlight`.
+    pub fn token_color(&self, highlight: TokenKind) -> Option<DynColors> {
+        match highlight {
+            TokenKind::Delimiter => self.delimiter,
+            TokenKind::Atom(AtomKind::Normal) => self.normal,
+            TokenKind::Atom(AtomKind::String) => self.string,
+            TokenKind::Atom(AtomKind::Type) => self.type_,
+            TokenKind::Atom(AtomKind::Comment) => self.comment,
+            TokenKind::Atom(AtomKind::Keyword) => self.keyword,
+            TokenKind::Atom(AtomKind::TreeSitterError) => self.error,
+        }
+    }
+
+    fn color_mut(&mut self, name: &str) -> Option<&mut Option<DynColors>> {
+        Some(match name {
+            "novel" => &mut self.novel,
+            "normal" => &mut self.normal,
+            "string" => &mut self.string,
+            "type" => &mut self.type_,
+            "comment" => &mut self.comment,
+            "keyword" => &mut self.keyword,
+            "delimiter" => &mut self.delimiter,
+            "error" => &mut self.error,
+            "line-number" => &mut self.line_number,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub lhs: SideColors,
+    pub rhs: SideColors,
+    pub header: Option<DynColors>,
+}
+
+fn ansi(color: AnsiColors) -> Option<DynColors> {
+    Some(DynColors::Ansi(color))
+}
+
+fn xterm(color: u8) -> Option<DynColors> {
+    Some(DynColors::Xterm(XtermColors::from(color)))
+}
+
+impl Theme {
+    /// The built-in theme called `name`, with colours suited to
+    /// `background`.
+    pub fn named(name: &str, background: BackgroundColor) -> Option<Self> {
+        let dark = background.is_dark();
+        let (lhs_novel, rhs_novel, string, comment) = match name {
+            "default" => {
+                if dark {
+                    (
+                        ansi(AnsiColors::BrightRed),
+                        ansi(AnsiColors::BrightGreen),
+                        ansi(AnsiColors::BrightMagenta),
+                        ansi(AnsiColors::BrightBlue),
+                    )
+                } else {
+                    (
+                        ansi(AnsiColors::Red),
+                        ansi(AnsiColors::Green),
+                        ansi(AnsiColors::Magenta),
+                        ansi(AnsiColors::Blue),
+                    )
+                }
+            }
+            // Colour-blind-safe palettes avoid telling sides apart
+            // by red and green. Comments are grey so they're not
+            // confused with novel content.
+            "blue-orange" => {
+                if dark {
+                    (xterm(208), xterm(39), None, xterm(245))
+                } else {
+                    (xterm(166), xterm(25), None, xterm(242))
+                }
+            }
+            "blue-yellow" => {
+                if dark {
+                    (xterm(220), xterm(39), None, xterm(245))
+                } else {
+                    (xterm(136), xterm(25), None, xterm(242))
+                }
+            }
+            _ => return None,
+        };
+
+        let side_colors = |novel| SideColors {
+            novel,
+            normal: None,
+            string,
+            type_: None,
+            comment,
+            keyword: None,
+            delimiter: None,
+            error: ansi(AnsiColors::Magenta),
+            line_number: None,
+        };
+
+        Some(Self {
+            lhs: side_colors(lhs_novel),
+            rhs: side_colors(rhs_novel),
+            header: if dark {
+                ansi(AnsiColors::BrightYellow)
+            } else {
+                ansi(AnsiColors::Yellow)
+            },
+        })
+    }
+
+    pub fn side(&self, side: Side) -> &SideColors {
+        match side {
+            Side::Left => &self.lhs,
+            Side::Right => &self.rhs,
+        }
+    }
+
+    /// Override colours with the settings in a theme file.
+    ///
+    /// Each line has the form `KEY = COLOR`, and lines starting with
+    /// `#` are ignored. Keys are token kinds (`novel`, `normal`,
+    /// `string`, `type`, `comment`, `keyword`, `delimiter`, `error`,
+    /// `line-number`), optionally prefixed with `lhs.` or `rhs.` to
+    /// set one side only, or `header`.
+    pub fn apply_file(&mut self, src: &str) -> Result<(), String> {
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("line {}: expected KEY = COLOR", i + 1)),
+            };
+            let color = parse_color(value).map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+            if key == "header" {
+                self.header = color;
+                continue;
+            }
+
+            let (sides, name): (&[Side], &str) = if let Some(name) = key.strip_prefix("lhs.") {
+                (&[Side::Left], name)
+            } else if let Some(name) = key.strip_prefix("rhs.") {
+                (&[Side::Right], name)
+            } else {
+                (&[Side::Left, Side::Right], key)
+            };
+
+            for side in sides {
+                let side_colors = match side {
+                    Side::Left => &mut self.lhs,
+                    Side::Right => &mut self.rhs,
+                };
+                match side_colors.color_mut(name) {
+                    Some(slot) => *slot = color,
+                    None => return Err(format!("line {}: unknown key `{}`", i + 1, key)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a colour: an ANSI colour name such as `red` or
+/// `bright-blue`, a 256-colour palette index, a 24-bit `#rrggbb`
+/// value, or `none`.
+fn parse_color(s: &str) -> Result<Option<DynColors>, String> {
+    if s == "none" {
+        return Ok(None);
+    }
+
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+            return Ok(Some(DynColors::Rgb(
+                component(0),
+                component(2),
+                component(4),
+            )));
+        }
+        return Err(format!("invalid RGB colour `{}`", s));
+    }
+
+    if let Ok(index) = s.parse::<u8>() {
+        return Ok(xterm(index));
+    }
+
+    let color = match s {
+        "black" => AnsiColors::Black,
+        "red" => AnsiColors::Red,
+        "green" => AnsiColors::Green,
+        "yellow" => AnsiColors::Yellow,
+        "blue" => AnsiColors::Blue,
+        "magenta" => AnsiColors::Magenta,
+        "cyan" => AnsiColors::Cyan,
+        "white" => AnsiColors::White,
+        "bright-black" => AnsiColors::BrightBlack,
+        "bright-red" => AnsiColors::BrightRed,
+        "bright-green" => AnsiColors::BrightGreen,
+        "bright-yellow" => AnsiColors::BrightYellow,
+        "bright-blue" => AnsiColors::BrightBlue,
+        "bright-magenta" => AnsiColors::BrightMagenta,
+        "bright-cyan" => AnsiColors::BrightCyan,
+        "bright-white" => AnsiColors::BrightWhite,
+        _ => return Err(format!("unknown colour `{}`", s)),
+    };
+    Ok(ansi(color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_named_themes_exist() {
+        for name in THEME_NAMES {
+            assert!(Theme::named(name, BackgroundColor::Dark).is_some());
+            assert!(Theme::named(name, BackgroundColor::Light).is_some());
+        }
+        assert!(Theme::named("nonexistent", BackgroundColor::Dark).is_none());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("none"), Ok(None));
+        assert_eq!(
+            parse_color("#ff8000"),
+            Ok(Some(DynColors::Rgb(255, 128, 0)))
+        );
+        assert_eq!(parse_color("208"), Ok(xterm(208)));
+        assert_eq!(parse_color("bright-red"), Ok(ansi(AnsiColors::BrightRed)));
+        assert!(parse_color("#ff80").is_err());
+        assert!(parse_color("256").is_err());
+    }
+
+    #[test]
+    fn test_apply_file() {
+        let mut theme = Theme::named("default", BackgroundColor::Dark).unwrap();
+        theme
+            .apply_file(
+                "# Blue and orange.
+                 lhs.novel = #e69f00
+                 rhs.novel = 33
+                 string = none",
+            )
+            .unwrap();
+
+        assert_eq!(theme.lhs.novel, Some(DynColors::Rgb(0xe6, 0x9f, 0x00)));
+        assert_eq!(theme.rhs.novel, xterm(33));
+        assert_eq!(theme.lhs.string, None);
+        assert_eq!(theme.rhs.string, None);
+    }
+
+    #[test]
+    fn test_apply_file_errors() {
+        let mut theme = Theme::named("default", BackgroundColor::Dark).unwrap();
+        assert_eq!(
+            theme.apply_file("\nlhs.bogus = red"),
+            Err("line 2: unknown key `lhs.bogus`".to_owned())
+        );
+        assert_eq!(
+            theme.apply_file("novel red"),
+            Err("line 1: expected KEY = COLOR".to_owned())
+        );
+    }
+}
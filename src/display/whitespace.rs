@@ -0,0 +1,265 @@
+//! Making whitespace visible, for languages where indentation is
+//! significant.
+//!
+//! Tree-sitter syntax trees don't include whitespace, so a line that
+//! is only reindented has no syntactic changes. When whitespace is
+//! shown, we treat changed indentation as novel, and replace tabs and
+//! trailing spaces with visible characters.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    lines::{split_on_newlines, LineNumber},
+    parse::syntax::{zip_repeat_shorter, AtomKind, MatchKind, MatchedPos, TokenKind},
+    positions::SingleLineSpan,
+};
+
+const TAB_MARKER: char = '→';
+const SPACE_MARKER: char = '·';
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Find lines whose first token is unchanged, but whose indentation
+/// differs from the line containing that token on the other side.
+/// Returns novel positions covering the indentation on each side.
+pub fn indentation_changes(
+    lhs_src: &str,
+    rhs_src: &str,
+    lhs_positions: &[MatchedPos],
+) -> (Vec<MatchedPos>, Vec<MatchedPos>) {
+    let lhs_lines = split_on_newlines(lhs_src);
+    let rhs_lines = split_on_newlines(rhs_src);
+
+    let mut seen: FxHashSet<(LineNumber, LineNumber)> = FxHashSet::default();
+    let mut lhs_res = vec![];
+    let mut rhs_res = vec![];
+    for mp in lhs_positions {
+        if let MatchKind::UnchangedToken {
+            self_pos,
+            opposite_pos,
+            ..
+        } = &mp.kind
+        {
+            for (lhs_span, rhs_span) in zip_repeat_shorter(self_pos, opposite_pos) {
+                let lhs_indent = leading_whitespace(lhs_lines[lhs_span.line.as_usize()]);
+                let rhs_indent = leading_whitespace(rhs_lines[rhs_span.line.as_usize()]);
+                if lhs_span.start_col as usize != lhs_indent.len()
+                    || rhs_span.start_col as usize != rhs_indent.len()
+                    || lhs_indent == rhs_indent
+                    || !seen.insert((lhs_span.line, rhs_span.line))
+                {
+                    continue;
+                }
+
+                let novel = |line, indent: &str| MatchedPos {
+                    kind: MatchKind::Novel {
+                        highlight: TokenKind::Atom(AtomKind::Normal),
+                    },
+                    pos: SingleLineSpan {
+                        line,
+                        start_col: 0,
+                        end_col: indent.len() as u32,
+                    },
+                };
+                lhs_res.push(novel(lhs_span.line, lhs_indent));
+                rhs_res.push(novel(rhs_span.line, rhs_indent));
+            }
+        }
+    }
+
+    (lhs_res, rhs_res)
+}
+
+/// For each line, the new byte offset of every old byte offset.
+type OffsetMap = Vec<Vec<usize>>;
+
+/// Replace tabs, trailing spaces, and spaces inside novel whitespace
+/// with visible characters. Tabs are followed by padding so lines
+/// keep their width.
+fn show_whitespace_in_src(
+    src: &str,
+    positions: &[MatchedPos],
+    tab_width: usize,
+) -> (String, OffsetMap) {
+    let mut novel_whitespace: FxHashMap<usize, Vec<(usize, usize)>> = FxHashMap::default();
+    let lines: Vec<&str> = src.split('\n').collect();
+    for mp in positions {
+        if matches!(
+            mp.kind,
+            MatchKind::Novel { .. } | MatchKind::NovelWord { .. }
+        ) {
+            let line = lines[mp.pos.line.as_usize()];
+            let start = mp.pos.start_col as usize;
+            let end = (mp.pos.end_col as usize).min(line.len());
+            if start < end && line[start..end].trim().is_empty() {
+                novel_whitespace
+                    .entry(mp.pos.line.as_usize())
+                    .or_default()
+                    .push((start, end));
+            }
+        }
+    }
+
+    let mut res = String::with_capacity(src.len());
+    let mut offsets = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            res.push('\n');
+        }
+
+        let content = line.strip_suffix('\r').unwrap_or(line);
+        let trailing_start = content.trim_end().len();
+        let line_novel_whitespace = novel_whitespace.get(&i);
+        let is_novel_whitespace = |offset: usize| match line_novel_whitespace {
+            Some(ranges) => ranges
+                .iter()
+                .any(|(start, end)| *start <= offset && offset < *end),
+            None => false,
+        };
+
+        let line_start = res.len();
+        let mut line_offsets = Vec::with_capacity(line.len() + 1);
+        for (offset, c) in line.char_indices() {
+            for _ in line_offsets.len()..=offset {
+                line_offsets.push(res.len() - line_start);
+            }
+
+            if c == '\t' {
+                res.push(TAB_MARKER);
+                res.push_str(&" ".repeat(tab_width.saturating_sub(1)));
+            } else if c == ' ' && (offset >= trailing_start || is_novel_whitespace(offset)) {
+                res.push(SPACE_MARKER);
+            } else {
+                res.push(c);
+            }
+        }
+        while line_offsets.len() <= line.len() {
+            line_offsets.push(res.len() - line_start);
+        }
+        offsets.push(line_offsets);
+    }
+
+    (res, offsets)
+}
+
+fn remap_span(span: &SingleLineSpan, offsets: &OffsetMap) -> SingleLineSpan {
+    let line_offsets = &offsets[span.line.as_usize()];
+    let remap_col = |col: u32| {
+        let col = (col as usize).min(line_offsets.len() - 1);
+        line_offsets[col] as u32
+    };
+
+    SingleLineSpan {
+        line: span.line,
+        start_col: remap_col(span.start_col),
+        end_col: remap_col(span.end_col),
+    }
+}
+
+fn remap_positions(
+    positions: &[MatchedPos],
+    self_offsets: &OffsetMap,
+    opposite_offsets: &OffsetMap,
+) -> Vec<MatchedPos> {
+    positions
+        .iter()
+        .map(|mp| {
+            let kind = match &mp.kind {
+                MatchKind::UnchangedToken {
+                    highlight,
+                    self_pos,
+                    opposite_pos,
+                } => MatchKind::UnchangedToken {
+                    highlight: *highlight,
+                    self_pos: self_pos
+                        .iter()
+                        .map(|span| remap_span(span, self_offsets))
+                        .collect(),
+                    opposite_pos: opposite_pos
+                        .iter()
+                        .map(|span| remap_span(span, opposite_offsets))
+                        .collect(),
+                },
+                MatchKind::NovelLinePart {
+                    highlight,
+                    self_pos,
+                    opposite_pos,
+                } => MatchKind::NovelLinePart {
+                    highlight: *highlight,
+                    self_pos: remap_span(self_pos, self_offsets),
+                    opposite_pos: opposite_pos
+                        .iter()
+                        .map(|span| remap_span(span, opposite_offsets))
+                        .collect(),
+                },
+                kind => kind.clone(),
+            };
+
+            MatchedPos {
+                kind,
+                pos: remap_span(&mp.pos, self_offsets),
+            }
+        })
+        .collect()
+}
+
+/// Make whitespace visible in both sides of a diff, returning the new
+/// sources and positions.
+pub fn show_whitespace(
+    lhs_src: &str,
+    rhs_src: &str,
+    lhs_positions: &[MatchedPos],
+    rhs_positions: &[MatchedPos],
+    tab_width: usize,
+) -> (String, String, Vec<MatchedPos>, Vec<MatchedPos>) {
+    let (new_lhs_src, lhs_offsets) = show_whitespace_in_src(lhs_src, lhs_positions, tab_width);
+    let (new_rhs_src, rhs_offsets) = show_whitespace_in_src(rhs_src, rhs_positions, tab_width);
+
+    (
+        new_lhs_src,
+        new_rhs_src,
+        remap_positions(lhs_positions, &lhs_offsets, &rhs_offsets),
+        remap_positions(rhs_positions, &rhs_offsets, &lhs_offsets),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_show_tabs_and_trailing_spaces() {
+        let (src, offsets) = show_whitespace_in_src("\tfoo  \nbar", &[], 2);
+        assert_eq!(src, "→ foo··\nbar");
+        // `f` moves from byte 1 to after the arrow (3 bytes) and a
+        // space.
+        assert_eq!(offsets[0][1], 4);
+        assert_eq!(offsets[1], vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_indentation_changes() {
+        let lhs_src = "if x:\n  foo()";
+        let rhs_src = "if x:\n    foo()";
+        let foo_pos = |start_col| SingleLineSpan {
+            line: 1.into(),
+            start_col,
+            end_col: start_col + 3,
+        };
+        let lhs_positions = [MatchedPos {
+            kind: MatchKind::UnchangedToken {
+                highlight: TokenKind::Atom(AtomKind::Normal),
+                self_pos: vec![foo_pos(2)],
+                opposite_pos: vec![foo_pos(4)],
+            },
+            pos: foo_pos(2),
+        }];
+
+        let (lhs_novel, rhs_novel) = indentation_changes(lhs_src, rhs_src, &lhs_positions);
+        assert_eq!(lhs_novel[0].pos.end_col, 2);
+        assert_eq!(rhs_novel[0].pos.end_col, 4);
+    }
+}
@@ -0,0 +1,325 @@
+//! Word diff display: each changed line is shown once, with removed
+//! and added content interleaved, like `git diff --word-diff`.
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    constants::Side,
+    display::context::all_matched_lines_filled,
+    display::hunks::{matched_lines_indexes_for_hunk, Hunk},
+    display::style::{self, apply_line_number_color, mark_novel, novel_ranges},
+    lines::{format_line_num, split_on_newlines, LineNumber},
+    options::DisplayOptions,
+    parse::syntax::{zip_repeat_shorter, MatchKind, MatchedPos},
+};
+
+/// A token that occurs on a line in both the LHS and the RHS, as
+/// (LHS start, LHS end, RHS start, RHS end) byte offsets.
+type Anchor = (usize, usize, usize, usize);
+
+/// Find the tokens that tie each pair of LHS and RHS lines together.
+fn anchors_by_line(
+    lhs_positions: &[MatchedPos],
+) -> FxHashMap<(LineNumber, LineNumber), Vec<Anchor>> {
+    let mut res: FxHashMap<(LineNumber, LineNumber), Vec<Anchor>> = FxHashMap::default();
+    for mp in lhs_positions {
+        match &mp.kind {
+            MatchKind::UnchangedToken {
+                self_pos,
+                opposite_pos,
+                ..
+            } => {
+                for (self_span, opposite_span) in zip_repeat_shorter(self_pos, opposite_pos) {
+                    res.entry((self_span.line, opposite_span.line))
+                        .or_default()
+                        .push((
+                            self_span.start_col as usize,
+                            self_span.end_col as usize,
+                            opposite_span.start_col as usize,
+                            opposite_span.end_col as usize,
+                        ));
+                }
+            }
+            MatchKind::NovelLinePart {
+                self_pos,
+                opposite_pos,
+                ..
+            } => {
+                for opposite_span in opposite_pos {
+                    res.entry((self_pos.line, opposite_span.line))
+                        .or_default()
+                        .push((
+                            self_pos.start_col as usize,
+                            self_pos.end_col as usize,
+                            opposite_span.start_col as usize,
+                            opposite_span.end_col as usize,
+                        ));
+                }
+            }
+            MatchKind::Novel { .. }
+            | MatchKind::NovelWord { .. }
+            | MatchKind::Ignored { .. }
+            | MatchKind::Renamed { .. } => {}
+        }
+    }
+    res
+}
+
+/// Mark the novel ranges of a line that only exists on one side.
+fn mark_line(
+    line: &str,
+    ranges: &[(usize, usize)],
+    side: Side,
+    display_options: &DisplayOptions,
+) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut i = 0;
+    for (start, end) in ranges {
+        res.push_str(&line[i..*start]);
+        res.push_str(&mark_novel(&line[*start..*end], side, display_options));
+        i = *end;
+    }
+    res.push_str(&line[i..]);
+    res
+}
+
+/// The RHS offset where removed LHS content at `lhs_start` should
+/// be shown: after the last token both lines share that precedes it.
+fn insertion_point(lhs_start: usize, anchors: &[Anchor], rhs_line: &str) -> usize {
+    let mut res = None;
+    for (_, anchor_lhs_end, _, anchor_rhs_end) in anchors {
+        if *anchor_lhs_end <= lhs_start {
+            res = Some(match res {
+                Some(prev) if prev > *anchor_rhs_end => prev,
+                _ => *anchor_rhs_end,
+            });
+        }
+    }
+
+    match res {
+        Some(offset) => offset.min(rhs_line.len()),
+        // Nothing precedes the removed content, so show it at the
+        // start of the line, after any indentation.
+        None => rhs_line.len() - rhs_line.trim_start().len(),
+    }
+}
+
+/// Show an LHS line and its corresponding RHS line as a single line,
+/// with the removed content of the LHS interleaved into the RHS.
+fn interleave_line(
+    lhs_line: &str,
+    rhs_line: &str,
+    lhs_ranges: &[(usize, usize)],
+    rhs_ranges: &[(usize, usize)],
+    anchors: &[Anchor],
+    display_options: &DisplayOptions,
+) -> String {
+    let mut removed: Vec<(usize, &str)> = lhs_ranges
+        .iter()
+        .map(|(start, end)| {
+            (
+                insertion_point(*start, anchors, rhs_line),
+                &lhs_line[*start..*end],
+            )
+        })
+        .collect();
+    // Sort by insertion point, keeping LHS order for ties.
+    removed.sort_by_key(|(offset, _)| *offset);
+    let mut removed = removed.into_iter().peekable();
+
+    let mut res = String::with_capacity(lhs_line.len() + rhs_line.len());
+    let mut i = 0;
+    let mut push_unchanged = |res: &mut String, i: &mut usize, end: usize| {
+        while let Some((offset, text)) = removed.next_if(|(offset, _)| *offset <= end) {
+            // Removed content that belongs inside an added range
+            // is shown after it.
+            let offset = offset.max(*i);
+            res.push_str(&rhs_line[*i..offset]);
+            res.push_str(&mark_novel(text, Side::Left, display_options));
+            *i = offset;
+        }
+        res.push_str(&rhs_line[*i..end]);
+        *i = end;
+    };
+
+    for (start, end) in rhs_ranges {
+        push_unchanged(&mut res, &mut i, *start);
+        res.push_str(&mark_novel(
+            &rhs_line[*start..*end],
+            Side::Right,
+            display_options,
+        ));
+        i = *end;
+    }
+    push_unchanged(&mut res, &mut i, rhs_line.len());
+
+    res
+}
+
+/// Display `hunks` as a word diff, returning the output lines for
+/// each hunk. Every line includes its trailing newline.
+pub fn lines_by_hunk(
+    lhs_src: &str,
+    rhs_src: &str,
+    display_options: &DisplayOptions,
+    lhs_positions: &[MatchedPos],
+    rhs_positions: &[MatchedPos],
+    hunks: &[Hunk],
+    lhs_display_path: &str,
+    rhs_display_path: &str,
+    display_language: &str,
+) -> Vec<Vec<String>> {
+    let lhs_lines = split_on_newlines(lhs_src);
+    let rhs_lines = split_on_newlines(rhs_src);
+    let lhs_ranges = novel_ranges(&lhs_lines, lhs_positions);
+    let rhs_ranges = novel_ranges(&rhs_lines, rhs_positions);
+    let anchors = anchors_by_line(lhs_positions);
+
+    let matched_lines =
+        all_matched_lines_filled(lhs_positions, rhs_positions, &lhs_lines, &rhs_lines);
+    let mut matched_lines_to_print = &matched_lines[..];
+
+    let format_line = |line_num: LineNumber, is_novel: bool, side: Side, content: &str| {
+        format!(
+            "{}{}\n",
+            apply_line_number_color(&format_line_num(line_num), is_novel, side, display_options),
+            style::replace_tabs(content, display_options.tab_width)
+        )
+    };
+
+    let mut res = Vec::with_capacity(hunks.len());
+    for (i, hunk) in hunks.iter().enumerate() {
+        let mut lines = vec![format!(
+            "{}\n",
+            style::header(
+                lhs_display_path,
+                rhs_display_path,
+                i + 1,
+                hunks.len(),
+                display_language,
+                display_options
+            )
+        )];
+
+        let (start_i, end_i) = matched_lines_indexes_for_hunk(
+            matched_lines_to_print,
+            hunk,
+            display_options.num_context_lines as usize,
+        );
+        let aligned_lines = &matched_lines_to_print[start_i..end_i];
+        matched_lines_to_print = &matched_lines_to_print[start_i..];
+
+        for (lhs_line, rhs_line) in aligned_lines {
+            match (lhs_line, rhs_line) {
+                (Some(lhs_line), Some(rhs_line)) => {
+                    let lhs_line_ranges = &lhs_ranges[lhs_line.as_usize()];
+                    let rhs_line_ranges = &rhs_ranges[rhs_line.as_usize()];
+                    let is_novel = !lhs_line_ranges.is_empty() || !rhs_line_ranges.is_empty();
+                    let content = interleave_line(
+                        lhs_lines[lhs_line.as_usize()],
+                        rhs_lines[rhs_line.as_usize()],
+                        lhs_line_ranges,
+                        rhs_line_ranges,
+                        anchors
+                            .get(&(*lhs_line, *rhs_line))
+                            .map(|anchors| &anchors[..])
+                            .unwrap_or(&[]),
+                        display_options,
+                    );
+                    // Without colour, a gutter marker would suggest
+                    // the whole line was added.
+                    lines.push(format_line(
+                        *rhs_line,
+                        is_novel && display_options.use_color,
+                        Side::Right,
+                        &content,
+                    ));
+                }
+                (Some(lhs_line), None) => {
+                    let ranges = &lhs_ranges[lhs_line.as_usize()];
+                    let content = mark_line(
+                        lhs_lines[lhs_line.as_usize()],
+                        ranges,
+                        Side::Left,
+                        display_options,
+                    );
+                    lines.push(format_line(
+                        *lhs_line,
+                        !ranges.is_empty(),
+                        Side::Left,
+                        &content,
+                    ));
+                }
+                (None, Some(rhs_line)) => {
+                    let ranges = &rhs_ranges[rhs_line.as_usize()];
+                    let content = mark_line(
+                        rhs_lines[rhs_line.as_usize()],
+                        ranges,
+                        Side::Right,
+                        display_options,
+                    );
+                    lines.push(format_line(
+                        *rhs_line,
+                        !ranges.is_empty(),
+                        Side::Right,
+                        &content,
+                    ));
+                }
+                (None, None) => {}
+            }
+        }
+
+        lines.push("\n".into());
+        res.push(lines);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_interleave_line() {
+        let display_options = DisplayOptions {
+            use_color: false,
+            ..DisplayOptions::default()
+        };
+
+        // `foo(a, c)` -> `foo(b, c)`, where `foo`, `(`, `,`, `c`
+        // and `)` are unchanged.
+        let anchors = [
+            (0, 3, 0, 3),
+            (3, 4, 3, 4),
+            (5, 6, 5, 6),
+            (7, 8, 7, 8),
+            (8, 9, 8, 9),
+        ];
+        assert_eq!(
+            interleave_line(
+                "foo(a, c)",
+                "foo(b, c)",
+                &[(4, 5)],
+                &[(4, 5)],
+                &anchors,
+                &display_options
+            ),
+            "foo([-a-]{+b+}, c)"
+        );
+    }
+
+    #[test]
+    fn test_interleave_line_no_anchors() {
+        let display_options = DisplayOptions {
+            use_color: false,
+            ..DisplayOptions::default()
+        };
+
+        assert_eq!(
+            interleave_line("  x", "  y;", &[(2, 3)], &[(2, 4)], &[], &display_options),
+            "  [-x-]{+y;+}"
+        );
+    }
+}
@@ -102,6 +102,17 @@ pub fn read_or_die(path: &Path) -> Vec<u8> {
     }
 }
 
+/// Read the target of the symlink at `path`, without following it.
+pub fn read_link_or_die(path: &Path) -> Vec<u8> {
+    match fs::read_link(path) {
+        Ok(target) => target.to_string_lossy().as_bytes().to_vec(),
+        Err(e) => {
+            eprint_read_error(&FileArgument::NamedPath(path.to_path_buf()), &e);
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    }
+}
+
 /// Group bytes into u16 values for conversion to UTF-16, respecting
 /// the byte order mark if present.
 fn u16_from_bytes(bytes: &[u8]) -> Vec<u16> {
@@ -128,10 +139,30 @@ fn u16_from_bytes(bytes: &[u8]) -> Vec<u16> {
 pub enum ProbableFileKind {
     Text(String),
     Binary,
+    /// A tar archive, whose members can be diffed individually.
+    Tar,
+}
+
+/// Does `bytes` look like a tar archive?
+pub fn is_tar(bytes: &[u8]) -> bool {
+    // tar headers are 512 bytes, and ustar and GNU archives have a
+    // magic string at offset 257. Check for it first, as
+    // tree_magic_mini is slow.
+    if bytes.len() < 512 || &bytes[257..262] != b"ustar" {
+        return false;
+    }
+
+    tree_magic_mini::from_u8(&bytes[..512]) == "application/x-tar"
 }
 
 /// Do these bytes look like a binary (non-textual) format?
 pub fn guess_content(bytes: &[u8]) -> ProbableFileKind {
+    // Archives of text files are often valid UTF-8, so check for
+    // them first.
+    if is_tar(bytes) {
+        return ProbableFileKind::Tar;
+    }
+
     // If the bytes are entirely valid UTF-8, treat them as a string.
     if let Ok(valid_utf8_string) = std::str::from_utf8(bytes) {
         return ProbableFileKind::Text(valid_utf8_string.to_string());
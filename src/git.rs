@@ -0,0 +1,333 @@
+//! Reading changed files directly from a git repository.
+//!
+//! This calls the local `git` binary, so users can compare revisions
+//! without configuring difftastic as an external diff tool.
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::exit_codes::EXIT_BAD_ARGUMENTS;
+
+/// The kinds of file that git tracks, distinguished by their mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    Regular,
+    Executable,
+    Symlink,
+    /// A gitlink, which records the commit of a submodule.
+    Submodule,
+}
+
+impl FileMode {
+    /// Parse the octal mode that git reports, e.g. `100644`. Returns
+    /// `None` for modes that mean the file doesn't exist on this side
+    /// (`000000`, or `.` in GIT_EXTERNAL_DIFF arguments).
+    pub fn from_git(mode: &str) -> Option<Self> {
+        match mode {
+            "100644" | "100664" => Some(FileMode::Regular),
+            "100755" => Some(FileMode::Executable),
+            "120000" => Some(FileMode::Symlink),
+            "160000" => Some(FileMode::Submodule),
+            _ => None,
+        }
+    }
+
+    pub fn as_git(self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Submodule => "160000",
+        }
+    }
+}
+
+/// An object git has recorded for a path: its hash and its mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitEntry {
+    /// The object hash. For submodules this is a commit hash rather
+    /// than a blob hash. This is all zeroes for files in the working
+    /// tree that git hasn't hashed.
+    pub hash: String,
+    pub mode: FileMode,
+}
+
+impl GitEntry {
+    /// Build an entry from the hash and mode that git reported, or
+    /// `None` if the file does not exist on this side.
+    pub fn from_git(hash: &str, mode: &str) -> Option<Self> {
+        FileMode::from_git(mode).map(|mode| GitEntry {
+            hash: hash.to_owned(),
+            mode,
+        })
+    }
+
+    /// Whether git has not computed a hash for this entry.
+    pub fn is_unhashed(&self) -> bool {
+        self.hash.chars().all(|c| c == '0')
+    }
+}
+
+/// A file that differs between two revisions, as reported by `git
+/// diff --raw`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// The path before the change, relative to the repository root.
+    pub lhs_path: String,
+    /// The path after the change. This only differs from `lhs_path`
+    /// when git detected a rename or a copy.
+    pub rhs_path: String,
+    /// The object before the change, or `None` if the file was added.
+    pub lhs: Option<GitEntry>,
+    /// The object after the change, or `None` if the file was deleted.
+    pub rhs: Option<GitEntry>,
+}
+
+/// Run git with `args` and return its stdout. Terminate the process
+/// if git cannot be run or reports an error.
+fn run_git_or_die(args: &[OsString]) -> Vec<u8> {
+    info!("Running git with arguments: {:?}", args);
+
+    let output = Command::new("git")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            eprintln!(
+                "git {} failed ({})",
+                args.iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                output.status
+            );
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+        Err(e) => {
+            eprintln!("Could not run git: {}", e);
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    }
+}
+
+/// The absolute path of the top level of the current git repository.
+pub fn repo_root() -> PathBuf {
+    let stdout = run_git_or_die(&["rev-parse".into(), "--show-toplevel".into()]);
+    PathBuf::from(String::from_utf8_lossy(&stdout).trim_end_matches('\n'))
+}
+
+/// All the files that differ between `lhs_rev` and `rhs_rev`. If
+/// `rhs_rev` is `None`, compare against the working tree.
+///
+/// Only paths matching `pathspecs` are included, if any are given.
+pub fn changed_files(
+    lhs_rev: &str,
+    rhs_rev: Option<&str>,
+    pathspecs: &[OsString],
+) -> Vec<ChangedFile> {
+    let mut revs = vec![lhs_rev];
+    if let Some(rhs_rev) = rhs_rev {
+        revs.push(rhs_rev);
+    }
+    raw_diff(&revs, pathspecs)
+}
+
+/// All the files in the working tree that differ from the index.
+pub fn unstaged_files(pathspecs: &[OsString]) -> Vec<ChangedFile> {
+    raw_diff(&[], pathspecs)
+}
+
+fn raw_diff(revs: &[&str], pathspecs: &[OsString]) -> Vec<ChangedFile> {
+    let mut args: Vec<OsString> = vec![
+        "diff".into(),
+        "--raw".into(),
+        "-z".into(),
+        "-M".into(),
+        "--no-abbrev".into(),
+    ];
+    args.extend(revs.iter().map(|rev| rev.into()));
+    args.push("--".into());
+    args.extend(pathspecs.iter().cloned());
+
+    parse_raw_diff(&run_git_or_die(&args))
+}
+
+/// Read the contents of the blob with this hash.
+pub fn read_blob(hash: &str) -> Vec<u8> {
+    run_git_or_die(&["cat-file".into(), "blob".into(), hash.into()])
+}
+
+/// Apply `patch` to the index of the repository at `repo_root`,
+/// leaving the working tree untouched. The patch may have hunks
+/// without context lines.
+pub fn apply_to_index(repo_root: &Path, patch: &str) {
+    let args: Vec<OsString> = vec![
+        "-C".into(),
+        repo_root.into(),
+        "apply".into(),
+        "--cached".into(),
+        "--unidiff-zero".into(),
+        // Staging a change to CRLF line endings is intentional.
+        "--whitespace=nowarn".into(),
+        "-".into(),
+    ];
+    info!("Running git with arguments: {:?}", args);
+
+    let child = Command::new("git")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Could not run git: {}", e);
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(patch.as_bytes()) {
+            eprintln!("Could not write patch to git: {}", e);
+        }
+    }
+
+    match child.wait() {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("git apply failed ({})", status);
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+        Err(e) => {
+            eprintln!("Could not run git: {}", e);
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    }
+}
+
+/// The abbreviated form of `hash`, as shown by `git log --oneline`.
+pub fn short_hash(hash: &str) -> &str {
+    &hash[..std::cmp::min(hash.len(), 7)]
+}
+
+/// The commit currently checked out in the submodule at `path`.
+pub fn submodule_head(path: &Path) -> String {
+    let stdout = run_git_or_die(&["-C".into(), path.into(), "rev-parse".into(), "HEAD".into()]);
+    String::from_utf8_lossy(&stdout)
+        .trim_end_matches('\n')
+        .to_owned()
+}
+
+/// Parse the output of `git diff --raw -z`.
+///
+/// Each change is a metadata field followed by one path, or two
+/// paths for renames and copies:
+///
+/// ```text
+/// :100644 100644 abc123... def456... M\0foo.rs\0
+/// :100644 100644 abc123... abc123... R100\0old.rs\0new.rs\0
+/// ```
+fn parse_raw_diff(output: &[u8]) -> Vec<ChangedFile> {
+    let output = String::from_utf8_lossy(output);
+    let mut fields = output.split('\0').filter(|field| !field.is_empty());
+
+    let mut res = vec![];
+    while let Some(metadata) = fields.next() {
+        let parts: Vec<&str> = metadata.trim_start_matches(':').split(' ').collect();
+        let (lhs, rhs, status) = match parts[..] {
+            [lhs_mode, rhs_mode, lhs_hash, rhs_hash, status] => (
+                GitEntry::from_git(lhs_hash, lhs_mode),
+                GitEntry::from_git(rhs_hash, rhs_mode),
+                status,
+            ),
+            _ => {
+                warn!("Unexpected git diff --raw output: {:?}", metadata);
+                break;
+            }
+        };
+        let status = status.chars().next().unwrap_or('M');
+
+        let lhs_path = match fields.next() {
+            Some(path) => path.to_owned(),
+            None => break,
+        };
+        let rhs_path = if status == 'R' || status == 'C' {
+            match fields.next() {
+                Some(path) => path.to_owned(),
+                None => break,
+            }
+        } else {
+            lhs_path.clone()
+        };
+
+        res.push(ChangedFile {
+            lhs_path,
+            rhs_path,
+            lhs,
+            rhs,
+        });
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_raw_diff_modified() {
+        let output = b":100644 100644 78981922613b2afb6025042ff6bd878ac1994e85 61780798228d17af2d34fce4cfbdf35556832472 M\0a.txt\0";
+        assert_eq!(
+            parse_raw_diff(output),
+            vec![ChangedFile {
+                lhs_path: "a.txt".into(),
+                rhs_path: "a.txt".into(),
+                lhs: Some(GitEntry {
+                    hash: "78981922613b2afb6025042ff6bd878ac1994e85".into(),
+                    mode: FileMode::Regular,
+                }),
+                rhs: Some(GitEntry {
+                    hash: "61780798228d17af2d34fce4cfbdf35556832472".into(),
+                    mode: FileMode::Regular,
+                }),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_raw_diff_rename_and_add() {
+        let output = b":100644 100644 587be6b4c3f93f93c489c0111bba5596147a26cb 587be6b4c3f93f93c489c0111bba5596147a26cb R100\0b.txt\0c.txt\0:000000 100644 0000000000000000000000000000000000000000 8ba3a16384aacc37d01564b28401755ce8053f51 A\0new.txt\0";
+        let res = parse_raw_diff(output);
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].lhs_path, "b.txt");
+        assert_eq!(res[0].rhs_path, "c.txt");
+        assert!(res[0].lhs.is_some());
+        assert_eq!(res[1].rhs_path, "new.txt");
+        assert_eq!(res[1].lhs, None);
+    }
+
+    #[test]
+    fn test_parse_raw_diff_modes() {
+        let output = b":100644 100755 61780798228d17af2d34fce4cfbdf35556832472 61780798228d17af2d34fce4cfbdf35556832472 M\0run.sh\0:160000 160000 beab5d745d1b5dd183fe39578cd4fbcf2b101585 0000000000000000000000000000000000000000 M\0sub\0";
+        let res = parse_raw_diff(output);
+
+        assert_eq!(res[0].lhs.as_ref().unwrap().mode, FileMode::Regular);
+        assert_eq!(res[0].rhs.as_ref().unwrap().mode, FileMode::Executable);
+
+        let submodule = res[1].rhs.as_ref().unwrap();
+        assert_eq!(submodule.mode, FileMode::Submodule);
+        assert!(submodule.is_unhashed());
+    }
+}
@@ -23,32 +23,43 @@
 // correct.
 #![allow(clippy::mutable_key_type)]
 
+mod conflicts;
 mod constants;
 mod diff;
 mod display;
 mod exit_codes;
 mod files;
+mod git;
 mod line_parser;
 mod lines;
 mod options;
 mod parse;
 mod positions;
+mod stage;
 mod summary;
+mod tar;
 
 #[macro_use]
 extern crate log;
 
-use crate::diff::{dijkstra, unchanged};
+use crate::diff::{byte_diff, dependency_changes, dijkstra, renames, table_changes, unchanged};
 use crate::display::hunks::{matched_pos_to_hunks, merge_adjacent};
+use crate::parse::csv;
 use crate::parse::guess_language::{LANG_EXTENSIONS, LANG_FILE_NAMES};
+use crate::parse::ini;
+use crate::parse::lockfile;
+use crate::parse::notebook;
 use crate::parse::syntax;
+use crate::parse::syntax::{MatchedPos, Syntax};
 use diff::changes::ChangeMap;
 use diff::dijkstra::ExceededGraphLimit;
 use display::context::opposite_positions;
 use exit_codes::{EXIT_FOUND_CHANGES, EXIT_SUCCESS};
 use files::{
-    guess_content, read_files_or_die, read_or_die, relative_paths_in_either, ProbableFileKind,
+    guess_content, is_tar, read_files_or_die, read_link_or_die, read_or_die,
+    relative_paths_in_either, ProbableFileKind,
 };
+use git::{FileMode, GitEntry};
 use log::info;
 use mimalloc::MiMalloc;
 use parse::guess_language::{guess, language_name};
@@ -61,13 +72,13 @@ use parse::guess_language::{guess, language_name};
 static GLOBAL: MiMalloc = MiMalloc;
 
 use diff::sliders::fix_all_sliders;
-use options::{DiffOptions, DisplayMode, DisplayOptions, FileArgument, Mode};
+use options::{BinaryDisplay, DiffOptions, DisplayMode, DisplayOptions, FileArgument, Mode};
 use owo_colors::OwoColorize;
 use rayon::prelude::*;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::{env, path::Path};
-use summary::{DiffResult, FileContent};
+use summary::{DiffResult, FileContent, LineEndings};
 use syntax::init_next_prev;
 use typed_arena::Arena;
 
@@ -106,13 +117,12 @@ fn main() {
             let src = String::from_utf8_lossy(&bytes).to_string();
 
             let language = language_override.or_else(|| guess(path, &src));
-            match language {
-                Some(lang) => {
-                    let ts_lang = tsp::from_language(lang);
+            match language.and_then(tsp::from_language) {
+                Some(ts_lang) => {
                     let tree = tsp::to_tree(&src, &ts_lang);
                     tsp::print_tree(&src, &tree);
                 }
-                None => {
+                _ => {
                     eprintln!("No tree-sitter parser for file: {:?}", path);
                 }
             }
@@ -127,15 +137,20 @@ fn main() {
             let src = String::from_utf8_lossy(&bytes).to_string();
 
             let language = language_override.or_else(|| guess(path, &src));
-            match language {
-                Some(lang) => {
-                    let ts_lang = tsp::from_language(lang);
+            match (language, language.and_then(tsp::from_language)) {
+                (Some(parse::guess_language::Language::Ini), _) => {
+                    let arena = Arena::new();
+                    let ast = ini::parse(&arena, &src);
+                    init_all_info(&ast, &[]);
+                    println!("{:#?}", ast);
+                }
+                (_, Some(ts_lang)) => {
                     let arena = Arena::new();
                     let ast = tsp::parse(&arena, &src, &ts_lang, ignore_comments);
                     init_all_info(&ast, &[]);
                     println!("{:#?}", ast);
                 }
-                None => {
+                _ => {
                     eprintln!("No tree-sitter parser for file: {:?}", path);
                 }
             }
@@ -178,6 +193,8 @@ fn main() {
             rhs_path,
             lhs_display_path,
             rhs_display_path,
+            lhs_git_entry,
+            rhs_git_entry,
         } => {
             if lhs_path == rhs_path {
                 let is_dir = match &lhs_path {
@@ -197,54 +214,80 @@ fn main() {
                     options::FileArgument::NamedPath(lhs_path),
                     options::FileArgument::NamedPath(rhs_path),
                 ) if lhs_path.is_dir() && rhs_path.is_dir() => {
-                    // We want to diff files in the directory in
-                    // parallel, but print the results serially (to
-                    // prevent display interleaving).
-                    // https://github.com/rayon-rs/rayon/issues/210#issuecomment-551319338
-                    let (send, recv) = std::sync::mpsc::sync_channel(1);
-
-                    let encountered_changes = encountered_changes.clone();
-                    let print_options = display_options.clone();
-
-                    let printing_thread = std::thread::spawn(move || {
-                        for diff_result in recv.into_iter() {
-                            print_diff_result(&print_options, &diff_result);
+                    print_diff_results(
+                        diff_directories(
+                            lhs_path,
+                            rhs_path,
+                            &display_options,
+                            &diff_options,
+                            language_override,
+                        ),
+                        &display_options,
+                        &encountered_changes,
+                    );
+                }
+                _ => {
+                    let (lhs_bytes, rhs_bytes) =
+                        read_files_or_die(&lhs_path, &rhs_path, missing_as_empty);
+
+                    // Diff the members of tar archives, as if they
+                    // were directories.
+                    let archive_results = if is_tar(&lhs_bytes) && is_tar(&rhs_bytes) {
+                        match tar::diff_archives(
+                            &lhs_bytes,
+                            &rhs_bytes,
+                            &display_options,
+                            &diff_options,
+                            language_override,
+                        ) {
+                            Ok(diff_results) => Some(diff_results),
+                            Err(e) => {
+                                eprintln!(
+                                    "warning: Could not read tar archive, diffing as binary: {}\n",
+                                    e
+                                );
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
+                    match archive_results {
+                        Some(diff_results) => {
+                            print_diff_results(
+                                diff_results,
+                                &display_options,
+                                &encountered_changes,
+                            );
+                        }
+                        None => {
+                            let diff_result = diff_git_file_content(
+                                &lhs_display_path,
+                                &rhs_display_path,
+                                &lhs_path,
+                                &rhs_path,
+                                lhs_git_entry.as_ref(),
+                                rhs_git_entry.as_ref(),
+                                &lhs_bytes,
+                                &rhs_bytes,
+                                &display_options,
+                                &diff_options,
+                                language_override,
+                            );
+                            if display_options.interactive {
+                                view_diff_results(
+                                    std::slice::from_ref(&diff_result),
+                                    &display_options,
+                                );
+                            } else {
+                                print_diff_result(&display_options, &diff_result);
+                            }
 
                             if diff_result.has_reportable_change() {
                                 encountered_changes.store(true, Ordering::Relaxed);
                             }
                         }
-                    });
-
-                    diff_directories(
-                        lhs_path,
-                        rhs_path,
-                        &display_options,
-                        &diff_options,
-                        language_override,
-                    )
-                    .try_for_each_with(send, |s, diff_result| s.send(diff_result))
-                    .expect("Receiver should be connected");
-
-                    printing_thread
-                        .join()
-                        .expect("Printing thread should not panic");
-                }
-                _ => {
-                    let diff_result = diff_file(
-                        &lhs_display_path,
-                        &rhs_display_path,
-                        &lhs_path,
-                        &rhs_path,
-                        &display_options,
-                        &diff_options,
-                        missing_as_empty,
-                        language_override,
-                    );
-                    print_diff_result(&display_options, &diff_result);
-
-                    if diff_result.has_reportable_change() {
-                        encountered_changes.store(true, Ordering::Relaxed);
                     }
                 }
             }
@@ -256,26 +299,133 @@ fn main() {
             };
             std::process::exit(exit_code);
         }
+        Mode::DiffFromGit {
+            diff_options,
+            display_options,
+            set_exit_code,
+            language_override,
+            lhs_rev,
+            rhs_rev,
+            pathspecs,
+        } => {
+            let changed_files = git::changed_files(&lhs_rev, rhs_rev.as_deref(), &pathspecs);
+
+            let encountered_changes = Arc::new(AtomicBool::new(false));
+            print_diff_results(
+                diff_git_revisions(
+                    changed_files,
+                    rhs_rev.is_none(),
+                    &display_options,
+                    &diff_options,
+                    language_override,
+                ),
+                &display_options,
+                &encountered_changes,
+            );
+
+            let exit_code = if set_exit_code && encountered_changes.load(Ordering::Relaxed) {
+                EXIT_FOUND_CHANGES
+            } else {
+                EXIT_SUCCESS
+            };
+            std::process::exit(exit_code);
+        }
+        Mode::Stage {
+            diff_options,
+            display_options,
+            language_override,
+            pathspecs,
+        } => {
+            stage::stage_interactively(
+                &display_options,
+                &diff_options,
+                language_override,
+                &pathspecs,
+            );
+        }
+        Mode::Conflicts {
+            diff_options,
+            display_options,
+            language_override,
+            path,
+        } => {
+            conflicts::show_conflicts(&path, &display_options, &diff_options, language_override);
+        }
     };
 }
 
+/// Print every diff result in `diff_results`, and record whether any
+/// of them had changes.
+fn print_diff_results(
+    diff_results: impl ParallelIterator<Item = DiffResult>,
+    display_options: &DisplayOptions,
+    encountered_changes: &Arc<AtomicBool>,
+) {
+    if display_options.interactive {
+        // The viewer needs every result before it can start.
+        let diff_results: Vec<DiffResult> = diff_results.collect();
+        if diff_results.iter().any(|r| r.has_reportable_change()) {
+            encountered_changes.store(true, Ordering::Relaxed);
+        }
+        view_diff_results(&diff_results, display_options);
+        return;
+    }
+
+    // We want to diff files in parallel, but print the results
+    // serially (to prevent display interleaving).
+    // https://github.com/rayon-rs/rayon/issues/210#issuecomment-551319338
+    let (send, recv) = std::sync::mpsc::sync_channel(1);
+
+    let encountered_changes = encountered_changes.clone();
+    let print_options = display_options.clone();
+
+    let printing_thread = std::thread::spawn(move || {
+        for diff_result in recv.into_iter() {
+            print_diff_result(&print_options, &diff_result);
+
+            if diff_result.has_reportable_change() {
+                encountered_changes.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+
+    diff_results
+        .try_for_each_with(send, |s, diff_result| s.send(diff_result))
+        .expect("Receiver should be connected");
+
+    printing_thread
+        .join()
+        .expect("Printing thread should not panic");
+}
+
+/// Show `diff_results` in the full-screen viewer.
+fn view_diff_results(diff_results: &[DiffResult], display_options: &DisplayOptions) {
+    if let Err(e) = display::interactive::run(diff_results, display_options) {
+        eprintln!("Could not run the interactive viewer: {}", e);
+    }
+}
+
 /// Print a diff between two files.
 fn diff_file(
     lhs_display_path: &str,
     rhs_display_path: &str,
     lhs_path: &FileArgument,
     rhs_path: &FileArgument,
+    lhs_git_entry: Option<&GitEntry>,
+    rhs_git_entry: Option<&GitEntry>,
     display_options: &DisplayOptions,
     diff_options: &DiffOptions,
     missing_as_empty: bool,
     language_override: Option<parse::guess_language::Language>,
 ) -> DiffResult {
     let (lhs_bytes, rhs_bytes) = read_files_or_die(lhs_path, rhs_path, missing_as_empty);
-    diff_file_content(
+    diff_git_file_content(
         lhs_display_path,
         rhs_display_path,
         lhs_path,
         rhs_path,
+        lhs_git_entry,
+        rhs_git_entry,
         &lhs_bytes,
         &rhs_bytes,
         display_options,
@@ -284,6 +434,74 @@ fn diff_file(
     )
 }
 
+/// Diff two files, taking into account the modes that git reported
+/// for them. Submodules and symlinks are compared by the commit or
+/// path they point to, rather than by their content.
+fn diff_git_file_content(
+    lhs_display_path: &str,
+    rhs_display_path: &str,
+    lhs_path: &FileArgument,
+    rhs_path: &FileArgument,
+    lhs_git_entry: Option<&GitEntry>,
+    rhs_git_entry: Option<&GitEntry>,
+    lhs_bytes: &[u8],
+    rhs_bytes: &[u8],
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<parse::guess_language::Language>,
+) -> DiffResult {
+    let lhs_mode = lhs_git_entry.map(|entry| entry.mode);
+    let rhs_mode = rhs_git_entry.map(|entry| entry.mode);
+
+    let special_contents = match (lhs_mode, rhs_mode) {
+        (Some(FileMode::Submodule), Some(FileMode::Submodule))
+        | (Some(FileMode::Submodule), None)
+        | (None, Some(FileMode::Submodule)) => Some((
+            FileContent::Submodule(lhs_git_entry.map(|entry| entry.hash.clone())),
+            FileContent::Submodule(rhs_git_entry.map(|entry| entry.hash.clone())),
+        )),
+        (Some(FileMode::Symlink), Some(FileMode::Symlink)) => Some((
+            FileContent::Symlink(String::from_utf8_lossy(lhs_bytes).into_owned()),
+            FileContent::Symlink(String::from_utf8_lossy(rhs_bytes).into_owned()),
+        )),
+        (Some(FileMode::Symlink), None) => Some((
+            FileContent::Symlink(String::from_utf8_lossy(lhs_bytes).into_owned()),
+            FileContent::Text("".into()),
+        )),
+        (None, Some(FileMode::Symlink)) => Some((
+            FileContent::Text("".into()),
+            FileContent::Symlink(String::from_utf8_lossy(rhs_bytes).into_owned()),
+        )),
+        _ => None,
+    };
+    if let Some((lhs_src, rhs_src)) = special_contents {
+        let has_changes = lhs_src != rhs_src;
+        return DiffResult {
+            has_byte_changes: has_changes,
+            has_syntactic_changes: has_changes,
+            ..DiffResult::new(lhs_display_path, rhs_display_path, lhs_src, rhs_src)
+        };
+    }
+
+    let mut diff_result = diff_file_content(
+        lhs_display_path,
+        rhs_display_path,
+        lhs_path,
+        rhs_path,
+        lhs_bytes,
+        rhs_bytes,
+        display_options,
+        diff_options,
+        language_override,
+    );
+    if let (Some(lhs_mode), Some(rhs_mode)) = (lhs_mode, rhs_mode) {
+        if lhs_mode != rhs_mode {
+            diff_result.mode_change = Some((lhs_mode, rhs_mode));
+        }
+    }
+    diff_result
+}
+
 fn diff_file_content(
     lhs_display_path: &str,
     rhs_display_path: &str,
@@ -296,27 +514,53 @@ fn diff_file_content(
     language_override: Option<parse::guess_language::Language>,
 ) -> DiffResult {
     let (mut lhs_src, mut rhs_src) = match (guess_content(lhs_bytes), guess_content(rhs_bytes)) {
-        (ProbableFileKind::Binary, _) | (_, ProbableFileKind::Binary) => {
+        (ProbableFileKind::Binary | ProbableFileKind::Tar, _)
+        | (_, ProbableFileKind::Binary | ProbableFileKind::Tar) => {
+            let has_byte_changes = lhs_bytes != rhs_bytes;
+            let byte_changes =
+                if has_byte_changes && display_options.binary_display == BinaryDisplay::Hex {
+                    byte_diff::byte_changes(lhs_bytes, rhs_bytes)
+                } else {
+                    None
+                };
+
+            // Only keep the bytes if we're showing them.
+            let keep_bytes = byte_changes.is_some();
+            let binary_content = |bytes: &[u8]| FileContent::Binary {
+                len: bytes.len(),
+                bytes: if keep_bytes {
+                    Some(bytes.to_vec())
+                } else {
+                    None
+                },
+            };
+
             return DiffResult {
-                lhs_display_path: lhs_display_path.into(),
-                rhs_display_path: rhs_display_path.into(),
-                display_language: None,
-                language_used: None,
-                lhs_src: FileContent::Binary,
-                rhs_src: FileContent::Binary,
-                lhs_positions: vec![],
-                rhs_positions: vec![],
-                hunks: vec![],
-                has_byte_changes: lhs_bytes != rhs_bytes,
-                has_syntactic_changes: false,
+                has_byte_changes,
+                byte_changes: byte_changes.unwrap_or_default(),
+                ..DiffResult::new(
+                    lhs_display_path,
+                    rhs_display_path,
+                    binary_content(lhs_bytes),
+                    binary_content(rhs_bytes),
+                )
             };
         }
         (ProbableFileKind::Text(lhs_src), ProbableFileKind::Text(rhs_src)) => (lhs_src, rhs_src),
     };
 
-    // Ignore the trailing newline, if present.
-    // TODO: highlight if this has changes (#144).
     // TODO: factor out a string cleaning function.
+    if diff_options.strip_cr {
+        lhs_src = lhs_src.replace("\r\n", "\n");
+        rhs_src = rhs_src.replace("\r\n", "\n");
+    }
+    let line_endings = Some((
+        LineEndings::from_src(&lhs_src),
+        LineEndings::from_src(&rhs_src),
+    ));
+
+    // Ignore the trailing newline, if present. Changes to it are
+    // reported separately from the diff.
     if lhs_src.ends_with('\n') {
         lhs_src.pop();
     }
@@ -330,41 +574,163 @@ fn diff_file_content(
         FileArgument::DevNull => (&lhs_src, Path::new(&lhs_display_path)),
     };
 
-    let language = language_override.or_else(|| guess(guess_path, guess_src));
-    let lang_config = language.map(tsp::from_language);
+    let mut language = language_override.or_else(|| guess(guess_path, guess_src));
+
+    // Diff the cells of Jupyter notebooks rather than their JSON.
+    let mut is_notebook = false;
+    if language_override.is_none() && guess_path.extension().map_or(false, |ext| ext == "ipynb") {
+        let include_outputs = !diff_options.ignore_notebook_outputs;
+        if let (Some(lhs_notebook), Some(rhs_notebook)) = (
+            notebook::render(&lhs_src, include_outputs),
+            notebook::render(&rhs_src, include_outputs),
+        ) {
+            language = rhs_notebook.language.or(lhs_notebook.language);
+            lhs_src = lhs_notebook.src;
+            rhs_src = rhs_notebook.src;
+            is_notebook = true;
+        }
+    }
+
+    // CSV and TSV files are diffed by cell, using our own parser.
+    let table_delimiter = if language_override.is_none() && !is_notebook {
+        csv::delimiter_for_path(guess_path)
+    } else {
+        None
+    };
+
+    // Summarise the dependencies that changed in lockfiles and
+    // manifests. Lockfiles are generated, so we only show the summary
+    // rather than diffing their content.
+    let mut dependency_changes = vec![];
+    if language_override.is_none() {
+        if let (Some(lhs_dependencies), Some(rhs_dependencies)) = (
+            lockfile::dependencies(guess_path, &lhs_src),
+            lockfile::dependencies(guess_path, &rhs_src),
+        ) {
+            dependency_changes =
+                dependency_changes::find_dependency_changes(&lhs_dependencies, &rhs_dependencies);
+        }
+    }
+    if !dependency_changes.is_empty()
+        && lockfile::dependency_file_kind(guess_path)
+            == Some(lockfile::DependencyFileKind::Lockfile)
+    {
+        return DiffResult {
+            display_language: Some("Dependencies".into()),
+            has_byte_changes: true,
+            line_endings,
+            dependency_changes,
+            ..DiffResult::new(
+                lhs_display_path,
+                rhs_display_path,
+                FileContent::Text(lhs_src),
+                FileContent::Text(rhs_src),
+            )
+        };
+    }
+
+    let lang_config = language.and_then(tsp::from_language);
 
     if lhs_bytes == rhs_bytes {
         // If the two files are completely identical, return early
         // rather than doing any more work.
         return DiffResult {
-            lhs_display_path: lhs_display_path.into(),
-            rhs_display_path: rhs_display_path.into(),
             display_language: language.map(|l| language_name(l).into()),
             language_used: language,
-            lhs_src: FileContent::Text("".into()),
-            rhs_src: FileContent::Text("".into()),
-            lhs_positions: vec![],
-            rhs_positions: vec![],
-            hunks: vec![],
-            has_byte_changes: false,
-            has_syntactic_changes: false,
+            line_endings,
+            ..DiffResult::new(
+                lhs_display_path,
+                rhs_display_path,
+                FileContent::Text("".into()),
+                FileContent::Text("".into()),
+            )
         };
     }
 
     let mut language_used = None;
-    let (lang_name, lhs_positions, rhs_positions) = match lang_config {
+    let mut renames = vec![];
+    let mut table_changes = vec![];
+    let (mut lang_name, mut lhs_positions, mut rhs_positions) = match (lang_config, table_delimiter)
+    {
         _ if lhs_bytes.len() > diff_options.byte_limit
             || rhs_bytes.len() > diff_options.byte_limit =>
         {
-            let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
-            let rhs_positions = line_parser::change_positions(&rhs_src, &lhs_src);
-            (
-                Some("Text (exceeded DFT_BYTE_LIMIT)".into()),
-                lhs_positions,
-                rhs_positions,
-            )
+            text_fallback(&lhs_src, &rhs_src, "DFT_BYTE_LIMIT")
+        }
+        (_, Some(delimiter)) => {
+            let lang_name = Some(csv::format_name(delimiter).into());
+
+            if diff_options.check_only {
+                let arena = Arena::new();
+                let lhs = csv::to_syntax(&arena, &lhs_src, &csv::parse_rows(&lhs_src, delimiter));
+                let rhs = csv::to_syntax(&arena, &rhs_src, &csv::parse_rows(&rhs_src, delimiter));
+                init_all_info(&lhs, &rhs);
+                let has_syntactic_changes = lhs != rhs;
+
+                return DiffResult {
+                    display_language: lang_name,
+                    has_byte_changes: true,
+                    has_syntactic_changes,
+                    line_endings,
+                    ..DiffResult::new(
+                        lhs_display_path,
+                        rhs_display_path,
+                        FileContent::Text(lhs_src),
+                        FileContent::Text(rhs_src),
+                    )
+                };
+            }
+
+            match diff_tables(&lhs_src, &rhs_src, delimiter, diff_options.graph_limit) {
+                Ok((lhs_positions, rhs_positions, changes)) => {
+                    table_changes = changes;
+                    (lang_name, lhs_positions, rhs_positions)
+                }
+                Err(ExceededGraphLimit {}) => text_fallback(&lhs_src, &rhs_src, "DFT_GRAPH_LIMIT"),
+            }
+        }
+        (None, None) if language == Some(parse::guess_language::Language::Ini) => {
+            let arena = Arena::new();
+            let lhs = ini::parse(&arena, &lhs_src);
+            let rhs = ini::parse(&arena, &rhs_src);
+            init_all_info(&lhs, &rhs);
+
+            if diff_options.check_only {
+                let has_syntactic_changes = lhs != rhs;
+
+                return DiffResult {
+                    display_language: language.map(|l| language_name(l).into()),
+                    language_used: language,
+                    has_byte_changes: true,
+                    has_syntactic_changes,
+                    line_endings,
+                    ..DiffResult::new(
+                        lhs_display_path,
+                        rhs_display_path,
+                        FileContent::Text(lhs_src),
+                        FileContent::Text(rhs_src),
+                    )
+                };
+            }
+
+            let mut change_map = ChangeMap::default();
+            match mark_changes(&lhs, &rhs, &mut change_map, diff_options.graph_limit) {
+                Ok(()) => {
+                    language_used = language;
+                    let language = parse::guess_language::Language::Ini;
+                    fix_all_sliders(language, &lhs, &mut change_map);
+                    fix_all_sliders(language, &rhs, &mut change_map);
+
+                    (
+                        Some(language_name(language).into()),
+                        syntax::change_positions(&lhs, &change_map),
+                        syntax::change_positions(&rhs, &change_map),
+                    )
+                }
+                Err(ExceededGraphLimit {}) => text_fallback(&lhs_src, &rhs_src, "DFT_GRAPH_LIMIT"),
+            }
         }
-        Some(ts_lang) => {
+        (Some(ts_lang), None) => {
             let lhs_tree = tsp::to_tree(&lhs_src, &ts_lang);
             let rhs_tree = tsp::to_tree(&rhs_src, &ts_lang);
 
@@ -392,55 +758,26 @@ fn diff_file_content(
 
                 language_used = language;
                 return DiffResult {
-                    lhs_display_path: lhs_display_path.into(),
-                    rhs_display_path: rhs_display_path.into(),
                     display_language: lang_name,
                     language_used,
-                    lhs_src: FileContent::Text(lhs_src),
-                    rhs_src: FileContent::Text(rhs_src),
-                    lhs_positions: vec![],
-                    rhs_positions: vec![],
-                    hunks: vec![],
                     has_byte_changes: true,
                     has_syntactic_changes,
+                    line_endings,
+                    ..DiffResult::new(
+                        lhs_display_path,
+                        rhs_display_path,
+                        FileContent::Text(lhs_src),
+                        FileContent::Text(rhs_src),
+                    )
                 };
             }
 
             let mut change_map = ChangeMap::default();
-            let possibly_changed = if env::var("DFT_DBG_KEEP_UNCHANGED").is_ok() {
-                vec![(lhs.clone(), rhs.clone())]
-            } else {
-                unchanged::mark_unchanged(&lhs, &rhs, &mut change_map)
-            };
-
-            let mut exceeded_graph_limit = false;
-
-            for (lhs_section_nodes, rhs_section_nodes) in possibly_changed {
-                init_next_prev(&lhs_section_nodes);
-                init_next_prev(&rhs_section_nodes);
-
-                match mark_syntax(
-                    lhs_section_nodes.get(0).copied(),
-                    rhs_section_nodes.get(0).copied(),
-                    &mut change_map,
-                    diff_options.graph_limit,
-                ) {
-                    Ok(()) => {}
-                    Err(ExceededGraphLimit {}) => {
-                        exceeded_graph_limit = true;
-                        break;
-                    }
-                }
-            }
+            let exceeded_graph_limit =
+                mark_changes(&lhs, &rhs, &mut change_map, diff_options.graph_limit).is_err();
 
             if exceeded_graph_limit {
-                let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
-                let rhs_positions = line_parser::change_positions(&rhs_src, &lhs_src);
-                (
-                    Some("Text (exceeded DFT_GRAPH_LIMIT)".into()),
-                    lhs_positions,
-                    rhs_positions,
-                )
+                text_fallback(&lhs_src, &rhs_src, "DFT_GRAPH_LIMIT")
             } else {
                 language_used = language;
                 // TODO: Make this .expect() unnecessary.
@@ -452,6 +789,12 @@ fn diff_file_content(
                 let mut lhs_positions = syntax::change_positions(&lhs, &change_map);
                 let mut rhs_positions = syntax::change_positions(&rhs, &change_map);
 
+                let (file_renames, lhs_renamed, rhs_renamed) =
+                    renames::find_renames(&lhs, &rhs, &change_map);
+                renames::mark_renamed(&mut lhs_positions, &lhs_renamed);
+                renames::mark_renamed(&mut rhs_positions, &rhs_renamed);
+                renames = file_renames;
+
                 if diff_options.ignore_comments {
                     let lhs_comments = tsp::comment_positions(&lhs_tree, &lhs_src, &ts_lang);
                     lhs_positions.extend(lhs_comments);
@@ -467,13 +810,30 @@ fn diff_file_content(
                 )
             }
         }
-        None => {
+        (None, None) => {
             let lhs_positions = line_parser::change_positions(&lhs_src, &rhs_src);
             let rhs_positions = line_parser::change_positions(&rhs_src, &lhs_src);
             (None, lhs_positions, rhs_positions)
         }
     };
 
+    if is_notebook {
+        lang_name = Some(match lang_name {
+            Some(lang_name) => format!("Jupyter Notebook ({})", lang_name),
+            None => "Jupyter Notebook".into(),
+        });
+    }
+
+    if display_options.show_whitespace {
+        let (lhs_indentation, rhs_indentation) =
+            display::whitespace::indentation_changes(&lhs_src, &rhs_src, &lhs_positions);
+        lhs_positions.extend(lhs_indentation);
+        rhs_positions.extend(rhs_indentation);
+        // Styles are applied in order of position.
+        lhs_positions.sort_by_key(|mp| mp.pos);
+        rhs_positions.sort_by_key(|mp| mp.pos);
+    }
+
     let opposite_to_lhs = opposite_positions(&lhs_positions);
     let opposite_to_rhs = opposite_positions(&rhs_positions);
 
@@ -489,18 +849,100 @@ fn diff_file_content(
     let has_syntactic_changes = !hunks.is_empty();
 
     DiffResult {
-        lhs_display_path: lhs_display_path.into(),
-        rhs_display_path: rhs_display_path.into(),
         display_language: lang_name,
         language_used,
-        lhs_src: FileContent::Text(lhs_src),
-        rhs_src: FileContent::Text(rhs_src),
         lhs_positions,
         rhs_positions,
         hunks,
         has_byte_changes: true,
         has_syntactic_changes,
+        line_endings,
+        renames,
+        table_changes,
+        dependency_changes,
+        ..DiffResult::new(
+            lhs_display_path,
+            rhs_display_path,
+            FileContent::Text(lhs_src),
+            FileContent::Text(rhs_src),
+        )
+    }
+}
+
+/// Diff `lhs_src` and `rhs_src` line by line, because diffing them
+/// structurally would exceed the limit named `limit_name`.
+fn text_fallback(
+    lhs_src: &str,
+    rhs_src: &str,
+    limit_name: &str,
+) -> (Option<String>, Vec<MatchedPos>, Vec<MatchedPos>) {
+    (
+        Some(format!("Text (exceeded {})", limit_name)),
+        line_parser::change_positions(lhs_src, rhs_src),
+        line_parser::change_positions(rhs_src, lhs_src),
+    )
+}
+
+/// Find the changed nodes between `lhs` and `rhs`, storing the
+/// results in `change_map`.
+fn mark_changes<'a>(
+    lhs: &[&'a Syntax<'a>],
+    rhs: &[&'a Syntax<'a>],
+    change_map: &mut ChangeMap<'a>,
+    graph_limit: usize,
+) -> Result<(), ExceededGraphLimit> {
+    let possibly_changed = if env::var("DFT_DBG_KEEP_UNCHANGED").is_ok() {
+        vec![(lhs.to_vec(), rhs.to_vec())]
+    } else {
+        unchanged::mark_unchanged(lhs, rhs, change_map)
+    };
+
+    for (lhs_section_nodes, rhs_section_nodes) in possibly_changed {
+        init_next_prev(&lhs_section_nodes);
+        init_next_prev(&rhs_section_nodes);
+
+        mark_syntax(
+            lhs_section_nodes.get(0).copied(),
+            rhs_section_nodes.get(0).copied(),
+            change_map,
+            graph_limit,
+        )?;
     }
+
+    Ok(())
+}
+
+/// Diff two CSV or TSV files, returning the changed positions and a
+/// description of the changed columns and cells.
+fn diff_tables(
+    lhs_src: &str,
+    rhs_src: &str,
+    delimiter: char,
+    graph_limit: usize,
+) -> Result<
+    (
+        Vec<MatchedPos>,
+        Vec<MatchedPos>,
+        Vec<table_changes::TableChange>,
+    ),
+    ExceededGraphLimit,
+> {
+    let lhs_rows = csv::parse_rows(lhs_src, delimiter);
+    let rhs_rows = csv::parse_rows(rhs_src, delimiter);
+
+    let arena = Arena::new();
+    let lhs = csv::to_syntax(&arena, lhs_src, &lhs_rows);
+    let rhs = csv::to_syntax(&arena, rhs_src, &rhs_rows);
+    init_all_info(&lhs, &rhs);
+
+    let mut change_map = ChangeMap::default();
+    mark_changes(&lhs, &rhs, &mut change_map, graph_limit)?;
+
+    let lhs_positions = syntax::change_positions(&lhs, &change_map);
+    let rhs_positions = syntax::change_positions(&rhs, &change_map);
+    let changes = table_changes::find_table_changes(&lhs_rows, &rhs_rows, &lhs, &rhs, &change_map);
+
+    Ok((lhs_positions, rhs_positions, changes))
 }
 
 /// Given two directories that contain the files, compare them
@@ -535,6 +977,8 @@ fn diff_directories<'a>(
             &rel_path.to_string_lossy(),
             &FileArgument::NamedPath(lhs_path),
             &FileArgument::NamedPath(rhs_path),
+            None,
+            None,
             &display_options,
             &diff_options,
             true,
@@ -543,79 +987,243 @@ fn diff_directories<'a>(
     })
 }
 
+/// Diff every file that git reported as changed. If
+/// `rhs_is_working_tree` is set, read the new version of each file
+/// from disk rather than from git.
+fn diff_git_revisions(
+    changed_files: Vec<git::ChangedFile>,
+    rhs_is_working_tree: bool,
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<parse::guess_language::Language>,
+) -> impl ParallelIterator<Item = DiffResult> {
+    let diff_options = diff_options.clone();
+    let display_options = display_options.clone();
+
+    // Paths in git output are relative to the repository root.
+    let repo_root = if rhs_is_working_tree {
+        Some(git::repo_root())
+    } else {
+        None
+    };
+
+    changed_files.into_par_iter().map(move |changed_file| {
+        info!("Changed file in git: {:?}", changed_file);
+
+        let lhs_path = match &changed_file.lhs {
+            Some(_) => FileArgument::NamedPath(changed_file.lhs_path.clone().into()),
+            None => FileArgument::DevNull,
+        };
+        let lhs_bytes = match &changed_file.lhs {
+            // Submodule hashes are commits in another repository, so
+            // there's no blob to read.
+            Some(entry) if entry.mode != FileMode::Submodule => git::read_blob(&entry.hash),
+            _ => vec![],
+        };
+
+        let rhs_path = match &changed_file.rhs {
+            Some(_) => FileArgument::NamedPath(changed_file.rhs_path.clone().into()),
+            None => FileArgument::DevNull,
+        };
+        let mut rhs_entry = changed_file.rhs.clone();
+        let rhs_bytes = match (&mut rhs_entry, &repo_root) {
+            (Some(entry), Some(repo_root)) => {
+                let path = repo_root.join(&changed_file.rhs_path);
+                match entry.mode {
+                    FileMode::Submodule => {
+                        // Git doesn't hash submodules in the working
+                        // tree, so ask the submodule where it is.
+                        if entry.is_unhashed() {
+                            entry.hash = git::submodule_head(&path);
+                        }
+                        vec![]
+                    }
+                    FileMode::Symlink => read_link_or_die(&path),
+                    FileMode::Regular | FileMode::Executable => read_or_die(&path),
+                }
+            }
+            (Some(entry), None) if entry.mode != FileMode::Submodule => git::read_blob(&entry.hash),
+            _ => vec![],
+        };
+
+        diff_git_file_content(
+            &changed_file.lhs_path,
+            &changed_file.rhs_path,
+            &lhs_path,
+            &rhs_path,
+            changed_file.lhs.as_ref(),
+            rhs_entry.as_ref(),
+            &lhs_bytes,
+            &rhs_bytes,
+            &display_options,
+            &diff_options,
+            language_override,
+        )
+    })
+}
+
 fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
+    for hunk_lines in render_diff_result(display_options, summary) {
+        for line in hunk_lines {
+            print!("{}", line);
+        }
+    }
+}
+
+/// The output lines for `summary`, grouped by hunk. Every line
+/// includes its trailing newline.
+fn render_diff_result(display_options: &DisplayOptions, summary: &DiffResult) -> Vec<Vec<String>> {
+    let metadata_changes: Vec<String> = summary
+        .metadata_changes()
+        .into_iter()
+        .map(|change| format!("{}\n", change))
+        .collect();
+    let mut res = render_file_contents(display_options, summary, !metadata_changes.is_empty());
+    if metadata_changes.is_empty() {
+        return res;
+    }
+
+    // Show metadata changes under the header of the first hunk, so
+    // the file header is only printed once.
+    match res.first_mut() {
+        Some(first_hunk_lines) => {
+            first_hunk_lines.splice(1..1, metadata_changes);
+        }
+        None => {
+            let display_language = match (&summary.lhs_src, &summary.rhs_src) {
+                (FileContent::Text(_), FileContent::Text(_)) => summary
+                    .display_language
+                    .clone()
+                    .unwrap_or_else(|| "Text".into()),
+                _ => "binary".into(),
+            };
+            let mut lines = vec![format!(
+                "{}\n",
+                display::style::header(
+                    &summary.lhs_display_path,
+                    &summary.rhs_display_path,
+                    1,
+                    1,
+                    &display_language,
+                    display_options
+                )
+            )];
+            lines.extend(metadata_changes);
+            lines.push("\n".into());
+            res.push(lines);
+        }
+    }
+    res
+}
+
+/// The output lines for the contents of the files in `summary`,
+/// grouped by hunk. If the file has metadata changes, we don't
+/// report unchanged contents.
+fn render_file_contents(
+    display_options: &DisplayOptions,
+    summary: &DiffResult,
+    has_metadata_changes: bool,
+) -> Vec<Vec<String>> {
+    let header = |display_language: &str| {
+        format!(
+            "{}\n",
+            display::style::header(
+                &summary.lhs_display_path,
+                &summary.rhs_display_path,
+                1,
+                1,
+                display_language,
+                display_options
+            )
+        )
+    };
+
+    let mut res = vec![];
     match (&summary.lhs_src, &summary.rhs_src) {
         (FileContent::Text(lhs_src), FileContent::Text(rhs_src)) => {
             let hunks = &summary.hunks;
+            let visible_whitespace;
+            let (lhs_src, rhs_src, lhs_positions, rhs_positions) =
+                if display_options.show_whitespace {
+                    visible_whitespace = display::whitespace::show_whitespace(
+                        lhs_src,
+                        rhs_src,
+                        &summary.lhs_positions,
+                        &summary.rhs_positions,
+                        display_options.tab_width,
+                    );
+                    (
+                        &visible_whitespace.0[..],
+                        &visible_whitespace.1[..],
+                        &visible_whitespace.2[..],
+                        &visible_whitespace.3[..],
+                    )
+                } else {
+                    (
+                        &lhs_src[..],
+                        &rhs_src[..],
+                        &summary.lhs_positions[..],
+                        &summary.rhs_positions[..],
+                    )
+                };
 
             let display_language = summary
                 .display_language
                 .clone()
                 .unwrap_or_else(|| "Text".into());
             if !summary.has_syntactic_changes {
-                if display_options.print_unchanged {
-                    println!(
-                        "{}",
-                        display::style::header(
-                            &summary.lhs_display_path,
-                            &summary.rhs_display_path,
-                            1,
-                            1,
-                            &display_language,
-                            display_options
-                        )
-                    );
-                    if display_language == "Text" || summary.lhs_src == summary.rhs_src {
-                        // TODO: there are other strings used for text now, so
-                        // they will hit the second case incorrectly.
-                        println!("No changes.\n");
-                    } else {
-                        println!("No syntactic changes.\n");
-                    }
+                if display_options.print_unchanged && !has_metadata_changes {
+                    let message =
+                        if display_language == "Text" || summary.lhs_src == summary.rhs_src {
+                            // TODO: there are other strings used for text now, so
+                            // they will hit the second case incorrectly.
+                            "No changes.\n"
+                        } else {
+                            "No syntactic changes.\n"
+                        };
+                    res.push(vec![header(&display_language), message.into(), "\n".into()]);
                 }
-                return;
+                return res;
             }
 
             if summary.has_syntactic_changes && hunks.is_empty() {
-                println!(
-                    "{}",
-                    display::style::header(
-                        &summary.lhs_display_path,
-                        &summary.rhs_display_path,
-                        1,
-                        1,
-                        &display_language,
-                        display_options
-                    )
-                );
-                if display_language == "Text" {
+                let message = if display_language == "Text" {
                     // TODO: there are other Text names now, so
                     // they will hit the second case incorrectly.
-                    println!("Has changes.\n");
+                    "Has changes.\n"
                 } else {
-                    println!("Has syntactic changes.\n");
-                }
-
-                return;
+                    "Has syntactic changes.\n"
+                };
+                res.push(vec![header(&display_language), message.into(), "\n".into()]);
+                return res;
             }
 
-            match display_options.display_mode {
-                DisplayMode::Inline => {
-                    display::inline::print(
-                        lhs_src,
-                        rhs_src,
-                        display_options,
-                        &summary.lhs_positions,
-                        &summary.rhs_positions,
-                        hunks,
-                        &summary.lhs_display_path,
-                        &summary.rhs_display_path,
-                        &display_language,
-                        summary.language_used,
-                    );
-                }
+            res.extend(match display_options.display_mode {
+                DisplayMode::Inline => display::inline::lines_by_hunk(
+                    lhs_src,
+                    rhs_src,
+                    display_options,
+                    lhs_positions,
+                    rhs_positions,
+                    hunks,
+                    &summary.lhs_display_path,
+                    &summary.rhs_display_path,
+                    &display_language,
+                    summary.language_used,
+                ),
+                DisplayMode::WordDiff => display::word_diff::lines_by_hunk(
+                    lhs_src,
+                    rhs_src,
+                    display_options,
+                    lhs_positions,
+                    rhs_positions,
+                    hunks,
+                    &summary.lhs_display_path,
+                    &summary.rhs_display_path,
+                    &display_language,
+                ),
                 DisplayMode::SideBySide | DisplayMode::SideBySideShowBoth => {
-                    display::side_by_side::print(
+                    display::side_by_side::lines_by_hunk(
                         hunks,
                         display_options,
                         &summary.lhs_display_path,
@@ -624,49 +1232,130 @@ fn print_diff_result(display_options: &DisplayOptions, summary: &DiffResult) {
                         summary.language_used,
                         lhs_src,
                         rhs_src,
-                        &summary.lhs_positions,
-                        &summary.rhs_positions,
-                    );
+                        lhs_positions,
+                        rhs_positions,
+                    )
                 }
+            });
+        }
+        (
+            FileContent::Binary {
+                len: lhs_len,
+                bytes: lhs_bytes,
+            },
+            FileContent::Binary {
+                len: rhs_len,
+                bytes: rhs_bytes,
+            },
+        ) => {
+            if let (Some(lhs_bytes), Some(rhs_bytes)) = (lhs_bytes, rhs_bytes) {
+                res.extend(display::hex::lines_by_hunk(
+                    lhs_bytes,
+                    rhs_bytes,
+                    &summary.byte_changes,
+                    display_options,
+                    &summary.lhs_display_path,
+                    &summary.rhs_display_path,
+                ));
+            } else if (display_options.print_unchanged && !has_metadata_changes)
+                || summary.has_byte_changes
+            {
+                let message = if !summary.has_byte_changes {
+                    "No changes.\n".into()
+                } else if display_options.binary_display == BinaryDisplay::Size {
+                    format!(
+                        "Binary contents changed: {} → {} bytes.\n",
+                        lhs_len, rhs_len
+                    )
+                } else if display_options.binary_display == BinaryDisplay::Hex {
+                    format!(
+                        "Binary contents changed (too large for a hex diff, over {} bytes).\n",
+                        byte_diff::MAX_BYTE_DIFF_SIZE
+                    )
+                } else {
+                    "Binary contents changed.\n".into()
+                };
+                res.push(vec![header("binary"), message]);
             }
         }
-        (FileContent::Binary, FileContent::Binary) => {
+        (FileContent::Text(_), FileContent::Binary { .. })
+        | (FileContent::Binary { .. }, FileContent::Text(_)) => {
+            // We're diffing a binary file against a text file.
+            res.push(vec![header("binary"), "Binary contents changed.\n".into()]);
+        }
+        (FileContent::Symlink(lhs_target), FileContent::Symlink(rhs_target)) => {
             if display_options.print_unchanged || summary.has_byte_changes {
-                println!(
-                    "{}",
-                    display::style::header(
-                        &summary.lhs_display_path,
-                        &summary.rhs_display_path,
-                        1,
-                        1,
-                        "binary",
-                        display_options
+                let message = if summary.has_byte_changes {
+                    format!(
+                        "Symlink target changed from {} to {}.\n",
+                        lhs_target, rhs_target
                     )
-                );
-                if summary.has_byte_changes {
-                    println!("Binary contents changed.");
                 } else {
-                    println!("No changes.");
-                }
+                    "No changes.\n".into()
+                };
+                res.push(vec![header("symlink"), message]);
             }
         }
-        (FileContent::Text(_), FileContent::Binary)
-        | (FileContent::Binary, FileContent::Text(_)) => {
-            // We're diffing a binary file against a text file.
-            println!(
-                "{}",
-                display::style::header(
-                    &summary.lhs_display_path,
-                    &summary.rhs_display_path,
-                    1,
-                    1,
-                    "binary",
-                    display_options
-                )
-            );
-            println!("Binary contents changed.");
+        (FileContent::Submodule(lhs_commit), FileContent::Submodule(rhs_commit)) => {
+            if display_options.print_unchanged || summary.has_byte_changes {
+                let message = match (lhs_commit, rhs_commit) {
+                    (Some(lhs_commit), Some(rhs_commit)) if lhs_commit != rhs_commit => format!(
+                        "Submodule moved from {} to {}.\n",
+                        git::short_hash(lhs_commit),
+                        git::short_hash(rhs_commit)
+                    ),
+                    (None, Some(rhs_commit)) => {
+                        format!("Submodule added at {}.\n", git::short_hash(rhs_commit))
+                    }
+                    (Some(lhs_commit), None) => {
+                        format!("Submodule removed from {}.\n", git::short_hash(lhs_commit))
+                    }
+                    _ => "No changes.\n".into(),
+                };
+                res.push(vec![header("submodule"), message]);
+            }
+        }
+        (FileContent::Text(_), FileContent::Symlink(target)) => {
+            res.push(vec![
+                header("symlink"),
+                format!("Symlink added → {}.\n", target),
+            ]);
+        }
+        (FileContent::Symlink(target), FileContent::Text(_)) => {
+            res.push(vec![
+                header("symlink"),
+                format!("Symlink removed → {}.\n", target),
+            ]);
+        }
+        (FileContent::Text(_), FileContent::Submodule(commit)) => {
+            let message = match commit {
+                Some(commit) => format!("Submodule added at {}.\n", git::short_hash(commit)),
+                None => "Submodule added.\n".into(),
+            };
+            res.push(vec![header("submodule"), message]);
+        }
+        (FileContent::Submodule(commit), FileContent::Text(_)) => {
+            let message = match commit {
+                Some(commit) => format!("Submodule removed from {}.\n", git::short_hash(commit)),
+                None => "Submodule removed.\n".into(),
+            };
+            res.push(vec![header("submodule"), message]);
+        }
+        (lhs_src, rhs_src) => {
+            // The file changed type, e.g. from a symlink to a
+            // submodule.
+            res.push(vec![
+                header(rhs_src.kind_name()),
+                format!(
+                    "Changed from a {} to a {}.\n",
+                    lhs_src.kind_name(),
+                    rhs_src.kind_name()
+                ),
+            ]);
         }
     }
+
+    res
 }
 
 #[cfg(test)]
@@ -1,13 +1,31 @@
 //! CLI option parsing.
 
-use std::{env, ffi::OsStr, path::Path, path::PathBuf};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    path::Path,
+    path::PathBuf,
+};
+#[cfg(unix)]
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    time::{Duration, Instant},
+};
 
-use clap::{crate_authors, crate_description, crate_version, Arg, Command};
+use clap::{crate_authors, crate_description, crate_version, Arg, ArgGroup, Command};
 use const_format::formatcp;
 use crossterm::tty::IsTty;
 
 use crate::{
-    display::style::BackgroundColor, exit_codes::EXIT_BAD_ARGUMENTS, parse::guess_language,
+    display::{
+        style::BackgroundColor,
+        theme::{Theme, THEME_NAMES},
+    },
+    exit_codes::EXIT_BAD_ARGUMENTS,
+    files::read_or_die,
+    git::GitEntry,
+    parse::guess_language,
 };
 
 pub const DEFAULT_BYTE_LIMIT: usize = 1_000_000;
@@ -28,7 +46,7 @@ pub enum ColorOutput {
 
 #[derive(Debug, Clone)]
 pub struct DisplayOptions {
-    pub background_color: BackgroundColor,
+    pub theme: Theme,
     pub use_color: bool,
     pub display_mode: DisplayMode,
     pub print_unchanged: bool,
@@ -37,12 +55,19 @@ pub struct DisplayOptions {
     pub num_context_lines: u32,
     pub in_vcs: bool,
     pub syntax_highlight: bool,
+    /// Show tabs and trailing spaces, and treat indentation changes
+    /// as novel.
+    pub show_whitespace: bool,
+    /// Show results in a full-screen viewer rather than printing them.
+    pub interactive: bool,
+    pub binary_display: BinaryDisplay,
 }
 
 impl Default for DisplayOptions {
     fn default() -> Self {
         Self {
-            background_color: BackgroundColor::Dark,
+            theme: Theme::named("default", BackgroundColor::Dark)
+                .expect("The default theme always exists"),
             use_color: false,
             display_mode: DisplayMode::SideBySide,
             print_unchanged: true,
@@ -51,6 +76,9 @@ impl Default for DisplayOptions {
             num_context_lines: 3,
             in_vcs: false,
             syntax_highlight: true,
+            show_whitespace: false,
+            interactive: false,
+            binary_display: BinaryDisplay::Summary,
         }
     }
 }
@@ -62,6 +90,11 @@ pub struct DiffOptions {
     // pub error_limit: usize,
     pub check_only: bool,
     pub ignore_comments: bool,
+    /// Treat CRLF line endings as LF.
+    pub strip_cr: bool,
+    /// Omit cell outputs and execution counts when diffing Jupyter
+    /// notebooks.
+    pub ignore_notebook_outputs: bool,
 }
 
 impl Default for DiffOptions {
@@ -72,6 +105,8 @@ impl Default for DiffOptions {
             // error_limit: 0,
             check_only: false,
             ignore_comments: false,
+            strip_cr: false,
+            ignore_notebook_outputs: false,
         }
     }
 }
@@ -92,6 +127,10 @@ fn app() -> clap::Command<'static> {
             env!("CARGO_BIN_NAME"),
             " old/ new/\n\n",
             "Difftastic can also be invoked with 7 arguments in the format that GIT_EXTERNAL_DIFF expects.\n\n",
+            "You can compare git revisions directly, without configuring git.\n\n",
+            "$ ",
+            env!("CARGO_BIN_NAME"),
+            " --git HEAD~1 HEAD -- src/\n\n",
             "See the full manual at: https://difftastic.wilfred.me.uk/")
         )
         .arg(
@@ -146,12 +185,20 @@ fn app() -> clap::Command<'static> {
         )
         .arg(
             Arg::new("display").long("display")
-                .possible_values(["side-by-side", "side-by-side-show-both", "inline"])
+                .possible_values(["side-by-side", "side-by-side-show-both", "inline", "word-diff"])
                 .default_value("side-by-side")
                 .value_name("MODE")
                 .env("DFT_DISPLAY")
                 .help("Display mode for showing results.")
         )
+        .arg(
+            Arg::new("binary").long("binary")
+                .possible_values(["hex", "summary", "size"])
+                .default_value("summary")
+                .value_name("MODE")
+                .env("DFT_BINARY")
+                .help("How to show changes to binary files. hex shows a hex dump of the changed bytes, size shows the old and new file sizes.")
+        )
         .arg(
             Arg::new("color").long("color")
                 .possible_values(["always", "auto", "never"])
@@ -164,9 +211,23 @@ fn app() -> clap::Command<'static> {
             Arg::new("background").long("background")
                 .value_name("BACKGROUND")
                 .env("DFT_BACKGROUND")
-                .possible_values(["dark", "light"])
-                .default_value("dark")
-                .help("Set the background brightness. Difftastic will prefer brighter colours on dark backgrounds.")
+                .possible_values(["auto", "dark", "light"])
+                .help("Set the background brightness. Difftastic will prefer brighter colours on dark backgrounds. By default, difftastic checks COLORFGBG and otherwise assumes dark. auto also asks the terminal, unless difftastic is run by git.")
+        )
+        .arg(
+            Arg::new("theme").long("theme")
+                .value_name("THEME")
+                .env("DFT_THEME")
+                .possible_values(THEME_NAMES)
+                .default_value("default")
+                .help("Colour theme. blue-orange and blue-yellow are safe for red-green colour blindness.")
+        )
+        .arg(
+            Arg::new("theme-file").long("theme-file")
+                .value_name("PATH")
+                .env("DFT_THEME_FILE")
+                .allow_invalid_utf8(true)
+                .help("Override theme colours with the KEY = COLOR lines in this file. See the manual for the format.")
         )
         .arg(
             Arg::new("syntax-highlight").long("syntax-highlight")
@@ -176,6 +237,11 @@ fn app() -> clap::Command<'static> {
                 .default_value("on")
                 .help("Enable or disable syntax highlighting.")
         )
+        .arg(
+            Arg::new("show-whitespace").long("show-whitespace")
+                .env("DFT_SHOW_WHITESPACE")
+                .help("Show tabs as → and trailing spaces as ·, and highlight lines whose indentation has changed.")
+        )
         .arg(
             Arg::new("exit-code").long("exit-code")
                 .env("DFT_EXIT_CODE")
@@ -191,10 +257,24 @@ fn app() -> clap::Command<'static> {
                 .env("DFT_IGNORE_COMMENTS")
                 .help("Don't consider comments when diffing.")
         )
+        .arg(
+            Arg::new("strip-cr").long("strip-cr")
+                .env("DFT_STRIP_CR")
+                .help("Remove carriage returns before diffing, so files that only differ in CRLF or LF line endings are unchanged.")
+        )
+        .arg(
+            Arg::new("ignore-notebook-outputs").long("ignore-notebook-outputs")
+                .env("DFT_IGNORE_NOTEBOOK_OUTPUTS")
+                .help("Don't consider cell outputs or execution counts when diffing Jupyter notebooks.")
+        )
         .arg(
             Arg::new("skip-unchanged").long("skip-unchanged")
                 .help("Don't display anything if a file is unchanged.")
         )
+        .arg(
+            Arg::new("interactive").long("interactive")
+                .help("Browse the results in a full-screen viewer, with keys to move between hunks and files.")
+        )
         .arg(
             Arg::new("missing-as-empty").long("missing-as-empty")
                 .help("Treat paths that don't exist as equivalent to an empty file. Only applies when diffing files, not directories.")
@@ -240,6 +320,28 @@ fn app() -> clap::Command<'static> {
         //         .validator(|s| s.parse::<usize>())
         //         .required(false),
         // )
+        .arg(
+            Arg::new("git").long("git")
+                .takes_value(true)
+                .value_name("REV")
+                .min_values(1)
+                .max_values(2)
+                .help("Compare two git revisions, using the git repository in the current directory. If only one revision is given, compare it with the working tree. Paths after -- limit which files are compared.")
+        )
+        .arg(
+            Arg::new("stage").long("stage")
+                .conflicts_with("interactive")
+                .help("Step through the hunks in unstaged files and choose which ones to add to the git index, like `git add -p`. Paths after -- limit which files are considered.")
+        )
+        .group(ArgGroup::new("git-modes").args(&["git", "stage"]))
+        .arg(
+            Arg::new("conflicts").long("conflicts")
+                .takes_value(true)
+                .value_name("PATH")
+                .allow_invalid_utf8(true)
+                .conflicts_with("git-modes")
+                .help("Show each conflict in a file with git conflict markers (<<<<<<< and >>>>>>>), as a diff between the two sides of the conflict.")
+        )
         .arg(
             Arg::new("paths")
                 .value_name("PATHS")
@@ -247,6 +349,15 @@ fn app() -> clap::Command<'static> {
                 .hide(true)
                 .allow_invalid_utf8(true),
         )
+        .arg(
+            Arg::new("pathspecs")
+                .value_name("PATHSPECS")
+                .multiple_values(true)
+                .last(true)
+                .requires("git-modes")
+                .hide(true)
+                .allow_invalid_utf8(true),
+        )
         .arg_required_else_help(true)
 }
 
@@ -255,6 +366,17 @@ pub enum DisplayMode {
     Inline,
     SideBySide,
     SideBySideShowBoth,
+    WordDiff,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryDisplay {
+    /// A hex dump of the changed bytes, with context.
+    Hex,
+    /// Only say that the file changed.
+    Summary,
+    /// Show the old and new file sizes.
+    Size,
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -333,10 +455,40 @@ pub enum Mode {
         lhs_display_path: String,
         /// The path that we should display for the RHS file.
         rhs_display_path: String,
+        /// The hash and mode of the LHS file, if git passed them.
+        lhs_git_entry: Option<GitEntry>,
+        /// The hash and mode of the RHS file, if git passed them.
+        rhs_git_entry: Option<GitEntry>,
+    },
+    DiffFromGit {
+        diff_options: DiffOptions,
+        display_options: DisplayOptions,
+        set_exit_code: bool,
+        language_override: Option<guess_language::Language>,
+        lhs_rev: String,
+        /// The revision to compare against. If this is `None`,
+        /// compare against the working tree.
+        rhs_rev: Option<String>,
+        /// Only compare paths that match these git pathspecs.
+        pathspecs: Vec<OsString>,
+    },
+    Stage {
+        diff_options: DiffOptions,
+        display_options: DisplayOptions,
+        language_override: Option<guess_language::Language>,
+        /// Only consider paths that match these git pathspecs.
+        pathspecs: Vec<OsString>,
     },
     ListLanguages {
         use_color: bool,
     },
+    Conflicts {
+        diff_options: DiffOptions,
+        display_options: DisplayOptions,
+        language_override: Option<guess_language::Language>,
+        /// The file containing conflict markers.
+        path: PathBuf,
+    },
     DumpTreeSitter {
         path: String,
         language_override: Option<guess_language::Language>,
@@ -378,6 +530,8 @@ pub fn parse_args() -> Mode {
     let use_color = should_use_color(color_output);
 
     let ignore_comments = matches.is_present("ignore-comments");
+    let strip_cr = matches.is_present("strip-cr");
+    let ignore_notebook_outputs = matches.is_present("ignore-notebook-outputs");
 
     if matches.is_present("list-languages") {
         return Mode::ListLanguages { use_color };
@@ -398,58 +552,6 @@ pub fn parse_args() -> Mode {
         };
     }
 
-    let args: Vec<_> = matches.values_of_os("paths").unwrap_or_default().collect();
-    info!("CLI arguments: {:?}", args);
-
-    // TODO: document these different ways of calling difftastic.
-    let (lhs_display_path, rhs_display_path, lhs_path, rhs_path, in_vcs) = match &args[..] {
-        [lhs_path, rhs_path] => {
-            let lhs_arg = FileArgument::from_cli_argument(lhs_path);
-            let rhs_arg = FileArgument::from_cli_argument(rhs_path);
-            (
-                lhs_arg.display(),
-                rhs_arg.display(),
-                lhs_arg,
-                rhs_arg,
-                false,
-            )
-        }
-        [display_path, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode] => {
-            // https://git-scm.com/docs/git#Documentation/git.txt-codeGITEXTERNALDIFFcode
-            (
-                display_path.to_string_lossy().to_string(),
-                display_path.to_string_lossy().to_string(),
-                FileArgument::from_path_argument(lhs_tmp_file),
-                FileArgument::from_path_argument(rhs_tmp_file),
-                true,
-            )
-        }
-        [old_name, lhs_tmp_file, _lhs_hash, _lhs_mode, rhs_tmp_file, _rhs_hash, _rhs_mode, new_name, _similarity] =>
-        {
-            // Rename file.
-            // TODO: where does git document these 9 arguments?
-            (
-                old_name.to_string_lossy().to_string(),
-                new_name.to_string_lossy().to_string(),
-                FileArgument::from_path_argument(lhs_tmp_file),
-                FileArgument::from_path_argument(rhs_tmp_file),
-                true,
-            )
-        }
-        _ => {
-            if !args.is_empty() {
-                eprintln!(
-                    "error: Difftastic does not support being called with {} argument{}.\n",
-                    args.len(),
-                    if args.len() == 1 { "" } else { "s" }
-                );
-            }
-            eprintln!("USAGE:\n\n    {}\n", USAGE);
-            eprintln!("For more information try --help");
-            std::process::exit(EXIT_BAD_ARGUMENTS);
-        }
-    };
-
     let display_width = if let Some(arg_width) = matches.value_of("width") {
         arg_width
             .parse::<usize>()
@@ -462,21 +564,23 @@ pub fn parse_args() -> Mode {
         "side-by-side" => DisplayMode::SideBySide,
         "side-by-side-show-both" => DisplayMode::SideBySideShowBoth,
         "inline" => DisplayMode::Inline,
+        "word-diff" => DisplayMode::WordDiff,
         _ => {
             unreachable!("clap has already validated display")
         }
     };
 
-    let background_color = match matches
-        .value_of("background")
-        .expect("Always present as we've given clap a default")
-    {
-        "dark" => BackgroundColor::Dark,
-        "light" => BackgroundColor::Light,
-        _ => unreachable!("clap has already validated the values"),
+    let binary_display = match matches.value_of("binary").expect("binary has a default") {
+        "hex" => BinaryDisplay::Hex,
+        "summary" => BinaryDisplay::Summary,
+        "size" => BinaryDisplay::Size,
+        _ => {
+            unreachable!("clap has already validated binary")
+        }
     };
 
     let syntax_highlight = matches.value_of("syntax-highlight") == Some("on");
+    let show_whitespace = matches.is_present("show-whitespace");
 
     let graph_limit = matches
         .value_of("graph-limit")
@@ -510,6 +614,12 @@ pub fn parse_args() -> Mode {
 
     let print_unchanged = !matches.is_present("skip-unchanged");
 
+    let interactive = matches.is_present("interactive");
+    if interactive && !std::io::stdout().is_tty() {
+        eprintln!("error: --interactive requires a terminal.");
+        std::process::exit(EXIT_BAD_ARGUMENTS);
+    }
+
     // TODO: is this necessary now we handle /dev/null as an empty
     // file on all platforms?
     let missing_as_empty = matches.is_present("missing-as-empty");
@@ -518,8 +628,57 @@ pub fn parse_args() -> Mode {
 
     let check_only = matches.is_present("check-only");
 
-    let display_options = DisplayOptions {
+    let diff_options = DiffOptions {
+        graph_limit,
+        byte_limit,
+        // error_limit,
+        check_only,
+        ignore_comments,
+        strip_cr,
+        ignore_notebook_outputs,
+    };
+
+    let args: Vec<_> = matches.values_of_os("paths").unwrap_or_default().collect();
+    info!("CLI arguments: {:?}", args);
+
+    let pathspecs: Vec<OsString> = matches
+        .values_of_os("pathspecs")
+        .unwrap_or_default()
+        .map(|pathspec| pathspec.to_owned())
+        .collect();
+
+    // Git runs difftastic once per file, so we don't wait for the
+    // terminal to report its background colour.
+    let in_vcs =
+        matches.is_present("git") || matches.is_present("stage") || matches!(args.len(), 7 | 9);
+
+    let background_color = match matches.value_of("background") {
+        Some("auto") => detect_background_color(use_color && !in_vcs),
+        Some("dark") => BackgroundColor::Dark,
+        Some("light") => BackgroundColor::Light,
+        None => detect_background_color(false),
+        _ => unreachable!("clap has already validated the values"),
+    };
+
+    let mut theme = Theme::named(
+        matches.value_of("theme").expect("theme has a default"),
         background_color,
+    )
+    .expect("clap has already validated theme");
+    if let Some(path) = matches.value_of_os("theme-file") {
+        let src = read_or_die(Path::new(path));
+        if let Err(e) = theme.apply_file(&String::from_utf8_lossy(&src)) {
+            eprintln!(
+                "error: Invalid theme file {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+    }
+
+    let display_options = DisplayOptions {
+        theme,
         use_color,
         print_unchanged,
         tab_width,
@@ -527,20 +686,129 @@ pub fn parse_args() -> Mode {
         display_width,
         num_context_lines,
         syntax_highlight,
-        in_vcs,
+        show_whitespace,
+        in_vcs: false,
+        interactive,
+        binary_display,
     };
 
-    let diff_options = DiffOptions {
-        graph_limit,
-        byte_limit,
-        // error_limit,
-        check_only,
-        ignore_comments,
-    };
+    if let Some(revs) = matches.values_of("git") {
+        if !args.is_empty() {
+            eprintln!("error: --git takes at most two revisions. Use -- before paths.\n");
+            eprintln!("For more information try --help");
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+
+        let mut revs = revs.map(|rev| rev.to_owned());
+        let lhs_rev = revs.next().expect("clap requires at least one revision");
+        let rhs_rev = revs.next();
+
+        return Mode::DiffFromGit {
+            diff_options,
+            display_options: DisplayOptions {
+                in_vcs: true,
+                ..display_options
+            },
+            set_exit_code,
+            language_override,
+            lhs_rev,
+            rhs_rev,
+            pathspecs,
+        };
+    }
+
+    if matches.is_present("stage") {
+        if !args.is_empty() {
+            eprintln!("error: --stage does not take any arguments. Use -- before paths.\n");
+            eprintln!("For more information try --help");
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+
+        return Mode::Stage {
+            diff_options,
+            display_options: DisplayOptions {
+                in_vcs: true,
+                ..display_options
+            },
+            language_override,
+            pathspecs,
+        };
+    }
+
+    if let Some(path) = matches.value_of_os("conflicts") {
+        if !args.is_empty() {
+            eprintln!("error: --conflicts takes a single file.\n");
+            eprintln!("For more information try --help");
+            std::process::exit(EXIT_BAD_ARGUMENTS);
+        }
+
+        return Mode::Conflicts {
+            diff_options,
+            display_options,
+            language_override,
+            path: PathBuf::from(path),
+        };
+    }
+
+    // TODO: document these different ways of calling difftastic.
+    let (lhs_display_path, rhs_display_path, lhs_path, rhs_path, lhs_git_entry, rhs_git_entry) =
+        match &args[..] {
+            [lhs_path, rhs_path] => {
+                let lhs_arg = FileArgument::from_cli_argument(lhs_path);
+                let rhs_arg = FileArgument::from_cli_argument(rhs_path);
+                (
+                    lhs_arg.display(),
+                    rhs_arg.display(),
+                    lhs_arg,
+                    rhs_arg,
+                    None,
+                    None,
+                )
+            }
+            [display_path, lhs_tmp_file, lhs_hash, lhs_mode, rhs_tmp_file, rhs_hash, rhs_mode] => {
+                // https://git-scm.com/docs/git#Documentation/git.txt-codeGITEXTERNALDIFFcode
+                (
+                    display_path.to_string_lossy().to_string(),
+                    display_path.to_string_lossy().to_string(),
+                    FileArgument::from_path_argument(lhs_tmp_file),
+                    FileArgument::from_path_argument(rhs_tmp_file),
+                    git_entry_from_args(lhs_hash, lhs_mode),
+                    git_entry_from_args(rhs_hash, rhs_mode),
+                )
+            }
+            [old_name, lhs_tmp_file, lhs_hash, lhs_mode, rhs_tmp_file, rhs_hash, rhs_mode, new_name, _similarity] =>
+            {
+                // Rename file.
+                // TODO: where does git document these 9 arguments?
+                (
+                    old_name.to_string_lossy().to_string(),
+                    new_name.to_string_lossy().to_string(),
+                    FileArgument::from_path_argument(lhs_tmp_file),
+                    FileArgument::from_path_argument(rhs_tmp_file),
+                    git_entry_from_args(lhs_hash, lhs_mode),
+                    git_entry_from_args(rhs_hash, rhs_mode),
+                )
+            }
+            _ => {
+                if !args.is_empty() {
+                    eprintln!(
+                        "error: Difftastic does not support being called with {} argument{}.\n",
+                        args.len(),
+                        if args.len() == 1 { "" } else { "s" }
+                    );
+                }
+                eprintln!("USAGE:\n\n    {}\n", USAGE);
+                eprintln!("For more information try --help");
+                std::process::exit(EXIT_BAD_ARGUMENTS);
+            }
+        };
 
     Mode::Diff {
         diff_options,
-        display_options,
+        display_options: DisplayOptions {
+            in_vcs,
+            ..display_options
+        },
         missing_as_empty,
         set_exit_code,
         language_override,
@@ -548,9 +816,17 @@ pub fn parse_args() -> Mode {
         rhs_path,
         lhs_display_path,
         rhs_display_path,
+        lhs_git_entry,
+        rhs_git_entry,
     }
 }
 
+/// Parse the hash and mode arguments that git passes to external
+/// diff tools. Git passes `.` for both when the file doesn't exist.
+fn git_entry_from_args(hash: &OsStr, mode: &OsStr) -> Option<GitEntry> {
+    GitEntry::from_git(&hash.to_string_lossy(), &mode.to_string_lossy())
+}
+
 /// Choose the display width: try to autodetect, or fall back to a
 /// sensible default.
 fn detect_display_width() -> usize {
@@ -561,6 +837,144 @@ fn detect_display_width() -> usize {
     80
 }
 
+/// How long to wait for the terminal to report its background colour.
+/// Terminals that don't support the query never reply.
+#[cfg(unix)]
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Guess whether the terminal has a dark or light background from
+/// `COLORFGBG`, then by asking the terminal if `query_terminal` is
+/// set, assuming dark if we can't tell. We only query the terminal
+/// when we're writing coloured output to it, so piped output never
+/// waits for a reply.
+fn detect_background_color(query_terminal: bool) -> BackgroundColor {
+    if let Ok(colorfgbg) = env::var("COLORFGBG") {
+        if let Some(background) = background_from_colorfgbg(&colorfgbg) {
+            return background;
+        }
+    }
+
+    if query_terminal && std::io::stdout().is_tty() {
+        if let Some(background) = query_terminal_background() {
+            return background;
+        }
+    }
+
+    BackgroundColor::Dark
+}
+
+/// Ask the terminal for its background colour with the OSC 11
+/// escape sequence.
+#[cfg(unix)]
+fn query_terminal_background() -> Option<BackgroundColor> {
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty").ok()?;
+
+    // Use raw mode so the reply isn't echoed, and we can read it
+    // without waiting for a newline.
+    crossterm::terminal::enable_raw_mode().ok()?;
+    let response = tty
+        .write_all(b"\x1b]11;?\x1b\\")
+        .and_then(|_| tty.flush())
+        .ok()
+        .and_then(|_| read_background_response());
+    let _ = crossterm::terminal::disable_raw_mode();
+
+    background_from_osc11(&response?)
+}
+
+#[cfg(not(unix))]
+fn query_terminal_background() -> Option<BackgroundColor> {
+    None
+}
+
+/// Read the terminal's reply to an OSC 11 query, giving up after
+/// `BACKGROUND_QUERY_TIMEOUT`.
+#[cfg(unix)]
+fn read_background_response() -> Option<String> {
+    let deadline = Instant::now() + BACKGROUND_QUERY_TIMEOUT;
+    let mut response = String::new();
+    loop {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        if !crossterm::event::poll(remaining).ok()? {
+            return None;
+        }
+        if let crossterm::event::Event::Key(key) = crossterm::event::read().ok()? {
+            push_key_event(&mut response, key);
+        }
+
+        // The reply is terminated with either BEL or ST.
+        if response.ends_with('\x07') || response.ends_with("\x1b\\") {
+            return Some(response);
+        }
+    }
+}
+
+/// crossterm doesn't understand OSC replies, so it reports them as
+/// key presses. Convert a key event back to the characters the
+/// terminal sent: ESC followed by a character is reported as Alt
+/// plus that character, and control characters such as BEL as Ctrl
+/// plus a letter.
+#[cfg(unix)]
+fn push_key_event(response: &mut String, key: crossterm::event::KeyEvent) {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    match key.code {
+        KeyCode::Char(c) => {
+            if key.modifiers.contains(KeyModifiers::ALT) {
+                response.push('\x1b');
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_lowercase() {
+                response.push((c as u8 - b'a' + 1) as char);
+            } else {
+                response.push(c);
+            }
+        }
+        KeyCode::Esc => response.push('\x1b'),
+        _ => {}
+    }
+}
+
+/// Parse the terminal's reply to an OSC 11 query, which has the
+/// form `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` followed by a terminator.
+fn background_from_osc11(response: &str) -> Option<BackgroundColor> {
+    let rgb = &response[response.find("rgb:")? + "rgb:".len()..];
+    let rgb = rgb.trim_end_matches(|c| c == '\x07' || c == '\x1b' || c == '\\');
+
+    let mut components = vec![];
+    for component in rgb.split('/') {
+        if component.is_empty() || component.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(component, 16).ok()?;
+        let max = (1u32 << (4 * component.len())) - 1;
+        components.push(value as f64 / max as f64);
+    }
+
+    match components[..] {
+        [r, g, b] => {
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            Some(if luminance < 0.5 {
+                BackgroundColor::Dark
+            } else {
+                BackgroundColor::Light
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parse `COLORFGBG`, which some terminals set to `FG;BG` (or
+/// `FG;default;BG`) using ANSI colour numbers.
+fn background_from_colorfgbg(colorfgbg: &str) -> Option<BackgroundColor> {
+    let bg: u8 = colorfgbg.rsplit(';').next()?.parse().ok()?;
+    // Colours 0-6 are black and dark colours, and 8 is dark grey.
+    Some(if bg <= 6 || bg == 8 {
+        BackgroundColor::Dark
+    } else {
+        BackgroundColor::Light
+    })
+}
+
 pub fn should_use_color(color_output: ColorOutput) -> bool {
     match color_output {
         ColorOutput::Always => true,
@@ -588,4 +1002,56 @@ mod tests {
         // Basic smoke test.
         assert!(detect_display_width() > 10);
     }
+
+    #[test]
+    fn test_background_from_osc11() {
+        assert!(matches!(
+            background_from_osc11("\x1b]11;rgb:0000/0000/0000\x1b\\"),
+            Some(BackgroundColor::Dark)
+        ));
+        assert!(matches!(
+            background_from_osc11("\x1b]11;rgb:ffff/ffff/dddd\x07"),
+            Some(BackgroundColor::Light)
+        ));
+        assert!(matches!(
+            background_from_osc11("\x1b]11;rgb:fd/f6/e3\x07"),
+            Some(BackgroundColor::Light)
+        ));
+        assert!(background_from_osc11("\x1b]11;?\x07").is_none());
+    }
+
+    #[test]
+    fn test_background_from_colorfgbg() {
+        assert!(matches!(
+            background_from_colorfgbg("15;0"),
+            Some(BackgroundColor::Dark)
+        ));
+        assert!(matches!(
+            background_from_colorfgbg("0;default;15"),
+            Some(BackgroundColor::Light)
+        ));
+        assert!(background_from_colorfgbg("default;default").is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_push_key_event() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+        let mut response = String::new();
+        for key in [
+            KeyEvent::new(KeyCode::Char(']'), KeyModifiers::ALT),
+            KeyEvent::new(KeyCode::Char('1'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL),
+            KeyEvent::new(KeyCode::Char('\\'), KeyModifiers::ALT),
+        ] {
+            push_key_event(&mut response, key);
+        }
+        assert_eq!(response, "\x1b]1\x07\x1b\\");
+    }
+
+    #[test]
+    fn test_git_entry_from_args_missing() {
+        assert_eq!(git_entry_from_args(OsStr::new("."), OsStr::new(".")), None);
+    }
 }
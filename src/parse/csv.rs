@@ -0,0 +1,231 @@
+//! A parser for CSV and TSV files.
+//!
+//! There's no tree-sitter grammar for CSV, and the format is simple
+//! enough to parse by hand. We follow RFC 4180, so cells may be
+//! quoted and quoted cells may contain delimiters, newlines and `""`
+//! escaped quotes.
+
+use std::path::Path;
+
+use typed_arena::Arena;
+
+use crate::{
+    lines::NewlinePositions,
+    parse::syntax::{AtomKind, Syntax},
+};
+
+/// A cell in a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    /// The byte offsets of the cell in the source, including any
+    /// quotes.
+    pub start: usize,
+    pub end: usize,
+    /// The value of the cell, without quotes.
+    pub value: String,
+    pub is_quoted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// The byte offsets of the delimiters between cells.
+    pub delimiters: Vec<usize>,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The delimiter used in files at `path`, if this is a CSV or TSV
+/// file.
+pub fn delimiter_for_path(path: &Path) -> Option<char> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "csv" => Some(','),
+        "tsv" | "tab" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// The name of the format using `delimiter`, as shown to the user.
+pub fn format_name(delimiter: char) -> &'static str {
+    if delimiter == '\t' {
+        "TSV"
+    } else {
+        "CSV"
+    }
+}
+
+/// Split `src` into rows of cells. Blank lines are ignored.
+pub fn parse_rows(src: &str, delimiter: char) -> Vec<Row> {
+    let mut rows = vec![];
+
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(row_start, _)) = chars.peek() {
+        let mut row = Row {
+            cells: vec![],
+            delimiters: vec![],
+            start: row_start,
+            end: src.len(),
+        };
+
+        loop {
+            let cell_start = chars.peek().map_or(src.len(), |(i, _)| *i);
+            let mut value = String::new();
+            let mut is_quoted = false;
+
+            if let Some((_, '"')) = chars.peek() {
+                is_quoted = true;
+                chars.next();
+                while let Some((_, c)) = chars.next() {
+                    // A closing quote, unless it's a "" escape.
+                    if c == '"' && chars.next_if(|(_, c)| *c == '"').is_none() {
+                        break;
+                    }
+                    value.push(c);
+                }
+            }
+
+            // Unquoted text, or any text after the closing quote.
+            let mut cell_end = chars.peek().map_or(src.len(), |(i, _)| *i);
+            let mut ends_with_cr = false;
+            while let Some((i, c)) = chars.next_if(|(_, c)| *c != delimiter && *c != '\n') {
+                value.push(c);
+                cell_end = i + c.len_utf8();
+                ends_with_cr = c == '\r';
+            }
+            // The \r of a \r\n line ending isn't part of the cell,
+            // even after a closing quote.
+            let at_line_end = chars.peek().map_or(true, |(_, c)| *c == '\n');
+            if ends_with_cr && at_line_end {
+                value.pop();
+                cell_end -= 1;
+            }
+
+            row.cells.push(Cell {
+                start: cell_start,
+                end: cell_end,
+                value,
+                is_quoted,
+            });
+
+            match chars.next() {
+                Some((i, '\n')) => {
+                    row.end = i;
+                    break;
+                }
+                Some((i, _)) => row.delimiters.push(i),
+                None => break,
+            }
+        }
+
+        let is_blank = row.cells.len() == 1 && row.cells[0].start == row.cells[0].end;
+        if !is_blank {
+            rows.push(row);
+        }
+    }
+
+    rows
+}
+
+/// Convert rows to difftastic syntax: a list for each row,
+/// containing atoms for its cells and delimiters.
+pub fn to_syntax<'a>(arena: &'a Arena<Syntax<'a>>, src: &str, rows: &[Row]) -> Vec<&'a Syntax<'a>> {
+    let nl_pos = NewlinePositions::from(src);
+
+    rows.iter()
+        .map(|row| {
+            let mut children = vec![];
+            for (i, cell) in row.cells.iter().enumerate() {
+                // Empty cells are represented by their adjacent
+                // delimiters.
+                if cell.start < cell.end {
+                    let kind = if cell.is_quoted {
+                        AtomKind::String
+                    } else {
+                        AtomKind::Normal
+                    };
+                    children.push(Syntax::new_atom(
+                        arena,
+                        nl_pos.from_offsets(cell.start, cell.end),
+                        &src[cell.start..cell.end],
+                        kind,
+                    ));
+                }
+                if let Some(&delimiter_start) = row.delimiters.get(i) {
+                    let delimiter_end = delimiter_end_offset(src, delimiter_start);
+                    children.push(Syntax::new_atom(
+                        arena,
+                        nl_pos.from_offsets(delimiter_start, delimiter_end),
+                        &src[delimiter_start..delimiter_end],
+                        AtomKind::Normal,
+                    ));
+                }
+            }
+
+            Syntax::new_list(
+                arena,
+                "",
+                nl_pos.from_offsets(row.start, row.start),
+                children,
+                "",
+                nl_pos.from_offsets(row.end, row.end),
+            )
+        })
+        .collect()
+}
+
+fn delimiter_end_offset(src: &str, delimiter_start: usize) -> usize {
+    delimiter_start
+        + src[delimiter_start..]
+            .chars()
+            .next()
+            .map_or(0, |c| c.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn values(rows: &[Row]) -> Vec<Vec<&str>> {
+        rows.iter()
+            .map(|row| row.cells.iter().map(|cell| cell.value.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let src = "id,name,price\r\n1,\"Widget, large\",10\n\n2,\"Say \"\"hi\"\"\nthere\",\n";
+        let rows = parse_rows(src, ',');
+        assert_eq!(
+            values(&rows),
+            vec![
+                vec!["id", "name", "price"],
+                vec!["1", "Widget, large", "10"],
+                vec!["2", "Say \"hi\"\nthere", ""],
+            ]
+        );
+        assert_eq!(
+            &src[rows[1].cells[1].start..rows[1].cells[1].end],
+            "\"Widget, large\""
+        );
+
+        let src = "\"a\",b\r\n\"c\r\",\"d\"\r\n";
+        let rows = parse_rows(src, ',');
+        assert_eq!(values(&rows), vec![vec!["a", "b"], vec!["c\r", "d"]]);
+        assert_eq!(&src[rows[1].cells[1].start..rows[1].cells[1].end], "\"d\"");
+    }
+
+    #[test]
+    fn test_parse_rows_tsv() {
+        let rows = parse_rows("a\tb,c\n1\t2", '\t');
+        assert_eq!(values(&rows), vec![vec!["a", "b,c"], vec!["1", "2"]]);
+    }
+
+    #[test]
+    fn test_delimiter_for_path() {
+        assert_eq!(delimiter_for_path(Path::new("seed.CSV")), Some(','));
+        assert_eq!(delimiter_for_path(Path::new("data.tsv")), Some('\t'));
+        assert_eq!(delimiter_for_path(Path::new("data.txt")), None);
+    }
+}
@@ -15,8 +15,9 @@ use regex::Regex;
 use std::{borrow::Borrow, ffi::OsStr, path::Path};
 
 /// Languages supported by difftastic. Each language here has a
-/// corresponding tree-sitter parser.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// corresponding tree-sitter parser, except INI files which use
+/// `parse::ini`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Language {
     Bash,
     C,
@@ -39,6 +40,7 @@ pub enum Language {
     Haskell,
     Hcl,
     Html,
+    Ini,
     Janet,
     Java,
     JavaScript,
@@ -61,10 +63,12 @@ pub enum Language {
     Rust,
     Scala,
     Sql,
+    Svelte,
     Swift,
     Toml,
     Tsx,
     TypeScript,
+    Vue,
     Yaml,
     Zig,
 }
@@ -93,6 +97,7 @@ pub fn language_name(language: Language) -> &'static str {
         Haskell => "Haskell",
         Hcl => "HCL",
         Html => "HTML",
+        Ini => "INI",
         Janet => "Janet",
         Java => "Java",
         JavaScript => "JavaScript",
@@ -115,10 +120,12 @@ pub fn language_name(language: Language) -> &'static str {
         Rust => "Rust",
         Scala => "Scala",
         Sql => "SQL",
+        Svelte => "Svelte",
         Swift => "Swift",
         Toml => "TOML",
         Tsx => "TypeScript TSX",
         TypeScript => "TypeScript",
+        Vue => "Vue",
         Yaml => "YAML",
         Zig => "Zig",
     }
@@ -131,8 +138,7 @@ pub const LANG_EXTENSIONS: &[(Language, &[&str])] = &[
     (
         Bash,
         &[
-            "sh", "bash", "bats", "cgi", "command", "env", "fcgi", "ksh", "sh.in", "tmux", "tool",
-            "zsh",
+            "sh", "bash", "bats", "cgi", "command", "fcgi", "ksh", "sh.in", "tmux", "tool", "zsh",
         ],
     ),
     (C, &["c"]),
@@ -168,6 +174,19 @@ pub const LANG_EXTENSIONS: &[(Language, &[&str])] = &[
     (Haskell, &["hs"]),
     (Hcl, &["hcl", "nomad", "tf", "tfvars", "workflow"]),
     (Html, &["html", "htm", "xhtml"]),
+    (
+        Ini,
+        &[
+            "ini",
+            "cfg",
+            "cnf",
+            "editorconfig",
+            "env",
+            "gitconfig",
+            "prefs",
+            "properties",
+        ],
+    ),
     (Janet, &["janet", "jdn"]),
     (Java, &["java"]),
     (JavaScript, &["cjs", "js", "mjs"]),
@@ -180,6 +199,7 @@ pub const LANG_EXTENSIONS: &[(Language, &[&str])] = &[
             "gltf",
             "har",
             "ice",
+            "ipynb",
             "JSON-tmLanguage",
             "jsonl",
             "mcmeta",
@@ -208,10 +228,12 @@ pub const LANG_EXTENSIONS: &[(Language, &[&str])] = &[
     (Rust, &["rs"]),
     (Scala, &["scala", "sbt", "sc"]),
     (Sql, &["sql", "pgsql"]),
+    (Svelte, &["svelte"]),
     (Swift, &["swift"]),
     (Toml, &["toml"]),
     (TypeScript, &["ts"]),
     (Tsx, &["tsx"]),
+    (Vue, &["vue"]),
     (Yaml, &["yaml", "yml"]),
     (Zig, &["zig"]),
 ];
@@ -227,9 +249,6 @@ pub const LANG_FILE_NAMES: &[(Language, &[&str])] = &[
             ".bash_profile",
             ".bashrc",
             ".cshrc",
-            ".env",
-            ".env.example",
-            ".flaskenv",
             ".kshrc",
             ".login",
             ".profile",
@@ -260,6 +279,27 @@ pub const LANG_FILE_NAMES: &[(Language, &[&str])] = &[
     (CMake, &["CMakeLists.txt"]),
     (EmacsLisp, &[".emacs", "_emacs", "Cask"]),
     (Erlang, &["Emakefile"]),
+    (
+        Ini,
+        &[
+            ".coveragerc",
+            ".editorconfig",
+            ".env",
+            ".env.development",
+            ".env.example",
+            ".env.local",
+            ".env.production",
+            ".env.test",
+            ".flake8",
+            ".flaskenv",
+            ".gitconfig",
+            ".gitmodules",
+            ".npmrc",
+            ".pylintrc",
+            "gitconfig",
+            "pylintrc",
+        ],
+    ),
     (
         Json,
         &[
@@ -358,6 +398,7 @@ fn from_emacs_mode_header(src: &str) -> Option<Language> {
             "haskell" => Some(Haskell),
             "hcl" => Some(Hcl),
             "html" => Some(Html),
+            "conf" | "conf-unix" | "conf-windows" | "conf-javaprop" => Some(Ini),
             "janet" => Some(Janet),
             "java" => Some(Java),
             "js" | "js2" => Some(JavaScript),
@@ -370,10 +411,12 @@ fn from_emacs_mode_header(src: &str) -> Option<Language> {
             "scala" => Some(Scala),
             "sh" => Some(Bash),
             "sql" => Some(Sql),
+            "svelte" => Some(Svelte),
             "swift" => Some(Swift),
             "toml" => Some(Toml),
             "tuareg" => Some(OCaml),
             "typescript" => Some(TypeScript),
+            "vue" => Some(Vue),
             "yaml" => Some(Yaml),
             "zig" => Some(Zig),
             _ => None,
@@ -393,30 +436,8 @@ fn from_shebang(src: &str) -> Option<Language> {
     }
     if let Some(first_line) = src.lines().next() {
         if let Some(cap) = RE.captures(first_line) {
-            let interpreter_path = Path::new(&cap[1]);
-            if let Some(name) = interpreter_path.file_name() {
-                match name.to_string_lossy().borrow() {
-                    "ash" | "bash" | "dash" | "ksh" | "mksh" | "pdksh" | "rc" | "sh" | "zsh" => {
-                        return Some(Bash)
-                    }
-                    "tcc" => return Some(C),
-                    "lisp" | "sbc" | "ccl" | "clisp" | "ecl" => return Some(CommonLisp),
-                    "elixir" => return Some(Elixir),
-                    "elvish" => return Some(Elvish),
-                    "escript" => return Some(Erlang),
-                    "hhvm" => return Some(Hack),
-                    "runghc" | "runhaskell" | "runhugs" => return Some(Haskell),
-                    "chakra" | "d8" | "gjs" | "js" | "node" | "nodejs" | "qjs" | "rhino" | "v8"
-                    | "v8-shell" => return Some(JavaScript),
-                    "ocaml" | "ocamlrun" | "ocamlscript" => return Some(OCaml),
-                    "perl" => return Some(Perl),
-                    "python" | "python2" | "python3" => return Some(Python),
-                    "Rscript" => return Some(R),
-                    "ruby" | "macruby" | "rake" | "jruby" | "rbx" => return Some(Ruby),
-                    "swift" => return Some(Swift),
-                    "deno" | "ts-node" => return Some(TypeScript),
-                    _ => {}
-                }
+            if let Some(language) = from_interpreter(&cap[1]) {
+                return Some(language);
             }
         }
 
@@ -429,6 +450,34 @@ fn from_shebang(src: &str) -> Option<Language> {
     None
 }
 
+/// The language run by the interpreter at `path`, such as
+/// `/usr/bin/python3`.
+pub fn from_interpreter(path: &str) -> Option<Language> {
+    let name = Path::new(path).file_name()?;
+    match name.to_string_lossy().borrow() {
+        "ash" | "bash" | "dash" | "ksh" | "mksh" | "pdksh" | "rc" | "sh" | "zsh" => Some(Bash),
+        "tcc" => Some(C),
+        "lisp" | "sbc" | "ccl" | "clisp" | "ecl" => Some(CommonLisp),
+        "elixir" => Some(Elixir),
+        "elvish" => Some(Elvish),
+        "escript" => Some(Erlang),
+        "hhvm" => Some(Hack),
+        "runghc" | "runhaskell" | "runhugs" => Some(Haskell),
+        "chakra" | "d8" | "gjs" | "js" | "node" | "nodejs" | "qjs" | "rhino" | "v8"
+        | "v8-shell" => Some(JavaScript),
+        "ocaml" | "ocamlrun" | "ocamlscript" => Some(OCaml),
+        "perl" => Some(Perl),
+        "python" | "python2" | "python3" => Some(Python),
+        "Rscript" => Some(R),
+        "ruby" | "macruby" | "rake" | "jruby" | "rbx" => Some(Ruby),
+        // Database clients, which read SQL from stdin or `-c`.
+        "mysql" | "psql" | "sqlite3" => Some(Sql),
+        "swift" => Some(Swift),
+        "deno" | "ts-node" => Some(TypeScript),
+        _ => None,
+    }
+}
+
 fn from_name(path: &Path) -> Option<Language> {
     match path.file_name() {
         Some(name) => {
@@ -460,6 +509,33 @@ pub fn from_extension(current_extension: &OsStr) -> Option<Language> {
     None
 }
 
+/// The language called `name`, such as `rust`, `Python` or `js`, as
+/// used in the info string of Markdown code blocks.
+pub fn from_language_name(name: &str) -> Option<Language> {
+    if name.is_empty() {
+        return None;
+    }
+
+    from_extension(OsStr::new(&name.to_lowercase())).or_else(|| {
+        LANG_EXTENSIONS
+            .iter()
+            .map(|(language, _)| *language)
+            .find(|language| language_name(*language).eq_ignore_ascii_case(name))
+    })
+}
+
+/// The language named in a `language=NAME` annotation, such as a
+/// `# language=sql` comment before a string containing SQL. This is
+/// the syntax used by IntelliJ for language injections.
+pub fn from_language_annotation(comment: &str) -> Option<Language> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\blanguage\s*=\s*([\w+#-]+)").unwrap();
+    }
+
+    let cap = RE.captures(comment)?;
+    from_language_name(&cap[1])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -477,6 +553,13 @@ mod tests {
         assert_eq!(guess(path, ""), Some(Bash));
     }
 
+    #[test]
+    fn test_guess_ini() {
+        assert_eq!(guess(Path::new("foo/.env"), "A=1"), Some(Ini));
+        assert_eq!(guess(Path::new(".gitconfig"), ""), Some(Ini));
+        assert_eq!(guess(Path::new("app.properties"), ""), Some(Ini));
+    }
+
     #[test]
     fn test_guess_by_shebang() {
         let path = Path::new("foo");
@@ -524,4 +607,27 @@ mod tests {
         let path = Path::new("jfkdlsjfkdsljfkdsljf");
         assert_eq!(guess(path, ""), None);
     }
+
+    #[test]
+    fn test_from_language_name() {
+        assert_eq!(from_language_name("rust"), Some(Rust));
+        assert_eq!(from_language_name("js"), Some(JavaScript));
+        assert_eq!(from_language_name("Python"), Some(Python));
+        assert_eq!(from_language_name("no_run"), None);
+        assert_eq!(from_language_name(""), None);
+    }
+
+    #[test]
+    fn test_from_interpreter() {
+        assert_eq!(from_interpreter("/usr/bin/python3"), Some(Python));
+        assert_eq!(from_interpreter("psql"), Some(Sql));
+        assert_eq!(from_interpreter("cat"), None);
+    }
+
+    #[test]
+    fn test_from_language_annotation() {
+        assert_eq!(from_language_annotation("# language=sql"), Some(Sql));
+        assert_eq!(from_language_annotation("// language = JSON"), Some(Json));
+        assert_eq!(from_language_annotation("# the language of love"), None);
+    }
 }
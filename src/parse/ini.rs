@@ -0,0 +1,357 @@
+//! A parser for INI-style configuration files, including `.cfg`,
+//! `.properties`, `.env` and `.gitconfig` files.
+//!
+//! These formats have no standard grammar and differ in small ways,
+//! so we parse them line by line and accept the common syntax of all
+//! of them. Each `[section]` is a list, with the section header as
+//! its open delimiter, and each `key = value` line is a list with
+//! `key =` as its open delimiter and the value as its child.
+
+use typed_arena::Arena;
+
+use crate::{
+    lines::NewlinePositions,
+    parse::syntax::{AtomKind, Syntax},
+};
+
+/// The byte offsets of a token in the source.
+type Span = (usize, usize);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Entry {
+    Comment(Span),
+    Pair {
+        /// An `export` keyword before the key, as used in `.env`
+        /// files.
+        export: Option<Span>,
+        key: Span,
+        separator: Option<Span>,
+        value: Option<Span>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Section {
+    header: Span,
+    entries: Vec<Entry>,
+}
+
+/// Entries before the first section, and the sections.
+#[derive(Debug, PartialEq, Eq)]
+struct IniFile {
+    preamble: Vec<Entry>,
+    sections: Vec<Section>,
+}
+
+/// The lines of `src`, with their byte offsets, excluding the line
+/// break.
+fn lines_with_offsets(src: &str) -> Vec<(usize, &str)> {
+    let mut res = vec![];
+    let mut offset = 0;
+    for line in src.split('\n') {
+        res.push((offset, line.strip_suffix('\r').unwrap_or(line)));
+        offset += line.len() + 1;
+    }
+    res
+}
+
+fn is_comment(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with(';') || line.starts_with('#') || line.starts_with('!')
+}
+
+/// Parse a `key = value` line starting at `offset`.
+fn parse_pair(line: &str, offset: usize) -> Entry {
+    let indent = line.len() - line.trim_start().len();
+    let mut pos = indent;
+
+    let mut export = None;
+    if let Some(rest) = line[pos..].strip_prefix("export ") {
+        export = Some((offset + pos, offset + pos + "export".len()));
+        pos = line.len() - rest.trim_start().len();
+    }
+
+    match line[pos..].find(|c| c == '=' || c == ':') {
+        Some(i) => {
+            let separator_start = pos + i;
+            let key = line[pos..separator_start].trim_end();
+            let value_start = separator_start + 1;
+            let value = line[value_start..].trim();
+            let value_start =
+                value_start + line[value_start..].len() - line[value_start..].trim_start().len();
+
+            Entry::Pair {
+                export,
+                key: (offset + pos, offset + pos + key.len()),
+                separator: Some((offset + separator_start, offset + separator_start + 1)),
+                value: if value.is_empty() {
+                    None
+                } else {
+                    Some((offset + value_start, offset + value_start + value.len()))
+                },
+            }
+        }
+        None => {
+            // A key without a value, such as a boolean in a
+            // .gitconfig.
+            let key = line[pos..].trim_end();
+            Entry::Pair {
+                export,
+                key: (offset + pos, offset + pos + key.len()),
+                separator: None,
+                value: None,
+            }
+        }
+    }
+}
+
+fn parse_file(src: &str) -> IniFile {
+    let mut preamble = vec![];
+    let mut sections: Vec<Section> = vec![];
+
+    let lines = lines_with_offsets(src);
+    let mut i = 0;
+    while i < lines.len() {
+        let (offset, line) = lines[i];
+        i += 1;
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let start = offset + indent;
+        let end = start + trimmed.len();
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            sections.push(Section {
+                header: (start, end),
+                entries: vec![],
+            });
+            continue;
+        }
+
+        let entry = if is_comment(line) {
+            Entry::Comment((start, end))
+        } else {
+            let mut entry = parse_pair(line, offset);
+
+            // Values continue onto the next line if they end with a
+            // backslash (.properties) or the next line is indented
+            // more deeply than the key and isn't itself a key
+            // (Python's configparser). Keys in a .gitconfig are all
+            // indented, so a valueless key there isn't a continuation.
+            if let Entry::Pair {
+                value: Some((_, ref mut value_end)),
+                ..
+            } = entry
+            {
+                while i < lines.len() {
+                    let (next_offset, next_line) = lines[i];
+                    let next_indent = next_line.len() - next_line.trim_start().len();
+                    let continues = src[..*value_end].ends_with('\\')
+                        || (next_indent > indent
+                            && !next_line.trim().is_empty()
+                            && !next_line.contains(|c| c == '=' || c == ':')
+                            && !is_comment(next_line));
+                    if !continues {
+                        break;
+                    }
+                    *value_end = next_offset + next_line.trim_end().len();
+                    i += 1;
+                }
+            }
+            entry
+        };
+
+        match sections.last_mut() {
+            Some(section) => section.entries.push(entry),
+            None => preamble.push(entry),
+        }
+    }
+
+    IniFile { preamble, sections }
+}
+
+fn entry_to_syntax<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    entry: &Entry,
+) -> &'a Syntax<'a> {
+    let atom = |(start, end): Span, kind: AtomKind| {
+        Syntax::new_atom(
+            arena,
+            nl_pos.from_offsets(start, end),
+            &src[start..end],
+            kind,
+        )
+    };
+
+    match entry {
+        Entry::Comment(span) => atom(*span, AtomKind::Comment),
+        Entry::Pair {
+            export,
+            key,
+            separator,
+            value,
+        } => {
+            // The key and separator are the open delimiter, so pairs
+            // are only matched up if they have the same key. The
+            // content ignores whitespace around the separator.
+            let mut open_content = String::new();
+            let mut open_position = vec![];
+            for (start, end) in export.iter().chain(Some(key)) {
+                if !open_content.is_empty() {
+                    open_content.push(' ');
+                }
+                open_content.push_str(&src[*start..*end]);
+                open_position.extend(nl_pos.from_offsets(*start, *end));
+            }
+            if let Some((start, end)) = separator {
+                open_content.push_str(&src[*start..*end]);
+                open_position.extend(nl_pos.from_offsets(*start, *end));
+            }
+
+            let children = value
+                .iter()
+                .map(|value| atom(*value, AtomKind::String))
+                .collect();
+
+            let end = value.or(*separator).unwrap_or(*key).1;
+            Syntax::new_list(
+                arena,
+                &open_content,
+                open_position,
+                children,
+                "",
+                nl_pos.from_offsets(end, end),
+            )
+        }
+    }
+}
+
+/// Parse `src` as an INI-style file.
+pub fn parse<'a>(arena: &'a Arena<Syntax<'a>>, src: &str) -> Vec<&'a Syntax<'a>> {
+    let nl_pos = NewlinePositions::from(src);
+    let file = parse_file(src);
+
+    let mut res: Vec<&'a Syntax<'a>> = file
+        .preamble
+        .iter()
+        .map(|entry| entry_to_syntax(arena, src, &nl_pos, entry))
+        .collect();
+
+    for section in &file.sections {
+        let children: Vec<_> = section
+            .entries
+            .iter()
+            .map(|entry| entry_to_syntax(arena, src, &nl_pos, entry))
+            .collect();
+
+        let (header_start, header_end) = section.header;
+        let end = match section.entries.last() {
+            Some(Entry::Comment((_, end))) => *end,
+            Some(Entry::Pair {
+                key,
+                separator,
+                value,
+                ..
+            }) => value.or(*separator).unwrap_or(*key).1,
+            None => header_end,
+        };
+        res.push(Syntax::new_list(
+            arena,
+            &src[header_start..header_end],
+            nl_pos.from_offsets(header_start, header_end),
+            children,
+            "",
+            nl_pos.from_offsets(end, end),
+        ));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn text(src: &str, (start, end): Span) -> &str {
+        &src[start..end]
+    }
+
+    #[test]
+    fn test_parse_pair() {
+        let src = "  export  DB_URL = postgres://localhost ";
+        match parse_pair(src, 0) {
+            Entry::Pair {
+                export: Some(export),
+                key,
+                separator: Some(separator),
+                value: Some(value),
+            } => {
+                assert_eq!(text(src, export), "export");
+                assert_eq!(text(src, key), "DB_URL");
+                assert_eq!(text(src, separator), "=");
+                assert_eq!(text(src, value), "postgres://localhost");
+            }
+            entry => panic!("Unexpected entry: {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let src = "; preamble\nname=demo\n\n# about core\n[core]\n\tbare\n[remote \"origin\"]\nurl = a\n  b\nmsg = x \\\ny\n";
+        let file = parse_file(src);
+
+        assert_eq!(
+            file.preamble
+                .iter()
+                .filter(|entry| matches!(entry, Entry::Comment(_)))
+                .count(),
+            2
+        );
+        assert_eq!(file.sections.len(), 2);
+        assert_eq!(text(src, file.sections[1].header), "[remote \"origin\"]");
+
+        let values: Vec<&str> = file.sections[1]
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Pair {
+                    value: Some(value), ..
+                } => Some(text(src, *value)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, vec!["a\n  b", "x \\\ny"]);
+    }
+
+    #[test]
+    fn test_parse_file_gitconfig_valueless_key() {
+        let src = "[core]\n\teditor = vim\n\tbare\n\tpager = less\n\t  -R\n";
+        let file = parse_file(src);
+
+        let entries: Vec<(&str, Option<&str>)> = file.sections[0]
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Pair { key, value, .. } => {
+                    Some((text(src, *key), value.map(|value| text(src, value))))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("editor", Some("vim")),
+                ("bare", None),
+                ("pager", Some("less\n\t  -R")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Helpers for reading values from JSON files, using the tree-sitter
+//! JSON parser.
+//!
+//! These are used for file formats that are stored as JSON but are
+//! better shown in another form, such as Jupyter notebooks and
+//! lockfiles.
+
+use tree_sitter as ts;
+
+use crate::parse::{guess_language::Language, tree_sitter_parser as tsp};
+
+/// Parse `json`, returning `None` if it isn't valid JSON.
+pub fn parse(json: &str) -> Option<ts::Tree> {
+    let config = tsp::from_language(Language::Json).unwrap();
+    let tree = tsp::to_tree(json, &config);
+    if tree.root_node().has_error() {
+        return None;
+    }
+    Some(tree)
+}
+
+/// The top-level object in `tree`, if any.
+pub fn root_object(tree: &ts::Tree) -> Option<ts::Node<'_>> {
+    named_children(tree.root_node())
+        .into_iter()
+        .find(|node| node.kind() == "object")
+}
+
+pub fn named_children(node: ts::Node) -> Vec<ts::Node> {
+    let mut cursor = node.walk();
+    let children = node.named_children(&mut cursor).collect();
+    children
+}
+
+/// The value associated with `key` in the JSON object `object`.
+pub fn field<'a>(object: ts::Node<'a>, json: &str, key: &str) -> Option<ts::Node<'a>> {
+    fields(object, json)
+        .into_iter()
+        .find(|(pair_key, _)| pair_key == key)
+        .map(|(_, value)| value)
+}
+
+/// The keys and values in the JSON object `object`.
+pub fn fields<'a>(object: ts::Node<'a>, json: &str) -> Vec<(String, ts::Node<'a>)> {
+    if object.kind() != "object" {
+        return vec![];
+    }
+
+    named_children(object)
+        .into_iter()
+        .filter(|pair| pair.kind() == "pair")
+        .filter_map(|pair| {
+            let key = pair
+                .child_by_field_name("key")
+                .and_then(|node| string_value(node, json))?;
+            let value = pair.child_by_field_name("value")?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// The value of a JSON string literal.
+pub fn string_value(node: ts::Node, json: &str) -> Option<String> {
+    if node.kind() != "string" {
+        return None;
+    }
+
+    let literal = &json[node.start_byte()..node.end_byte()];
+    let content = literal.strip_prefix('"')?.strip_suffix('"')?;
+    Some(unescape(content))
+}
+
+/// Replace the escape sequences in the contents of a JSON string.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('r') => res.push('\r'),
+            Some('b') => res.push('\u{8}'),
+            Some('f') => res.push('\u{c}'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let mut code_point = u32::from_str_radix(&hex, 16).unwrap_or(0xFFFD);
+
+                // Characters outside the BMP are written as a
+                // surrogate pair, e.g. 😀.
+                if (0xD800..0xDC00).contains(&code_point) {
+                    let rest = chars.as_str();
+                    if let Some(low) = rest
+                        .strip_prefix("\\u")
+                        .and_then(|rest| rest.get(..4))
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .filter(|low| (0xDC00..0xE000).contains(low))
+                    {
+                        code_point = 0x10000 + ((code_point - 0xD800) << 10) + (low - 0xDC00);
+                        chars = rest[6..].chars();
+                    }
+                }
+                // An unpaired surrogate isn't a valid char, so this
+                // gives U+FFFD.
+                res.push(char::from_u32(code_point).unwrap_or('\u{FFFD}'));
+            }
+            Some(other) => res.push(other),
+            None => {}
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_field() {
+        let src = r#"{"name": "demo", "version": 2}"#;
+        let tree = parse(src).unwrap();
+        let root = root_object(&tree).unwrap();
+        assert_eq!(
+            field(root, src, "name").and_then(|node| string_value(node, src)),
+            Some("demo".to_owned())
+        );
+        assert!(field(root, src, "missing").is_none());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(
+            unescape(r#"a\"b\\c\u00e9\ud83d\ude00"#),
+            "a\"b\\c\u{e9}\u{1F600}"
+        );
+        assert_eq!(unescape(r#"\ud83d\u0041"#), "\u{FFFD}A");
+    }
+}
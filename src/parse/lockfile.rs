@@ -0,0 +1,434 @@
+//! Reading the dependencies listed in lockfiles and package
+//! manifests.
+//!
+//! Lockfiles are generated, so a structural diff of them is large
+//! and rarely useful. Instead, we summarise which packages were
+//! added, removed or updated.
+
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::parse::json::{self, field, fields, named_children, string_value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyFileKind {
+    /// A generated file listing every package version, such as
+    /// Cargo.lock. We only show the dependency changes.
+    Lockfile,
+    /// A hand-written file declaring dependencies, such as
+    /// package.json. We show the dependency changes as well as the
+    /// diff.
+    Manifest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `[[package]]` tables with a `name` and `version`, used by
+    /// Cargo.lock, poetry.lock and uv.lock.
+    TomlPackages,
+    PackageLockJson,
+    YarnLock,
+    ComposerLock,
+    PipfileLock,
+    GemfileLock,
+    CargoToml,
+    PackageJson,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub version: String,
+}
+
+impl Dependency {
+    fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+        }
+    }
+}
+
+fn format_for_path(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy();
+    let format = match name.as_ref() {
+        "Cargo.lock" | "poetry.lock" | "uv.lock" => Format::TomlPackages,
+        "package-lock.json" | "npm-shrinkwrap.json" => Format::PackageLockJson,
+        "yarn.lock" => Format::YarnLock,
+        "composer.lock" => Format::ComposerLock,
+        "Pipfile.lock" => Format::PipfileLock,
+        "Gemfile.lock" => Format::GemfileLock,
+        "Cargo.toml" => Format::CargoToml,
+        "package.json" => Format::PackageJson,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Whether the file at `path` is a lockfile or manifest that we can
+/// read dependencies from.
+pub fn dependency_file_kind(path: &Path) -> Option<DependencyFileKind> {
+    let kind = match format_for_path(path)? {
+        Format::CargoToml | Format::PackageJson => DependencyFileKind::Manifest,
+        _ => DependencyFileKind::Lockfile,
+    };
+    Some(kind)
+}
+
+/// The dependencies in `src`, the contents of the lockfile or
+/// manifest at `path`. Returns `None` if this isn't a file we
+/// recognise, or it's malformed.
+pub fn dependencies(path: &Path, src: &str) -> Option<Vec<Dependency>> {
+    let format = format_for_path(path)?;
+
+    // A file that has been added or removed.
+    if src.trim().is_empty() {
+        return Some(vec![]);
+    }
+
+    match format {
+        Format::TomlPackages => Some(toml_packages(src)),
+        Format::YarnLock => Some(yarn_lock(src)),
+        Format::GemfileLock => Some(gemfile_lock(src)),
+        Format::CargoToml => Some(cargo_toml(src)),
+        Format::PackageLockJson
+        | Format::ComposerLock
+        | Format::PipfileLock
+        | Format::PackageJson => json_dependencies(format, src),
+    }
+}
+
+/// The value of a `key = "value"` line in a TOML file.
+fn toml_string<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(key)?.trim_start().strip_prefix('=')?;
+    let value = rest.trim();
+    Some(value.trim_matches('"'))
+}
+
+fn toml_packages(src: &str) -> Vec<Dependency> {
+    let mut res = vec![];
+
+    let mut in_package = false;
+    let mut name = None;
+    let mut version = None;
+    for line in src.lines().chain(Some("[[package]]")) {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if let (Some(name), Some(version)) = (name.take(), version.take()) {
+                res.push(Dependency::new(name, version));
+            }
+            // Ignore subtables, such as [package.dependencies] in
+            // poetry.lock.
+            in_package = line == "[[package]]";
+            continue;
+        }
+
+        if in_package {
+            if let Some(value) = toml_string(line, "name") {
+                name = Some(value);
+            } else if let Some(value) = toml_string(line, "version") {
+                version = Some(value);
+            }
+        }
+    }
+
+    res
+}
+
+/// The package name in a yarn.lock entry such as `"@babel/core@^7.0.0"`
+/// or `lodash@npm:^4.17.0`.
+fn yarn_package_name(spec: &str) -> &str {
+    let spec = spec.trim().trim_matches('"');
+    // Scoped packages start with @, so look for the version @ after
+    // the first character.
+    match spec.get(1..).and_then(|rest| rest.find('@')) {
+        Some(i) => &spec[..i + 1],
+        None => spec,
+    }
+}
+
+fn yarn_lock(src: &str) -> Vec<Dependency> {
+    let mut res = vec![];
+
+    let mut name = None;
+    for line in src.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with(' ') {
+            // An entry header, listing every version range that
+            // resolved to this version.
+            name = match line.strip_suffix(':') {
+                Some(specs) if !specs.starts_with("__metadata") && !specs.is_empty() => {
+                    specs.split(", ").next().map(yarn_package_name)
+                }
+                _ => None,
+            };
+            continue;
+        }
+
+        // `  version "1.2.3"` in yarn 1 and `  version: 1.2.3` in
+        // later versions.
+        if let (Some(package_name), Some(version)) = (name, line.trim().strip_prefix("version")) {
+            let version = version.trim_start_matches(':').trim().trim_matches('"');
+            res.push(Dependency::new(package_name, version));
+            name = None;
+        }
+    }
+
+    res
+}
+
+fn gemfile_lock(src: &str) -> Vec<Dependency> {
+    lazy_static! {
+        // Gems are indented by four spaces under `specs:`, and their
+        // own dependencies by six.
+        static ref RE: Regex = Regex::new(r"^    ([^ ]+) \(([^)]+)\)$").unwrap();
+    }
+
+    src.lines()
+        .filter_map(|line| RE.captures(line))
+        .map(|cap| Dependency::new(&cap[1], &cap[2]))
+        .collect()
+}
+
+fn cargo_toml(src: &str) -> Vec<Dependency> {
+    lazy_static! {
+        static ref SECTION_RE: Regex =
+            Regex::new(r"^\[(?:.+\.)?(?:dev-|build-)?dependencies(?:\.([\w-]+))?\]$").unwrap();
+        static ref DEPENDENCY_RE: Regex = Regex::new(r"^([\w-]+)\s*=\s*(.+)$").unwrap();
+        static ref VERSION_RE: Regex = Regex::new(r#"\bversion\s*=\s*"([^"]*)""#).unwrap();
+    }
+
+    let mut res = vec![];
+
+    let mut in_dependencies = false;
+    // The dependency name in a `[dependencies.foo]` table.
+    let mut table_dependency: Option<String> = None;
+    for line in src.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_dependencies = false;
+            table_dependency = None;
+            if let Some(cap) = SECTION_RE.captures(line) {
+                match cap.get(1) {
+                    Some(name) => table_dependency = Some(name.as_str().to_owned()),
+                    None => in_dependencies = true,
+                }
+            }
+            continue;
+        }
+
+        if let Some(name) = &table_dependency {
+            if let Some(version) = toml_string(line, "version") {
+                res.push(Dependency::new(name, version));
+            }
+        } else if in_dependencies {
+            if let Some(cap) = DEPENDENCY_RE.captures(line) {
+                let value = cap[2].trim();
+                let version = if value.starts_with('"') {
+                    value.trim_matches('"')
+                } else {
+                    // Dependencies without a version, such as path
+                    // dependencies, are shown as `*`.
+                    VERSION_RE
+                        .captures(value)
+                        .and_then(|cap| cap.get(1))
+                        .map_or("*", |m| m.as_str())
+                };
+                res.push(Dependency::new(&cap[1], version));
+            }
+        }
+    }
+
+    res
+}
+
+fn json_dependencies(format: Format, src: &str) -> Option<Vec<Dependency>> {
+    let tree = json::parse(src)?;
+    let root = json::root_object(&tree)?;
+
+    let mut res = vec![];
+    match format {
+        Format::PackageLockJson => {
+            if let Some(packages) = field(root, src, "packages") {
+                // Lockfile version 2 and later.
+                for (path, package) in fields(packages, src) {
+                    // The empty path is the project itself.
+                    let name = match path.rfind("node_modules/") {
+                        Some(i) => &path[i + "node_modules/".len()..],
+                        None => continue,
+                    };
+                    if let Some(version) =
+                        field(package, src, "version").and_then(|node| string_value(node, src))
+                    {
+                        res.push(Dependency::new(name, &version));
+                    }
+                }
+            } else {
+                // Lockfile version 1, where dependencies are nested.
+                let mut stack: Vec<_> = field(root, src, "dependencies").into_iter().collect();
+                while let Some(dependencies) = stack.pop() {
+                    for (name, package) in fields(dependencies, src) {
+                        if let Some(version) =
+                            field(package, src, "version").and_then(|node| string_value(node, src))
+                        {
+                            res.push(Dependency::new(&name, &version));
+                        }
+                        stack.extend(field(package, src, "dependencies"));
+                    }
+                }
+            }
+        }
+        Format::ComposerLock => {
+            for key in &["packages", "packages-dev"] {
+                for package in field(root, src, key).map_or(vec![], named_children) {
+                    let name = field(package, src, "name").and_then(|node| string_value(node, src));
+                    let version =
+                        field(package, src, "version").and_then(|node| string_value(node, src));
+                    if let (Some(name), Some(version)) = (name, version) {
+                        res.push(Dependency::new(&name, &version));
+                    }
+                }
+            }
+        }
+        Format::PipfileLock => {
+            for key in &["default", "develop"] {
+                for (name, package) in
+                    field(root, src, key).map_or(vec![], |node| fields(node, src))
+                {
+                    if let Some(version) =
+                        field(package, src, "version").and_then(|node| string_value(node, src))
+                    {
+                        res.push(Dependency::new(&name, version.trim_start_matches("==")));
+                    }
+                }
+            }
+        }
+        Format::PackageJson => {
+            for key in &[
+                "dependencies",
+                "devDependencies",
+                "peerDependencies",
+                "optionalDependencies",
+            ] {
+                for (name, version) in
+                    field(root, src, key).map_or(vec![], |node| fields(node, src))
+                {
+                    if let Some(version) = string_value(version, src) {
+                        res.push(Dependency::new(&name, &version));
+                    }
+                }
+            }
+        }
+        _ => unreachable!("Not a JSON format: {:?}", format),
+    }
+
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn pairs(dependencies: &[Dependency]) -> Vec<(&str, &str)> {
+        dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.version.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_cargo_lock() {
+        let src = r#"version = 3
+
+[[package]]
+name = "serde"
+version = "1.0.152"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "poetry-style"
+version = "2.0"
+
+[package.dependencies]
+name = "not-a-package"
+"#;
+        assert_eq!(
+            pairs(&dependencies(Path::new("Cargo.lock"), src).unwrap()),
+            vec![("serde", "1.0.152"), ("poetry-style", "2.0")]
+        );
+    }
+
+    #[test]
+    fn test_yarn_lock() {
+        let src = r#"# yarn lockfile v1
+
+"@babel/core@^7.0.0", "@babel/core@^7.1.0":
+  version "7.20.12"
+  resolved "https://registry.yarnpkg.com/@babel/core/-/core-7.20.12.tgz"
+
+lodash@npm:^4.17.0:
+  version: 4.17.21
+"#;
+        assert_eq!(
+            pairs(&dependencies(Path::new("yarn.lock"), src).unwrap()),
+            vec![("@babel/core", "7.20.12"), ("lodash", "4.17.21")]
+        );
+    }
+
+    #[test]
+    fn test_package_lock_json() {
+        let src = r#"{
+  "lockfileVersion": 3,
+  "packages": {
+    "": {"name": "app"},
+    "node_modules/left-pad": {"version": "1.3.0"},
+    "node_modules/a/node_modules/@scope/b": {"version": "2.0.0"}
+  }
+}"#;
+        assert_eq!(
+            pairs(&dependencies(Path::new("package-lock.json"), src).unwrap()),
+            vec![("left-pad", "1.3.0"), ("@scope/b", "2.0.0")]
+        );
+    }
+
+    #[test]
+    fn test_cargo_toml() {
+        let src = r#"[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+serde = "1.0"
+tokio = { version = "1.24", features = ["full"] }
+local = { path = "../local" }
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2"
+
+[dependencies.regex]
+version = "1.7"
+"#;
+        assert_eq!(
+            pairs(&dependencies(Path::new("Cargo.toml"), src).unwrap()),
+            vec![
+                ("serde", "1.0"),
+                ("tokio", "1.24"),
+                ("local", "*"),
+                ("libc", "0.2"),
+                ("regex", "1.7")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_file() {
+        assert_eq!(dependencies(Path::new("foo.toml"), ""), None);
+    }
+}
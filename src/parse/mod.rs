@@ -1,3 +1,8 @@
+pub mod csv;
 pub mod guess_language;
+pub mod ini;
+pub mod json;
+pub mod lockfile;
+pub mod notebook;
 pub mod syntax;
 pub mod tree_sitter_parser;
@@ -0,0 +1,250 @@
+//! Jupyter notebooks, which are JSON files containing code and
+//! Markdown cells.
+//!
+//! Diffing the JSON directly shows escaped source strings, and
+//! changes to outputs and metadata drown out changes to the code.
+//! Instead, we render each notebook as a script in the "percent"
+//! format used by jupytext, with `# %%` before each cell and Markdown
+//! as comments. The script is then diffed with the parser for the
+//! notebook's language.
+
+use tree_sitter as ts;
+
+use crate::parse::{
+    guess_language::{self as guess, Language},
+    json::{self, field, named_children, string_value},
+};
+
+/// A notebook rendered as a script.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RenderedNotebook {
+    pub src: String,
+    /// The language of the notebook's kernel, if we support it.
+    pub language: Option<Language>,
+}
+
+/// Render the notebook in `json` as a script. Returns `None` if this
+/// isn't a notebook.
+///
+/// If `include_outputs` is false, cell outputs and execution counts
+/// are omitted.
+pub fn render(json: &str, include_outputs: bool) -> Option<RenderedNotebook> {
+    // A notebook that's been added or removed.
+    if json.trim().is_empty() {
+        return Some(RenderedNotebook {
+            src: "".into(),
+            language: None,
+        });
+    }
+
+    let tree = json::parse(json)?;
+    let notebook = json::root_object(&tree)?;
+    let cells = field(notebook, json, "cells")?;
+    if cells.kind() != "array" {
+        return None;
+    }
+
+    let language = kernel_language(notebook, json);
+    let comment = comment_prefix(language);
+
+    let mut lines: Vec<String> = vec![];
+    for cell in named_children(cells) {
+        let cell_type = field(cell, json, "cell_type")
+            .and_then(|node| string_value(node, json))
+            .unwrap_or_default();
+        let source = field(cell, json, "source")
+            .and_then(|node| text_value(node, json))
+            .unwrap_or_default();
+
+        if !lines.is_empty() {
+            lines.push("".into());
+        }
+
+        if cell_type == "code" {
+            let execution_count = field(cell, json, "execution_count")
+                .filter(|node| node.kind() == "number" && include_outputs)
+                .map(|node| &json[node.start_byte()..node.end_byte()]);
+            match execution_count {
+                Some(count) => lines.push(format!("{} %% In [{}]", comment, count)),
+                None => lines.push(format!("{} %%", comment)),
+            }
+            lines.extend(source.lines().map(|line| line.to_owned()));
+
+            if include_outputs {
+                if let Some(outputs) = field(cell, json, "outputs") {
+                    for output in named_children(outputs) {
+                        lines.push(format!("{} Out:", comment));
+                        for line in output_text(output, json).lines() {
+                            lines.push(commented(comment, line));
+                        }
+                    }
+                }
+            }
+        } else {
+            lines.push(format!("{} %% [{}]", comment, cell_type));
+            lines.extend(source.lines().map(|line| commented(comment, line)));
+        }
+    }
+
+    Some(RenderedNotebook {
+        src: lines.join("\n"),
+        language,
+    })
+}
+
+/// The language of the notebook's kernel, from its metadata.
+fn kernel_language(notebook: ts::Node, json: &str) -> Option<Language> {
+    let metadata = field(notebook, json, "metadata")?;
+    let name = field(metadata, json, "kernelspec")
+        .and_then(|kernelspec| field(kernelspec, json, "language"))
+        .or_else(|| {
+            field(metadata, json, "language_info")
+                .and_then(|language_info| field(language_info, json, "name"))
+        })
+        .and_then(|node| string_value(node, json))?;
+
+    guess::from_language_name(&name)
+}
+
+/// The line comment syntax for `language`.
+fn comment_prefix(language: Option<Language>) -> &'static str {
+    match language {
+        Some(
+            Language::C
+            | Language::CPlusPlus
+            | Language::CSharp
+            | Language::Go
+            | Language::Java
+            | Language::JavaScript
+            | Language::Kotlin
+            | Language::Rust
+            | Language::Scala
+            | Language::Swift
+            | Language::TypeScript,
+        ) => "//",
+        Some(Language::Haskell | Language::Lua | Language::Sql) => "--",
+        _ => "#",
+    }
+}
+
+fn commented(comment: &str, line: &str) -> String {
+    if line.is_empty() {
+        comment.to_owned()
+    } else {
+        format!("{} {}", comment, line)
+    }
+}
+
+/// A textual summary of a cell output.
+fn output_text(output: ts::Node, json: &str) -> String {
+    let output_type = field(output, json, "output_type")
+        .and_then(|node| string_value(node, json))
+        .unwrap_or_default();
+
+    match output_type.as_str() {
+        "stream" => field(output, json, "text")
+            .and_then(|node| text_value(node, json))
+            .unwrap_or_default(),
+        "error" => {
+            let ename = field(output, json, "ename").and_then(|node| string_value(node, json));
+            let evalue = field(output, json, "evalue").and_then(|node| string_value(node, json));
+            format!(
+                "{}: {}",
+                ename.unwrap_or_default(),
+                evalue.unwrap_or_default()
+            )
+        }
+        _ => {
+            // execute_result or display_data, which have a value for
+            // each MIME type.
+            let data = match field(output, json, "data") {
+                Some(data) => data,
+                None => return "".into(),
+            };
+
+            let mut parts = vec![];
+            for pair in named_children(data) {
+                let mime_type = pair
+                    .child_by_field_name("key")
+                    .and_then(|node| string_value(node, json))
+                    .unwrap_or_default();
+                let value = pair.child_by_field_name("value");
+
+                if mime_type == "text/plain" {
+                    if let Some(text) = value.and_then(|node| text_value(node, json)) {
+                        parts.push(text);
+                    }
+                } else {
+                    // Show that there's an image or HTML output, and
+                    // its size, so changes are visible.
+                    let size = value.map_or(0, |node| node.end_byte() - node.start_byte());
+                    parts.push(format!("[{}, {} bytes]", mime_type, size));
+                }
+            }
+            parts.join("\n")
+        }
+    }
+}
+
+/// The value of a multiline string in a notebook, which may be a
+/// JSON string or an array of strings.
+fn text_value(node: ts::Node, json: &str) -> Option<String> {
+    match node.kind() {
+        "string" => string_value(node, json),
+        "array" => Some(
+            named_children(node)
+                .into_iter()
+                .filter_map(|line| string_value(line, json))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": ["# Analysis\n", "\n", "Load the data."]
+  },
+  {
+   "cell_type": "code",
+   "execution_count": 3,
+   "metadata": {"scrolled": true},
+   "outputs": [{"output_type": "stream", "name": "stdout", "text": ["42\n"]}],
+   "source": ["x = \"a\\tb\"\n", "print(x)"]
+  }
+ ],
+ "metadata": {"kernelspec": {"language": "python", "name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(NOTEBOOK, true),
+            Some(RenderedNotebook {
+                src: "# %% [markdown]\n# # Analysis\n#\n# Load the data.\n\n# %% In [3]\nx = \"a\\tb\"\nprint(x)\n# Out:\n# 42".into(),
+                language: Some(Language::Python),
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_without_outputs() {
+        let rendered = render(NOTEBOOK, false).unwrap();
+        assert!(rendered.src.ends_with("# %%\nx = \"a\\tb\"\nprint(x)"));
+    }
+
+    #[test]
+    fn test_render_not_notebook() {
+        assert_eq!(render("{\"name\": \"foo\"}", true), None);
+    }
+}
@@ -28,6 +28,13 @@ impl<'a> fmt::Debug for ChangeKind<'a> {
                     rhs_node.id()
                 )
             }
+            ReplacedAtom(lhs_node, rhs_node) => {
+                format!(
+                    "ReplacedAtom(lhs ID: {}, rhs ID: {})",
+                    lhs_node.id(),
+                    rhs_node.id()
+                )
+            }
             Novel => "Novel".to_owned(),
         };
         f.write_str(&desc)
@@ -639,13 +646,20 @@ pub enum MatchKind {
     Ignored {
         highlight: TokenKind,
     },
+    /// Novel content in an identifier that was renamed consistently.
+    Renamed {
+        highlight: TokenKind,
+    },
 }
 
 impl MatchKind {
     pub fn is_novel(&self) -> bool {
         matches!(
             self,
-            MatchKind::Novel { .. } | MatchKind::NovelWord { .. } | MatchKind::NovelLinePart { .. }
+            MatchKind::Novel { .. }
+                | MatchKind::NovelWord { .. }
+                | MatchKind::NovelLinePart { .. }
+                | MatchKind::Renamed { .. }
         )
     }
 }
@@ -683,33 +697,86 @@ pub fn split_words(s: &str) -> Vec<&str> {
     res
 }
 
-fn split_comment_words(
+/// Whether `s` looks like an identifier, such as `foo_bar` or
+/// `fooBar`.
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Split an identifier into its camelCase or snake_case segments.
+/// Digits stay with the segment they follow.
+///
+/// "fooBar_baz" -> vec!["foo", "Bar", "_", "baz"]
+/// "HTTPServer" -> vec!["HTTP", "Server"]
+pub fn split_identifier_segments(s: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+
+    let mut res = vec![];
+    let mut segment_start = 0;
+    for i in 1..chars.len() {
+        let (idx, c) = chars[i];
+        let (_, prev) = chars[i - 1];
+        let next_is_lowercase = chars
+            .get(i + 1)
+            .map(|(_, next)| next.is_lowercase())
+            .unwrap_or(false);
+
+        let is_boundary = c == '_'
+            || prev == '_'
+            || (c.is_uppercase() && (prev.is_lowercase() || prev.is_numeric()))
+            || (c.is_uppercase() && prev.is_uppercase() && next_is_lowercase);
+        if is_boundary {
+            res.push(&s[segment_start..idx]);
+            segment_start = idx;
+        }
+    }
+
+    if segment_start < s.len() {
+        res.push(&s[segment_start..]);
+    }
+    res
+}
+
+/// Diff the words of an atom against the words of the atom it
+/// replaced, so only the changed words are highlighted.
+fn split_atom_words(
     content: &str,
     pos: SingleLineSpan,
     opposite_content: &str,
     opposite_pos: SingleLineSpan,
+    highlight: TokenKind,
+    split: fn(&str) -> Vec<&str>,
 ) -> Vec<MatchedPos> {
-    // TODO: merge adjacent single-line comments unless there are
-    // blank lines between them.
-    let content_parts = split_words(content);
-    let other_parts = split_words(opposite_content);
+    let content_parts = split(content);
+    let other_parts = split(opposite_content);
 
     let content_newlines = NewlinePositions::from(content);
     let opposite_content_newlines = NewlinePositions::from(opposite_content);
 
-    let mut offset = 0;
-    let mut opposite_offset = 0;
+    // `split` may skip parts of the content, so find the offset of
+    // each word from its address.
+    let offset_of = |word: &str, whole: &str| word.as_ptr() as usize - whole.as_ptr() as usize;
+
+    // The diff results are interned, so track our position in the
+    // parts to find the original words.
+    let mut content_parts_iter = content_parts.iter();
+    let mut other_parts_iter = other_parts.iter();
 
     let mut res = vec![];
     for diff_res in myers_diff::slice_by_hash(&content_parts, &other_parts) {
         match diff_res {
-            myers_diff::DiffResult::Left(word) => {
+            myers_diff::DiffResult::Left(_) => {
                 // This word is novel to this side.
+                let word = content_parts_iter.next().unwrap();
                 if !is_all_whitespace(word) {
+                    let offset = offset_of(word, content);
                     res.push(MatchedPos {
-                        kind: MatchKind::NovelWord {
-                            highlight: TokenKind::Atom(AtomKind::Comment),
-                        },
+                        kind: MatchKind::NovelWord { highlight },
                         pos: content_newlines.from_offsets_relative_to(
                             pos,
                             offset,
@@ -717,10 +784,13 @@ fn split_comment_words(
                         )[0],
                     });
                 }
-                offset += word.len();
             }
-            myers_diff::DiffResult::Both(word, opposite_word) => {
+            myers_diff::DiffResult::Both(_, _) => {
                 // This word is present on both sides.
+                let word = content_parts_iter.next().unwrap();
+                let opposite_word = other_parts_iter.next().unwrap();
+                let offset = offset_of(word, content);
+                let opposite_offset = offset_of(opposite_word, opposite_content);
                 let word_pos =
                     content_newlines.from_offsets_relative_to(pos, offset, offset + word.len())[0];
                 let opposite_word_pos = opposite_content_newlines.from_offsets_relative_to(
@@ -731,18 +801,16 @@ fn split_comment_words(
 
                 res.push(MatchedPos {
                     kind: MatchKind::NovelLinePart {
-                        highlight: TokenKind::Atom(AtomKind::Comment),
+                        highlight,
                         self_pos: word_pos,
                         opposite_pos: opposite_word_pos,
                     },
                     pos: word_pos,
                 });
-                offset += word.len();
-                opposite_offset += opposite_word.len();
             }
-            myers_diff::DiffResult::Right(opposite_word) => {
+            myers_diff::DiffResult::Right(_) => {
                 // Only exists on other side, nothing to do on this side.
-                opposite_offset += opposite_word.len();
+                other_parts_iter.next();
             }
         }
     }
@@ -750,6 +818,50 @@ fn split_comment_words(
     res
 }
 
+/// Characters that start comment lines, such as `//`, `#`, `;;` or
+/// the `*` in block comments.
+const COMMENT_MARKER_CHARS: &str = "/*#;!%-";
+
+/// Split a comment into words, ignoring whitespace and the comment
+/// markers at the start of each line. Reflowing a comment changes
+/// where its lines break, but not its words.
+fn split_comment_words_ignoring_layout(s: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut at_line_start = true;
+    for word in split_words(s) {
+        if word == "\n" {
+            at_line_start = true;
+            continue;
+        }
+        if is_all_whitespace(word) {
+            continue;
+        }
+        if at_line_start && word.chars().all(|c| COMMENT_MARKER_CHARS.contains(c)) {
+            continue;
+        }
+
+        at_line_start = false;
+        res.push(word);
+    }
+    res
+}
+
+fn split_comment_words(
+    content: &str,
+    pos: SingleLineSpan,
+    opposite_content: &str,
+    opposite_pos: SingleLineSpan,
+) -> Vec<MatchedPos> {
+    split_atom_words(
+        content,
+        pos,
+        opposite_content,
+        opposite_pos,
+        TokenKind::Atom(AtomKind::Comment),
+        split_comment_words_ignoring_layout,
+    )
+}
+
 impl MatchedPos {
     fn new(
         ck: ChangeKind,
@@ -772,12 +884,43 @@ impl MatchedPos {
 
                 split_comment_words(
                     this_content,
-                    // TODO: handle the whole pos here.
+                    // Only the start of the atom is needed: later
+                    // lines are found from the newlines in its content.
                     pos[0],
                     opposite_content,
                     opposite_pos[0],
                 )
             }
+            ReplacedAtom(this, opposite) => {
+                let (this_content, this_kind) = match this {
+                    List { .. } => unreachable!(),
+                    Atom { content, kind, .. } => (content, kind),
+                };
+                let (opposite_content, opposite_pos) = match opposite {
+                    List { .. } => unreachable!(),
+                    Atom {
+                        content, position, ..
+                    } => (content, position),
+                };
+
+                // Strings are compared word by word, and identifiers
+                // by their camelCase or snake_case segments.
+                let split = if *this_kind == AtomKind::String {
+                    split_words
+                } else {
+                    split_identifier_segments
+                };
+                split_atom_words(
+                    this_content,
+                    // Only the start of the atom is needed: later
+                    // lines are found from the newlines in its content.
+                    pos[0],
+                    opposite_content,
+                    opposite_pos[0],
+                    highlight,
+                    split,
+                )
+            }
             Unchanged(opposite) => {
                 let opposite_pos = match opposite {
                     List {
@@ -1098,6 +1241,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_comment_words_ignoring_layout() {
+        assert_eq!(
+            split_comment_words_ignoring_layout("// foo bar\n  // baz."),
+            vec!["foo", "bar", "baz", "."]
+        );
+        assert_eq!(
+            split_comment_words_ignoring_layout("/* foo\n * bar */"),
+            vec!["foo", "bar", "*", "/"]
+        );
+    }
+
+    #[test]
+    fn test_split_comment_words_reflowed() {
+        let content = "// foo bar\n// baz";
+        let pos = SingleLineSpan {
+            line: 0.into(),
+            start_col: 0,
+            end_col: 10,
+        };
+        let opposite_content = "// foo\n// bar baz";
+        let opposite_pos = SingleLineSpan {
+            line: 0.into(),
+            start_col: 0,
+            end_col: 6,
+        };
+
+        let res = split_comment_words(content, pos, opposite_content, opposite_pos);
+        assert!(res
+            .iter()
+            .all(|mp| matches!(mp.kind, MatchKind::NovelLinePart { .. })));
+        assert_eq!(res.len(), 3);
+    }
+
+    #[test]
+    fn test_split_identifier_segments() {
+        assert_eq!(
+            split_identifier_segments("fooBar_baz"),
+            vec!["foo", "Bar", "_", "baz"]
+        );
+        assert_eq!(
+            split_identifier_segments("HTTPServer2"),
+            vec!["HTTP", "Server2"]
+        );
+        assert_eq!(split_identifier_segments("x"), vec!["x"]);
+    }
+
+    #[test]
+    fn test_is_identifier() {
+        assert!(is_identifier("foo_bar1"));
+        assert!(is_identifier("_private"));
+        assert!(!is_identifier("1st"));
+        assert!(!is_identifier("+="));
+        assert!(!is_identifier(""));
+    }
+
     #[test]
     fn test_split_words() {
         let s = "example.com";
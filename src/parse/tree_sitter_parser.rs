@@ -1,9 +1,14 @@
 //! Load and configure parsers written with tree-sitter.
 
+use std::cell::RefCell;
+use std::cmp::min;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::rc::Rc;
 
 use crate::parse::guess_language as guess;
+use lazy_static::lazy_static;
+use regex::Regex;
 use tree_sitter as ts;
 use typed_arena::Arena;
 
@@ -20,14 +25,141 @@ use super::syntax::MatchedPos;
 /// JavaScript). This contains how to identify such nodes, and what
 /// languages we should parse them as.
 ///
-/// Note that we don't support sub-languages more than one layer deep.
+/// Sub-languages may contain their own sub-languages, up to
+/// `MAX_SUB_LANGUAGE_DEPTH` layers deep (e.g. CSS in a JavaScript
+/// template string in HTML).
 pub struct TreeSitterSubLanguage {
-    /// How to identify a node. The query must contain exactly one
-    /// capture group (the name is arbitrary).
+    /// How to identify a node. The query must capture the node as
+    /// `@contents`. If the node starts and ends with anonymous
+    /// tokens, such as the backticks of a template string, they're
+    /// excluded.
     query: ts::Query,
 
     /// What language parser to use (refers in turn to a TreeSitterConfig).
-    parse_as: guess::Language,
+    ///
+    /// If this is `None`, the language is chosen from the other
+    /// captures of the query (see `language_from_captures`).
+    parse_as: Option<guess::Language>,
+}
+
+/// How many layers of sub-languages we parse.
+const MAX_SUB_LANGUAGE_DEPTH: usize = 4;
+
+/// Sub-languages for JavaScript and TypeScript: tagged template
+/// literals, such as `` css`...` `` in Lit or styled-components, and
+/// strings annotated with a `// language=sql` comment.
+fn javascript_sub_languages(language: ts::Language) -> Vec<TreeSitterSubLanguage> {
+    let query = |tags: &str| {
+        ts::Query::new(
+            language,
+            &format!(
+                "(call_expression
+                   function: [(identifier) (member_expression)] @tag
+                   arguments: (template_string) @contents
+                   (#match? @tag \"^({})$\"))",
+                tags
+            ),
+        )
+        .unwrap()
+    };
+
+    vec![
+        TreeSitterSubLanguage {
+            query: query("css|keyframes|createGlobalStyle|injectGlobal|styled\\\\.[a-zA-Z]+"),
+            parse_as: Some(guess::Language::Css),
+        },
+        TreeSitterSubLanguage {
+            query: query("html|svg"),
+            parse_as: Some(guess::Language::Html),
+        },
+        TreeSitterSubLanguage {
+            query: query("sql|SQL"),
+            parse_as: Some(guess::Language::Sql),
+        },
+        TreeSitterSubLanguage {
+            query: ts::Query::new(
+                language,
+                r#"((comment) @language
+                    .
+                    [(lexical_declaration
+                       (variable_declarator value: [(string) (template_string)] @contents))
+                     (variable_declaration
+                       (variable_declarator value: [(string) (template_string)] @contents))]
+                    (#match? @language "language="))"#,
+            )
+            .unwrap(),
+            parse_as: None,
+        },
+    ]
+}
+
+/// Sub-languages for HTML, and for single-file components in
+/// `dialect` (Vue or Svelte) that use HTML syntax.
+fn html_sub_languages(
+    language: ts::Language,
+    dialect: guess::Language,
+) -> Vec<TreeSitterSubLanguage> {
+    let query = |source: &str| ts::Query::new(language, source).unwrap();
+    let script_with_lang = |langs: &str| {
+        query(&format!(
+            r#"(script_element
+                 (start_tag
+                   (attribute
+                     (attribute_name) @_name
+                     [(attribute_value) @_lang
+                      (quoted_attribute_value (attribute_value) @_lang)]))
+                 (raw_text) @contents
+                 (#eq? @_name "lang")
+                 (#match? @_lang "^({})$"))"#,
+            langs
+        ))
+    };
+
+    let mut sub_languages = vec![
+        TreeSitterSubLanguage {
+            query: script_with_lang("ts|typescript"),
+            parse_as: Some(guess::Language::TypeScript),
+        },
+        TreeSitterSubLanguage {
+            query: script_with_lang("tsx"),
+            parse_as: Some(guess::Language::Tsx),
+        },
+        // This includes <style lang="scss"> and <style lang="less">,
+        // which are close enough to CSS for the CSS parser.
+        TreeSitterSubLanguage {
+            query: query("(style_element (raw_text) @contents)"),
+            parse_as: Some(guess::Language::Css),
+        },
+        TreeSitterSubLanguage {
+            query: query("(script_element (raw_text) @contents)"),
+            parse_as: Some(guess::Language::JavaScript),
+        },
+    ];
+
+    match dialect {
+        // Directives such as v-if="..." and :prop="...".
+        guess::Language::Vue => sub_languages.push(TreeSitterSubLanguage {
+            query: query(
+                r#"(attribute
+                     (attribute_name) @_name
+                     (quoted_attribute_value) @contents
+                     (#match? @_name "^(v-|:|@|#)"))"#,
+            ),
+            parse_as: Some(guess::Language::JavaScript),
+        }),
+        // Attribute values such as on:click={handler}.
+        guess::Language::Svelte => sub_languages.push(TreeSitterSubLanguage {
+            query: query(
+                r#"(attribute
+                     [(attribute_value) (quoted_attribute_value)] @contents
+                     (#match? @contents "^[\"']?[{](?s:.*)[}][\"']?$"))"#,
+            ),
+            parse_as: Some(guess::Language::JavaScript),
+        }),
+        _ => {}
+    }
+
+    sub_languages
 }
 
 /// Configuration for a tree-sitter parser.
@@ -57,8 +189,38 @@ pub struct TreeSitterConfig {
     /// language.
     highlight_query: ts::Query,
 
-    /// Sub-languages in use, if any.
+    /// Delimiters around JavaScript expressions inside text nodes,
+    /// such as `{{ }}` in Vue templates.
+    interpolation_delimiters: Option<(&'static str, &'static str)>,
+
+    /// Sub-languages in use, if any. If several sub-languages match
+    /// the same node, the first one is used.
     sub_languages: Vec<TreeSitterSubLanguage>,
+
+    /// The language of fenced code blocks in comments and strings
+    /// that don't name one.
+    code_block_language: Option<guess::Language>,
+}
+
+impl TreeSitterConfig {
+    /// A parser configuration with no interpolations or
+    /// sub-languages.
+    fn new(
+        language: ts::Language,
+        atom_nodes: HashSet<&'static str>,
+        delimiter_tokens: Vec<(&'static str, &'static str)>,
+        highlight_query: ts::Query,
+    ) -> Self {
+        Self {
+            language,
+            atom_nodes,
+            delimiter_tokens,
+            highlight_query,
+            interpolation_delimiters: None,
+            sub_languages: vec![],
+            code_block_language: None,
+        }
+    }
 }
 
 extern "C" {
@@ -122,48 +284,93 @@ const OCAML_ATOM_NODES: [&str; 6] = [
     "attribute_id",
 ];
 
-pub fn from_language(language: guess::Language) -> TreeSitterConfig {
+/// The tree-sitter parser for `language`, or `None` if we don't use
+/// tree-sitter for it. INI files have their own parser in
+/// `parse::ini`.
+pub fn from_language(language: guess::Language) -> Option<TreeSitterConfig> {
     use guess::Language::*;
-    match language {
+    let config = match language {
         Bash => {
             let language = unsafe { tree_sitter_bash() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["string", "raw_string", "heredoc_body"]
-                    .into_iter()
-                    .collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                sub_languages: vec![
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(
+                            language,
+                            r#"((comment) @language
+                                .
+                                (redirected_statement redirect: (heredoc_redirect))
+                                .
+                                (heredoc_body) @contents
+                                (#match? @language "language="))"#,
+                        )
+                        .unwrap(),
+                        parse_as: None,
+                    },
+                    // E.g. `psql <<EOF` or `cat > config.json <<JSON`.
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(
+                            language,
+                            r#"((redirected_statement
+                                 body: (command name: (command_name) @interpreter)
+                                 redirect: (heredoc_redirect (heredoc_start) @delimiter))
+                                .
+                                (heredoc_body) @contents)"#,
+                        )
+                        .unwrap(),
+                        parse_as: None,
+                    },
+                    // E.g. `python3 -c '...'` or `node -e '...'`.
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(
+                            language,
+                            r#"(command
+                                 name: (command_name) @interpreter
+                                 argument: (word) @_flag
+                                 .
+                                 argument: [(raw_string) (string)] @contents
+                                 (#match? @_flag "^-[ce]$"))"#,
+                        )
+                        .unwrap(),
+                        parse_as: None,
+                    },
+                ],
+                ..TreeSitterConfig::new(
                     language,
-                    include_str!("../../vendored_parsers/highlights/bash.scm"),
+                    vec!["string", "raw_string", "heredoc_body"]
+                        .into_iter()
+                        .collect(),
+                    vec![("(", ")"), ("{", "}"), ("[", "]")],
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/bash.scm"),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         C => {
             let language = unsafe { tree_sitter_c() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_literal", "char_literal"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec!["string_literal", "char_literal"].into_iter().collect(),
+                vec![("(", ")"), ("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/c.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         CPlusPlus => {
             let language = unsafe { tree_sitter_cpp() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
                 // The C++ grammar extends the C grammar, so the node
                 // names are generally the same.
-                atom_nodes: vec!["string_literal", "char_literal"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                vec!["string_literal", "char_literal"].into_iter().collect(),
+                vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")],
+                ts::Query::new(
                     language,
                     concat!(
                         include_str!("../../vendored_parsers/highlights/c.scm"),
@@ -171,255 +378,238 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Clojure => {
             let language = unsafe { tree_sitter_clojure() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")]
+                vec![].into_iter().collect(),
+                vec![("{", "}"), ("(", ")"), ("[", "]")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/clojure.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         CMake => {
             let language = unsafe { tree_sitter_cmake() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["argument"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")")].into_iter().collect(),
-                highlight_query: ts::Query::new(
+                vec!["argument"].into_iter().collect(),
+                vec![("(", ")")].into_iter().collect(),
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/cmake.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         CommonLisp => {
             let language = unsafe { tree_sitter_commonlisp() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["str_lit", "char_lit"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")")],
-                highlight_query: ts::Query::new(language, "").unwrap(),
-                sub_languages: vec![],
-            }
+                vec!["str_lit", "char_lit"].into_iter().collect(),
+                vec![("(", ")")],
+                ts::Query::new(language, "").unwrap(),
+            )
         }
         CSharp => {
             let language = unsafe { tree_sitter_c_sharp() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![
+                vec![
                     "string_literal",
                     "verbatim_string_literal",
                     "character_literal",
                 ]
                 .into_iter()
                 .collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")")],
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/c-sharp.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Css => {
             let language = unsafe { tree_sitter_css() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["integer_value", "float_value", "color_value"]
+                vec!["integer_value", "float_value", "color_value"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")")],
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/css.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Dart => {
             let language = unsafe { tree_sitter_dart() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_literal", "script_tag"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                vec!["string_literal", "script_tag"].into_iter().collect(),
+                vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/dart.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         EmacsLisp => {
             let language = unsafe { tree_sitter_elisp() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")]
+                vec![].into_iter().collect(),
+                vec![("{", "}"), ("(", ")"), ("[", "]")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/elisp.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Elixir => {
             let language = unsafe { tree_sitter_elixir() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "heredoc"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("do", "end")]
+                vec!["string", "heredoc"].into_iter().collect(),
+                vec![("(", ")"), ("{", "}"), ("do", "end")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/elixir.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Elm => {
             let language = unsafe { tree_sitter_elm() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_constant_expr"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")],
-                highlight_query: ts::Query::new(
+                vec!["string_constant_expr"].into_iter().collect(),
+                vec![("{", "}"), ("[", "]"), ("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/elm.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Elvish => {
             let language = unsafe { tree_sitter_elvish() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: [].into(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|")],
-                highlight_query: ts::Query::new(
+                [].into(),
+                vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/elvish.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Erlang => {
             let language = unsafe { tree_sitter_erlang() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: [].into(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                [].into(),
+                vec![("(", ")"), ("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/erlang.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Gleam => {
             let language = unsafe { tree_sitter_gleam() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: ["string"].into(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                ["string"].into(),
+                vec![("(", ")"), ("[", "]"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/gleam.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Go => {
             let language = unsafe { tree_sitter_go() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["interpreted_string_literal", "raw_string_literal"]
+                vec!["interpreted_string_literal", "raw_string_literal"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")]
+                vec![("{", "}"), ("[", "]"), ("(", ")")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/go.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Hack => {
             let language = unsafe { tree_sitter_hack() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["prefixed_string", "heredoc"].into_iter().collect(),
-                delimiter_tokens: vec![("[", "]"), ("(", ")"), ("<", ">"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                vec!["prefixed_string", "heredoc"].into_iter().collect(),
+                vec![("[", "]"), ("(", ")"), ("<", ">"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/hack.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Hare => {
             let language = unsafe { tree_sitter_hare() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_constant", "rune_constant"]
+                vec!["string_constant", "rune_constant"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("[", "]"), ("(", ")"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                vec![("[", "]"), ("(", ")"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/hare.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Haskell => {
             let language = unsafe { tree_sitter_haskell() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![("[", "]"), ("(", ")")],
-                highlight_query: ts::Query::new(
+                vec![].into_iter().collect(),
+                vec![("[", "]"), ("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/haskell.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Hcl => {
             let language = unsafe { tree_sitter_hcl() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_lit", "heredoc_template"].into_iter().collect(),
-                delimiter_tokens: vec![
+                vec!["string_lit", "heredoc_template"].into_iter().collect(),
+                vec![
                     ("[", "]"),
                     ("(", ")"),
                     ("{", "}"),
@@ -427,56 +617,53 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ("%{~", "~}"),
                     ("${", "}"),
                 ],
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/hcl.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
-        Html => {
+        Html | Svelte | Vue => {
+            let dialect = language;
             let language = unsafe { tree_sitter_html() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec![
-                    "doctype",
-                    "quoted_attribute_value",
-                    "comment",
-                    "raw_text",
-                    "tag_name",
-                    "text",
-                ]
-                .into_iter()
-                .collect(),
-                delimiter_tokens: vec![("<", ">"), ("<!", ">"), ("<!--", "-->")]
+                sub_languages: html_sub_languages(language, dialect),
+                interpolation_delimiters: match dialect {
+                    Svelte => Some(("{", "}")),
+                    Vue => Some(("{{", "}}")),
+                    _ => None,
+                },
+                ..TreeSitterConfig::new(
+                    language,
+                    vec![
+                        "doctype",
+                        "quoted_attribute_value",
+                        "comment",
+                        "raw_text",
+                        "tag_name",
+                        "text",
+                    ]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
-                    language,
-                    include_str!("../../vendored_parsers/highlights/html.scm"),
+                    vec![("<", ">"), ("<!", ">"), ("<!--", "-->")]
+                        .into_iter()
+                        .collect(),
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/html.scm"),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![
-                    TreeSitterSubLanguage {
-                        query: ts::Query::new(language, "(style_element (raw_text) @contents)")
-                            .unwrap(),
-                        parse_as: Css,
-                    },
-                    TreeSitterSubLanguage {
-                        query: ts::Query::new(language, "(script_element (raw_text) @contents)")
-                            .unwrap(),
-                        parse_as: JavaScript,
-                    },
-                ],
             }
         }
+        Ini => return None,
         Janet => {
             let language = unsafe { tree_sitter_janet_simple() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![
+                vec![].into_iter().collect(),
+                vec![
                     ("@{", "}"),
                     ("@(", ")"),
                     ("@[", "]"),
@@ -486,71 +673,70 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 ]
                 .into_iter()
                 .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/janet_simple.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Java => {
             let language = unsafe { tree_sitter_java() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec![].into_iter().collect(),
+                vec![("(", ")"), ("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/java.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         JavaScript | Jsx => {
             let language = unsafe { tree_sitter_javascript() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["string", "template_string", "regex"]
-                    .into_iter()
-                    .collect(),
-                delimiter_tokens: vec![
-                    ("[", "]"),
-                    ("(", ")"),
-                    ("{", "}"),
-                    // We may see a standalone < token in an expression
-                    // like 1 < 2, but we should never see both a < and a
-                    // > at the same level in JSX.
-                    ("<", ">"),
-                ],
-                highlight_query: ts::Query::new(
+                sub_languages: javascript_sub_languages(language),
+                ..TreeSitterConfig::new(
                     language,
-                    include_str!("../../vendored_parsers/highlights/javascript.scm"),
+                    vec!["string", "template_string", "regex"]
+                        .into_iter()
+                        .collect(),
+                    vec![
+                        ("[", "]"),
+                        ("(", ")"),
+                        ("{", "}"),
+                        // We may see a standalone < token in an expression
+                        // like 1 < 2, but we should never see both a < and a
+                        // > at the same level in JSX.
+                        ("<", ">"),
+                    ],
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/javascript.scm"),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         Json => {
             let language = unsafe { tree_sitter_json() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec!["string"].into_iter().collect(),
+                vec![("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/json.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Julia => {
             let language = unsafe { tree_sitter_julia() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![
+                vec![
                     "string_literal",
                     "prefixed_string_literal",
                     "command_literal",
@@ -558,130 +744,125 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 ]
                 .into_iter()
                 .collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]"), ("(", ")")],
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("[", "]"), ("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/julia.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Kotlin => {
             let language = unsafe { tree_sitter_kotlin() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["line_string_literal", "character_literal"]
+                vec!["line_string_literal", "character_literal"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")]
+                vec![("(", ")"), ("{", "}"), ("[", "]"), ("<", ">")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/kotlin.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Lua => {
             let language = unsafe { tree_sitter_lua() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")]
+                vec!["string"].into_iter().collect(),
+                vec![("(", ")"), ("{", "}"), ("[", "]")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/lua.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Make => {
             let language = unsafe { tree_sitter_make() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["shell_text"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")")].into_iter().collect(),
-                highlight_query: ts::Query::new(
-                    language,
-                    include_str!("../../vendored_parsers/highlights/make.scm"),
-                )
-                .unwrap(),
                 sub_languages: vec![TreeSitterSubLanguage {
                     query: ts::Query::new(language, "(shell_function (shell_command) @contents)")
                         .unwrap(),
-                    parse_as: Bash,
+                    parse_as: Some(Bash),
                 }],
+                ..TreeSitterConfig::new(
+                    language,
+                    vec!["shell_text"].into_iter().collect(),
+                    vec![("(", ")")].into_iter().collect(),
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/make.scm"),
+                    )
+                    .unwrap(),
+                )
             }
         }
         Nix => {
             let language = unsafe { tree_sitter_nix() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string_expression", "indented_string_expression"]
+                vec!["string_expression", "indented_string_expression"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]")].into_iter().collect(),
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("[", "]")].into_iter().collect(),
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/nix.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         OCaml => {
             let language = unsafe { tree_sitter_ocaml() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: OCAML_ATOM_NODES.iter().copied().collect(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                OCAML_ATOM_NODES.iter().copied().collect(),
+                vec![("(", ")"), ("[", "]"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/ocaml.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         OCamlInterface => {
             let language = unsafe { tree_sitter_ocaml_interface() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: OCAML_ATOM_NODES.iter().copied().collect(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                OCAML_ATOM_NODES.iter().copied().collect(),
+                vec![("(", ")"), ("[", "]"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/ocaml.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Pascal => {
             let language = unsafe { tree_sitter_pascal() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec![].into_iter().collect(),
+                vec![("(", ")"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/pascal.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Perl => {
             let language = unsafe { tree_sitter_perl() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![
+                vec![
                     "string_single_quoted",
                     "string_double_quoted",
                     "comments",
@@ -693,52 +874,75 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 ]
                 .into_iter()
                 .collect(),
-                delimiter_tokens: vec![("(", ")"), ("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec![("(", ")"), ("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/perl.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Php => {
             let language = unsafe { tree_sitter_php() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "encapsed_string"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                vec!["string", "encapsed_string"].into_iter().collect(),
+                vec![("(", ")"), ("[", "]"), ("{", "}")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/php.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Python => {
             let language = unsafe { tree_sitter_python() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["string"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")"), ("[", "]"), ("{", "}")],
-                highlight_query: ts::Query::new(
+                sub_languages: vec![TreeSitterSubLanguage {
+                    query: ts::Query::new(
+                        language,
+                        r#"((comment) @language
+                            .
+                            (expression_statement (assignment right: (string) @contents))
+                            (#match? @language "language="))
+                           ; A comment before the first statement of a block
+                           ; isn't inside the block.
+                           ((comment) @language
+                            .
+                            (block
+                              .
+                              (expression_statement (assignment right: (string) @contents)))
+                            (#match? @language "language="))
+                           (argument_list
+                             (comment) @language
+                             .
+                             (string) @contents
+                             (#match? @language "language="))"#,
+                    )
+                    .unwrap(),
+                    parse_as: None,
+                }],
+                ..TreeSitterConfig::new(
                     language,
-                    include_str!("../../vendored_parsers/highlights/python.scm"),
+                    vec!["string"].into_iter().collect(),
+                    vec![("(", ")"), ("[", "]"), ("{", "}")],
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/python.scm"),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         Qml => {
             let language = unsafe { tree_sitter_qmljs() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "template_string", "regex"]
+                vec!["string", "template_string", "regex"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                ts::Query::new(
                     language,
                     concat!(
                         include_str!("../../vendored_parsers/highlights/javascript.scm"),
@@ -747,31 +951,29 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         R => {
             let language = unsafe { tree_sitter_r() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "special"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec!["string", "special"].into_iter().collect(),
+                vec![("{", "}"), ("(", ")"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/r.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Ruby => {
             let language = unsafe { tree_sitter_ruby() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "heredoc_body", "regex"]
+                vec!["string", "heredoc_body", "regex"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: vec![
+                vec![
                     ("{", "}"),
                     ("(", ")"),
                     ("[", "]"),
@@ -780,125 +982,150 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                     ("begin", "end"),
                     ("class", "end"),
                 ],
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/ruby.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Rust => {
             let language = unsafe { tree_sitter_rust() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["char_literal", "string_literal"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                sub_languages: vec![
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(
+                            language,
+                            r#"(macro_invocation
+                                 macro: [(identifier) (scoped_identifier)] @_macro
+                                 (token_tree [(string_literal) (raw_string_literal)] @contents)
+                                 (#match? @_macro "^(sqlx::)?query(_as|_scalar)?(_unchecked)?$"))"#,
+                        )
+                        .unwrap(),
+                        parse_as: Some(Sql),
+                    },
+                    TreeSitterSubLanguage {
+                        query: ts::Query::new(
+                            language,
+                            r#"((line_comment) @language
+                                .
+                                (let_declaration
+                                  value: [(string_literal) (raw_string_literal)] @contents)
+                                (#match? @language "language="))"#,
+                        )
+                        .unwrap(),
+                        parse_as: None,
+                    },
+                ],
+                ..TreeSitterConfig::new(
                     language,
-                    include_str!("../../vendored_parsers/highlights/rust.scm"),
+                    vec!["char_literal", "string_literal"].into_iter().collect(),
+                    vec![("{", "}"), ("(", ")"), ("[", "]"), ("|", "|"), ("<", ">")],
+                    ts::Query::new(
+                        language,
+                        include_str!("../../vendored_parsers/highlights/rust.scm"),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         Scala => {
             let language = unsafe { tree_sitter_scala() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "template_string"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec!["string", "template_string"].into_iter().collect(),
+                vec![("{", "}"), ("(", ")"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/scala.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Sql => {
             let language = unsafe { tree_sitter_sql() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string", "identifier"].into_iter().collect(),
-                delimiter_tokens: vec![("(", ")")],
-                highlight_query: ts::Query::new(
+                vec!["string", "identifier"].into_iter().collect(),
+                vec![("(", ")")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/sql.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Swift => {
             let language = unsafe { tree_sitter_swift() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: ["line_string_literal"].into(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                ["line_string_literal"].into(),
+                vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/swift.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Toml => {
             let language = unsafe { tree_sitter_toml() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["string"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("[", "]")],
-                highlight_query: ts::Query::new(
+                vec!["string"].into_iter().collect(),
+                vec![("{", "}"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/toml.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Tsx => {
             let language = unsafe { tree_sitter_tsx() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["string", "template_string"].into_iter().collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                sub_languages: javascript_sub_languages(language),
+                ..TreeSitterConfig::new(
                     language,
-                    concat!(
-                        include_str!("../../vendored_parsers/highlights/javascript.scm"),
-                        include_str!("../../vendored_parsers/highlights/typescript.scm"),
-                    ),
+                    vec!["string", "template_string"].into_iter().collect(),
+                    vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                    ts::Query::new(
+                        language,
+                        concat!(
+                            include_str!("../../vendored_parsers/highlights/javascript.scm"),
+                            include_str!("../../vendored_parsers/highlights/typescript.scm"),
+                        ),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         TypeScript => {
             let language = unsafe { tree_sitter_typescript() };
             TreeSitterConfig {
-                language,
-                atom_nodes: vec!["string", "template_string", "regex", "predefined_type"]
-                    .into_iter()
-                    .collect(),
-                delimiter_tokens: vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
-                highlight_query: ts::Query::new(
+                sub_languages: javascript_sub_languages(language),
+                ..TreeSitterConfig::new(
                     language,
-                    concat!(
-                        include_str!("../../vendored_parsers/highlights/javascript.scm"),
-                        include_str!("../../vendored_parsers/highlights/typescript.scm"),
-                    ),
+                    vec!["string", "template_string", "regex", "predefined_type"]
+                        .into_iter()
+                        .collect(),
+                    vec![("{", "}"), ("(", ")"), ("[", "]"), ("<", ">")],
+                    ts::Query::new(
+                        language,
+                        concat!(
+                            include_str!("../../vendored_parsers/highlights/javascript.scm"),
+                            include_str!("../../vendored_parsers/highlights/typescript.scm"),
+                        ),
+                    )
+                    .unwrap(),
                 )
-                .unwrap(),
-                sub_languages: vec![],
             }
         }
         Yaml => {
             let language = unsafe { tree_sitter_yaml() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec![
+                vec![
                     "string_scalar",
                     "double_quote_scalar",
                     "single_quote_scalar",
@@ -906,34 +1133,40 @@ pub fn from_language(language: guess::Language) -> TreeSitterConfig {
                 ]
                 .into_iter()
                 .collect(),
-                delimiter_tokens: (vec![("{", "}"), ("(", ")"), ("[", "]")]),
-                highlight_query: ts::Query::new(
+                vec![("{", "}"), ("(", ")"), ("[", "]")],
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/yaml.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
         Zig => {
             let language = unsafe { tree_sitter_zig() };
-            TreeSitterConfig {
+            TreeSitterConfig::new(
                 language,
-                atom_nodes: vec!["STRINGLITERALSINGLE", "BUILTINIDENTIFIER"]
+                vec!["STRINGLITERALSINGLE", "BUILTINIDENTIFIER"]
                     .into_iter()
                     .collect(),
-                delimiter_tokens: (vec![("{", "}"), ("[", "]"), ("(", ")")])
+                vec![("{", "}"), ("[", "]"), ("(", ")")]
                     .into_iter()
                     .collect(),
-                highlight_query: ts::Query::new(
+                ts::Query::new(
                     language,
                     include_str!("../../vendored_parsers/highlights/zig.scm"),
                 )
                 .unwrap(),
-                sub_languages: vec![],
-            }
+            )
         }
-    }
+    };
+
+    Some(TreeSitterConfig {
+        // Code examples in comments are usually in the same language
+        // as the file. For example, rustdoc treats a bare ``` fence
+        // as Rust.
+        code_block_language: Some(language),
+        ..config
+    })
 }
 
 /// Parse `src` with tree-sitter.
@@ -955,32 +1188,157 @@ pub fn parse_subtrees(
     tree: &tree_sitter::Tree,
 ) -> HashMap<usize, (tree_sitter::Tree, TreeSitterConfig, HighlightedNodeIds)> {
     let mut subtrees = HashMap::new();
+    parse_subtrees_at_depth(src, config, tree, 1, &mut subtrees);
+    subtrees
+}
+
+/// The byte range and points of the content of `node`. For nodes
+/// like template strings, this excludes the surrounding delimiters.
+fn sub_language_range(node: &ts::Node, src: &str) -> ts::Range {
+    lazy_static! {
+        // A string token without child nodes, such as a Rust
+        // r#"raw string"#.
+        static ref STRING_TOKEN_RE: Regex =
+            Regex::new(r#"^([a-zA-Z]*#*(?:"+|'+|`))(?s:.*?)((?:"+|'+|`)#*)$"#).unwrap();
+    }
+
+    let mut range = node.range();
+
+    if node.child_count() == 0 && node.kind().contains("string") {
+        if let Some(cap) = STRING_TOKEN_RE.captures(&src[range.start_byte..range.end_byte]) {
+            let start_byte = range.start_byte + cap[1].len();
+            let end_byte = range.end_byte - cap[2].len();
+            if start_byte < end_byte {
+                range.start_point.column += cap[1].len();
+                range.end_point.column -= cap[2].len();
+                range.start_byte = start_byte;
+                range.end_byte = end_byte;
+            }
+        }
+        return range;
+    }
+
+    // Heredoc bodies end with the closing delimiter, e.g. `EOF`.
+    if node.kind() == "heredoc_body" {
+        let body = &src[range.start_byte..range.end_byte];
+        if let Some(i) = body.rfind('\n') {
+            range.end_byte = range.start_byte + i + 1;
+            range.end_point = ts::Point::new(range.end_point.row, 0);
+        }
+        return range;
+    }
+
+    let first_child = node.child(0);
+    let last_child = node.child(node.child_count().saturating_sub(1));
+    if let (Some(first_child), Some(last_child)) = (first_child, last_child) {
+        if node.child_count() >= 2 && !first_child.is_named() && !last_child.is_named() {
+            range.start_byte = first_child.end_byte();
+            range.start_point = first_child.end_position();
+            range.end_byte = last_child.start_byte();
+            range.end_point = last_child.start_position();
+        }
+    }
+
+    range
+}
+
+/// The language of a sub-language match, based on its captures:
+///
+/// * `@language`: a comment containing `language=NAME`.
+/// * `@interpreter`: a command such as `python3` or `psql`.
+/// * `@delimiter`: a heredoc delimiter naming a language, such as
+///   `<<'SQL'`. This is only used if `@interpreter` isn't a known
+///   interpreter, e.g. `cat`.
+fn language_from_captures(
+    query: &ts::Query,
+    m: &ts::QueryMatch,
+    src: &str,
+) -> Option<guess::Language> {
+    let capture_text = |name: &str| {
+        query
+            .capture_index_for_name(name)
+            .and_then(|i| m.nodes_for_capture_index(i).next())
+            .map(|node| &src[node.start_byte()..node.end_byte()])
+    };
+
+    if let Some(annotation) = capture_text("language") {
+        return guess::from_language_annotation(annotation);
+    }
+    if let Some(language) = capture_text("interpreter").and_then(guess::from_interpreter) {
+        return Some(language);
+    }
+
+    // Ignore short delimiters, so a `<<D` heredoc isn't treated
+    // as a .d file.
+    let delimiter = capture_text("delimiter")?.trim_matches(|c| c == '\'' || c == '"');
+    if delimiter.len() >= 2 {
+        guess::from_language_name(delimiter)
+    } else {
+        None
+    }
+}
+
+/// Parse the sub-languages of `tree`, and the sub-languages inside
+/// those, adding them all to `subtrees`.
+fn parse_subtrees_at_depth(
+    src: &str,
+    config: &TreeSitterConfig,
+    tree: &tree_sitter::Tree,
+    depth: usize,
+    subtrees: &mut HashMap<usize, (tree_sitter::Tree, TreeSitterConfig, HighlightedNodeIds)>,
+) {
+    if depth > MAX_SUB_LANGUAGE_DEPTH {
+        return;
+    }
 
     for language in &config.sub_languages {
+        let capture_i = language
+            .query
+            .capture_index_for_name("contents")
+            .expect("Sub-language queries should capture @contents");
+
         let mut query_cursor = tree_sitter::QueryCursor::new();
         for m in query_cursor.matches(&language.query, tree.root_node(), src.as_bytes()) {
-            let node = m.nodes_for_capture_index(0).next().unwrap();
-            if node.byte_range().is_empty() {
+            let node = match m.nodes_for_capture_index(capture_i).next() {
+                Some(node) => node,
+                None => continue,
+            };
+            if subtrees.contains_key(&node.id()) {
+                // An earlier sub-language has already claimed this
+                // node, e.g. <script lang="ts"> rather than <script>.
+                continue;
+            }
+            let range = sub_language_range(&node, src);
+            if range.start_byte >= range.end_byte {
                 continue;
             }
 
-            let subconfig = from_language(language.parse_as);
+            let parse_as = match language.parse_as {
+                Some(parse_as) => Some(parse_as),
+                None => language_from_captures(&language.query, &m, src),
+            };
+            let subconfig = match parse_as.and_then(from_language) {
+                Some(subconfig) => subconfig,
+                None => continue,
+            };
+
             let mut parser = ts::Parser::new();
             parser
                 .set_language(subconfig.language)
                 .expect("Incompatible tree-sitter version");
             parser
-                .set_included_ranges(&[node.range()])
+                .set_included_ranges(&[range])
                 .expect("Incompatible tree-sitter version");
 
             let tree = parser.parse(src, None).unwrap();
             let sub_highlights = tree_highlights(&tree, src, &subconfig);
 
+            // Node IDs are unique across trees, so nested subtrees
+            // can share a map.
+            parse_subtrees_at_depth(src, &subconfig, &tree, depth + 1, subtrees);
             subtrees.insert(node.id(), (tree, subconfig, sub_highlights));
         }
     }
-
-    subtrees
 }
 
 /// Calculate which tree-sitter node IDs should have which syntax
@@ -1150,6 +1508,7 @@ pub fn to_syntax<'a>(
     let subtrees = parse_subtrees(src, config, tree);
 
     let nl_pos = NewlinePositions::from(src);
+    let configs = CodeBlockConfigs::default();
     let mut cursor = tree.walk();
 
     // The tree always has a single root, whereas we want nodes for
@@ -1160,6 +1519,7 @@ pub fn to_syntax<'a>(
         arena,
         src,
         &nl_pos,
+        &configs,
         &mut cursor,
         config,
         &highlights,
@@ -1242,6 +1602,7 @@ fn all_syntaxes_from_cursor<'a>(
     arena: &'a Arena<Syntax<'a>>,
     src: &str,
     nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
@@ -1251,15 +1612,17 @@ fn all_syntaxes_from_cursor<'a>(
     let mut result: Vec<&Syntax> = vec![];
 
     loop {
-        result.extend(syntax_from_cursor(
+        result.extend(child_syntax_from_cursor(
             arena,
             src,
             nl_pos,
+            configs,
             cursor,
             config,
             highlights,
             subtrees,
             ignore_comments,
+            &mut 0,
         ));
 
         if !cursor.goto_next_sibling() {
@@ -1270,12 +1633,137 @@ fn all_syntaxes_from_cursor<'a>(
     result
 }
 
+/// Is this tree-sitter node a comment?
+fn is_comment(node: &ts::Node, highlights: &HighlightedNodeIds) -> bool {
+    // 'extra' nodes in tree-sitter are comments. Most parsers use
+    // 'comment' as their comment node name, but if they don't we
+    // can still detect comments by looking at their syntax
+    // highlighting.
+    !node.is_error()
+        && (node.is_extra()
+            || node.kind() == "comment"
+            || highlights.comment_ids.contains(&node.id()))
+}
+
+/// Is this tree-sitter node a comment on a single line?
+fn is_line_comment(node: &ts::Node, src: &str, highlights: &HighlightedNodeIds) -> bool {
+    is_comment(node, highlights)
+        && node.child_count() == 0
+        && !src[node.start_byte()..node.end_byte()]
+            .trim_end()
+            .contains('\n')
+}
+
+/// If the node at `cursor` is a single-line comment followed by
+/// single-line comments on the next lines, return a single comment
+/// atom for all of them, leaving `cursor` on the last one. Blank
+/// lines or code between comments end the block.
+///
+/// This allows us to compare reflowed comments word by word.
+fn line_comment_block<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
+    cursor: &mut ts::TreeCursor,
+    config: &TreeSitterConfig,
+    highlights: &HighlightedNodeIds,
+    node_i: &mut isize,
+) -> Option<&'a Syntax<'a>> {
+    let node = cursor.node();
+    if !is_line_comment(&node, src, highlights) {
+        return None;
+    }
+
+    let start = node.start_byte();
+    let mut end = src[..node.end_byte()].trim_end().len();
+    let mut merged = false;
+    while let Some(next) = cursor.node().next_sibling() {
+        let between = &src[end..next.start_byte()];
+        if !is_line_comment(&next, src, highlights)
+            || !between.trim().is_empty()
+            || between.matches('\n').count() != 1
+        {
+            break;
+        }
+
+        cursor.goto_next_sibling();
+        *node_i += 1;
+        end = src[..next.end_byte()].trim_end().len();
+        merged = true;
+    }
+
+    if !merged {
+        // Use the usual logic for lone comments.
+        return None;
+    }
+
+    if let Some(syntax) = syntax_with_code_blocks(
+        arena,
+        src,
+        nl_pos,
+        configs,
+        start,
+        end,
+        AtomKind::Comment,
+        config.code_block_language,
+    ) {
+        return Some(syntax);
+    }
+
+    let position = nl_pos.from_offsets(start, end);
+    Some(Syntax::new_atom(
+        arena,
+        position,
+        &src[start..end],
+        AtomKind::Comment,
+    ))
+}
+
+/// Convert the tree-sitter node at `cursor` to a difftastic syntax
+/// node, merging adjacent line comments. `node_i` is the index of
+/// the node among its siblings, and is incremented for each
+/// additional node consumed.
+fn child_syntax_from_cursor<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
+    cursor: &mut ts::TreeCursor,
+    config: &TreeSitterConfig,
+    highlights: &HighlightedNodeIds,
+    subtrees: &HashMap<usize, (tree_sitter::Tree, TreeSitterConfig, HighlightedNodeIds)>,
+    ignore_comments: bool,
+    node_i: &mut isize,
+) -> Option<&'a Syntax<'a>> {
+    if !ignore_comments && !subtrees.contains_key(&cursor.node().id()) {
+        if let Some(comment) = line_comment_block(
+            arena, src, nl_pos, configs, cursor, config, highlights, node_i,
+        ) {
+            return Some(comment);
+        }
+    }
+
+    syntax_from_cursor(
+        arena,
+        src,
+        nl_pos,
+        configs,
+        cursor,
+        config,
+        highlights,
+        subtrees,
+        ignore_comments,
+    )
+}
+
 /// Convert the tree-sitter node at `cursor` to a difftastic syntax
 /// node.
 fn syntax_from_cursor<'a>(
     arena: &'a Arena<Syntax<'a>>,
     src: &str,
     nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
@@ -1291,10 +1779,11 @@ fn syntax_from_cursor<'a>(
             arena,
             src,
             nl_pos,
+            configs,
             &mut sub_cursor,
             subconfig,
             subhighlights,
-            &HashMap::new(),
+            subtrees,
             ignore_comments,
         );
     }
@@ -1309,16 +1798,49 @@ fn syntax_from_cursor<'a>(
         );
 
         // Treat error nodes as atoms, even if they have children.
-        atom_from_cursor(arena, src, nl_pos, cursor, highlights, ignore_comments)
+        atom_from_cursor(
+            arena,
+            src,
+            nl_pos,
+            configs,
+            cursor,
+            config,
+            highlights,
+            ignore_comments,
+        )
     } else if config.atom_nodes.contains(node.kind()) {
+        if let (Some(delimiters), "text") = (config.interpolation_delimiters, node.kind()) {
+            if let Some(syntax) = syntax_with_interpolations(
+                arena,
+                src,
+                nl_pos,
+                configs,
+                node.start_byte(),
+                node.end_byte(),
+                delimiters,
+            ) {
+                return Some(syntax);
+            }
+        }
+
         // Treat nodes like string literals as atoms, regardless
         // of whether they have children.
-        atom_from_cursor(arena, src, nl_pos, cursor, highlights, ignore_comments)
+        atom_from_cursor(
+            arena,
+            src,
+            nl_pos,
+            configs,
+            cursor,
+            config,
+            highlights,
+            ignore_comments,
+        )
     } else if node.child_count() > 0 {
         Some(list_from_cursor(
             arena,
             src,
             nl_pos,
+            configs,
             cursor,
             config,
             highlights,
@@ -1326,7 +1848,16 @@ fn syntax_from_cursor<'a>(
             ignore_comments,
         ))
     } else {
-        atom_from_cursor(arena, src, nl_pos, cursor, highlights, ignore_comments)
+        atom_from_cursor(
+            arena,
+            src,
+            nl_pos,
+            configs,
+            cursor,
+            config,
+            highlights,
+            ignore_comments,
+        )
     }
 }
 
@@ -1336,6 +1867,7 @@ fn list_from_cursor<'a>(
     arena: &'a Arena<Syntax<'a>>,
     src: &str,
     nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
     cursor: &mut ts::TreeCursor,
     config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
@@ -1383,43 +1915,49 @@ fn list_from_cursor<'a>(
     loop {
         let node = cursor.node();
         if node_i < i {
-            before_delim.extend(syntax_from_cursor(
+            before_delim.extend(child_syntax_from_cursor(
                 arena,
                 src,
                 nl_pos,
+                configs,
                 cursor,
                 config,
                 highlights,
                 subtrees,
                 ignore_comments,
+                &mut node_i,
             ));
         } else if node_i == i {
             inner_open_content = &src[node.start_byte()..node.end_byte()];
             inner_open_position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
         } else if node_i < j {
-            between_delim.extend(syntax_from_cursor(
+            between_delim.extend(child_syntax_from_cursor(
                 arena,
                 src,
                 nl_pos,
+                configs,
                 cursor,
                 config,
                 highlights,
                 subtrees,
                 ignore_comments,
+                &mut node_i,
             ));
         } else if node_i == j {
             inner_close_content = &src[node.start_byte()..node.end_byte()];
             inner_close_position = nl_pos.from_offsets(node.start_byte(), node.end_byte());
         } else if node_i > j {
-            after_delim.extend(syntax_from_cursor(
+            after_delim.extend(child_syntax_from_cursor(
                 arena,
                 src,
                 nl_pos,
+                configs,
                 cursor,
                 config,
                 highlights,
                 subtrees,
                 ignore_comments,
+                &mut node_i,
             ));
         }
 
@@ -1469,7 +2007,9 @@ fn atom_from_cursor<'a>(
     arena: &'a Arena<Syntax<'a>>,
     src: &str,
     nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
     cursor: &mut ts::TreeCursor,
+    config: &TreeSitterConfig,
     highlights: &HighlightedNodeIds,
     ignore_comments: bool,
 ) -> Option<&'a Syntax<'a>> {
@@ -1496,15 +2036,7 @@ fn atom_from_cursor<'a>(
 
     let highlight = if node.is_error() {
         AtomKind::TreeSitterError
-    } else if node.is_extra()
-        || node.kind() == "comment"
-        || highlights.comment_ids.contains(&node.id())
-    {
-        // 'extra' nodes in tree-sitter are comments. Most parsers use
-        // 'comment' as their comment node name, but if they don't we
-        // can still detect comments by looking at their syntax
-        // highlighting.
-
+    } else if is_comment(&node, highlights) {
         if ignore_comments {
             return None;
         }
@@ -1520,9 +2052,397 @@ fn atom_from_cursor<'a>(
         AtomKind::Normal
     };
 
+    if matches!(highlight, AtomKind::Comment | AtomKind::String) && content.contains(FENCE) {
+        if let Some(syntax) = syntax_with_code_blocks(
+            arena,
+            src,
+            nl_pos,
+            configs,
+            node.start_byte(),
+            node.end_byte(),
+            highlight,
+            config.code_block_language,
+        ) {
+            return Some(syntax);
+        }
+    }
+
     Some(Syntax::new_atom(arena, position, content, highlight))
 }
 
+const FENCE: &str = "```";
+
+/// The configs for the languages of code blocks and interpolations,
+/// built when first needed. Building a config compiles its queries,
+/// so we only want to do it once per file.
+#[derive(Default)]
+struct CodeBlockConfigs {
+    configs: RefCell<HashMap<guess::Language, Option<Rc<TreeSitterConfig>>>>,
+}
+
+impl CodeBlockConfigs {
+    fn get(&self, language: guess::Language) -> Option<Rc<TreeSitterConfig>> {
+        self.configs
+            .borrow_mut()
+            .entry(language)
+            .or_insert_with(|| from_language(language).map(Rc::new))
+            .clone()
+    }
+}
+
+/// A fenced code block inside a comment or string, such as a
+/// ```` ```rust ```` example in a doc comment.
+#[derive(Debug, PartialEq)]
+struct FencedCodeBlock {
+    language: guess::Language,
+    /// The byte ranges of each line of code, excluding comment
+    /// markers at the start of the line.
+    code_ranges: Vec<(usize, usize)>,
+}
+
+/// Is `prefix` only whitespace and comment markers, such as `/// `
+/// or ` * `?
+fn is_line_prefix(prefix: &str) -> bool {
+    prefix
+        .chars()
+        .all(|c| c.is_whitespace() || "/*#;!%-\"'".contains(c))
+}
+
+/// Find the fenced code blocks in `src[start..end]` whose language we
+/// know. Blocks without a language name are in `default_language`.
+fn fenced_code_blocks(
+    src: &str,
+    start: usize,
+    end: usize,
+    default_language: Option<guess::Language>,
+) -> Vec<FencedCodeBlock> {
+    let mut res = vec![];
+    // The language and line prefix of the block we're inside, and
+    // the code lines seen so far. The language is `None` for blocks
+    // in languages we don't know, such as ```` ```text ````, so we
+    // still find where they end.
+    let mut open_block: Option<(Option<guess::Language>, &str, Vec<(usize, usize)>)> = None;
+
+    let mut line_start = start;
+    for line in src[start..end].split('\n') {
+        let line_end = line_start + line.len();
+
+        match &mut open_block {
+            None => {
+                if let Some(fence_i) = line.find(FENCE) {
+                    let prefix = &line[..fence_i];
+                    let info = &line[fence_i + FENCE.len()..];
+                    let name = info
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .next()
+                        .unwrap_or("");
+                    if is_line_prefix(prefix) {
+                        let language = if name.is_empty() {
+                            default_language
+                        } else {
+                            guess::from_language_name(name)
+                        };
+                        open_block = Some((language, prefix, vec![]));
+                    }
+                }
+            }
+            Some((language, prefix, code_ranges)) => {
+                let code_start = if let Some(code) = line.strip_prefix(*prefix) {
+                    line_end - code.len()
+                } else if line.trim_end() == prefix.trim_end() {
+                    // A blank line, e.g. `///` in a Rust doc comment.
+                    line_end
+                } else {
+                    // The line isn't indented as much as the fence.
+                    line_end - line.trim_start().len()
+                };
+
+                if src[code_start..line_end].trim() == FENCE {
+                    if let Some(language) = language {
+                        if !code_ranges.is_empty() {
+                            res.push(FencedCodeBlock {
+                                language: *language,
+                                code_ranges: std::mem::take(code_ranges),
+                            });
+                        }
+                    }
+                    open_block = None;
+                } else {
+                    // Include the newline, so the parser sees
+                    // separate lines.
+                    code_ranges.push((code_start, min(line_end + 1, end)));
+                }
+            }
+        }
+
+        line_start = line_end + 1;
+    }
+
+    res
+}
+
+/// Parse the code in `code_ranges` with `config`.
+fn parse_code_block<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
+    config: &TreeSitterConfig,
+    code_ranges: &[(usize, usize)],
+) -> Vec<&'a Syntax<'a>> {
+    let point = |offset: usize| {
+        let span = nl_pos.from_offsets(offset, offset)[0];
+        ts::Point::new(span.line.as_usize(), span.start_col as usize)
+    };
+    let ranges: Vec<ts::Range> = code_ranges
+        .iter()
+        .map(|(start, end)| ts::Range {
+            start_byte: *start,
+            end_byte: *end,
+            start_point: point(*start),
+            end_point: point(*end),
+        })
+        .collect();
+
+    let mut parser = ts::Parser::new();
+    parser
+        .set_language(config.language)
+        .expect("Incompatible tree-sitter version");
+    parser
+        .set_included_ranges(&ranges)
+        .expect("Incompatible tree-sitter version");
+    let tree = parser.parse(src, None).unwrap();
+
+    let highlights = tree_highlights(&tree, src, config);
+    let subtrees = parse_subtrees(src, config, &tree);
+
+    let mut cursor = tree.walk();
+    if !cursor.goto_first_child() {
+        return vec![];
+    }
+    all_syntaxes_from_cursor(
+        arena,
+        src,
+        nl_pos,
+        configs,
+        &mut cursor,
+        config,
+        &highlights,
+        &subtrees,
+        false,
+    )
+}
+
+/// Add an atom for the text in `src[start..end]` to `children`,
+/// excluding surrounding whitespace.
+fn push_text<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    start: usize,
+    end: usize,
+    kind: AtomKind,
+    children: &mut Vec<&'a Syntax<'a>>,
+) {
+    let content = src[start..end].trim_end();
+    let trimmed = content.trim_start();
+    if !trimmed.is_empty() {
+        let start = start + content.len() - trimmed.len();
+        children.push(Syntax::new_atom(
+            arena,
+            nl_pos.from_offsets(start, start + trimmed.len()),
+            trimmed,
+            kind,
+        ));
+    }
+}
+
+/// If the comment or string in `src[start..end]` contains fenced
+/// code blocks, return a list of the text around the blocks and the
+/// code inside them, parsed as the language of the block.
+///
+/// This gives structural diffs of code examples in doc comments.
+fn syntax_with_code_blocks<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
+    start: usize,
+    end: usize,
+    kind: AtomKind,
+    default_language: Option<guess::Language>,
+) -> Option<&'a Syntax<'a>> {
+    // Blocks in languages that we don't parse with tree-sitter are
+    // left as text.
+    let blocks: Vec<_> = fenced_code_blocks(src, start, end, default_language)
+        .into_iter()
+        .filter_map(|block| configs.get(block.language).map(|config| (block, config)))
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let mut children = vec![];
+    let mut text_start = start;
+    for (block, config) in &blocks {
+        let (code_start, _) = block.code_ranges[0];
+        let (_, code_end) = block.code_ranges[block.code_ranges.len() - 1];
+
+        push_text(
+            arena,
+            src,
+            nl_pos,
+            text_start,
+            code_start,
+            kind,
+            &mut children,
+        );
+        children.extend(parse_code_block(
+            arena,
+            src,
+            nl_pos,
+            configs,
+            config,
+            &block.code_ranges,
+        ));
+        text_start = code_end;
+    }
+    push_text(arena, src, nl_pos, text_start, end, kind, &mut children);
+
+    Some(Syntax::new_list(
+        arena,
+        "",
+        nl_pos.from_offsets(start, start),
+        children,
+        "",
+        nl_pos.from_offsets(end, end),
+    ))
+}
+
+/// Find the expressions in `src[start..end]` wrapped in `open` and
+/// `close`, such as `{{ user.name }}` in a Vue template. Returns the
+/// byte ranges of each expression, including its delimiters.
+fn interpolations(
+    src: &str,
+    start: usize,
+    end: usize,
+    open: &str,
+    close: &str,
+) -> Vec<(usize, usize)> {
+    let mut res = vec![];
+
+    let mut i = start;
+    while let Some(open_offset) = src[i..end].find(open) {
+        let open_start = i + open_offset;
+        let expr_start = open_start + open.len();
+
+        // Find the closing delimiter, allowing for braces inside the
+        // expression, e.g. `{ {a: 1}.a }`.
+        let mut depth: usize = 0;
+        let mut expr_end = None;
+        for (offset, c) in src[expr_start..end].char_indices() {
+            let j = expr_start + offset;
+            if depth == 0 && src[j..end].starts_with(close) {
+                expr_end = Some(j);
+                break;
+            }
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let expr_end = match expr_end {
+            Some(expr_end) => expr_end,
+            None => break,
+        };
+
+        // Svelte block tags, such as `{#if x}` and `{/if}`, aren't
+        // expressions.
+        let expr = src[expr_start..expr_end].trim();
+        if !expr.is_empty() && !expr.starts_with(|c| "#:/@".contains(c)) {
+            res.push((open_start, expr_end + close.len()));
+        }
+        i = expr_end + close.len();
+    }
+
+    res
+}
+
+/// If the text in `src[start..end]` contains interpolated
+/// expressions, return a list of the text and the expressions,
+/// parsed as JavaScript.
+fn syntax_with_interpolations<'a>(
+    arena: &'a Arena<Syntax<'a>>,
+    src: &str,
+    nl_pos: &NewlinePositions,
+    configs: &CodeBlockConfigs,
+    start: usize,
+    end: usize,
+    (open, close): (&str, &str),
+) -> Option<&'a Syntax<'a>> {
+    let ranges = interpolations(src, start, end, open, close);
+    if ranges.is_empty() {
+        return None;
+    }
+    let js_config = configs
+        .get(guess::Language::JavaScript)
+        .expect("JavaScript is parsed with tree-sitter");
+
+    let mut children = vec![];
+    let mut text_start = start;
+    for (interpolation_start, interpolation_end) in ranges {
+        push_text(
+            arena,
+            src,
+            nl_pos,
+            text_start,
+            interpolation_start,
+            AtomKind::Normal,
+            &mut children,
+        );
+
+        let expr_start = interpolation_start + open.len();
+        let expr_end = interpolation_end - close.len();
+        children.push(Syntax::new_list(
+            arena,
+            open,
+            nl_pos.from_offsets(interpolation_start, expr_start),
+            parse_code_block(
+                arena,
+                src,
+                nl_pos,
+                configs,
+                &js_config,
+                &[(expr_start, expr_end)],
+            ),
+            close,
+            nl_pos.from_offsets(expr_end, interpolation_end),
+        ));
+
+        text_start = interpolation_end;
+    }
+    push_text(
+        arena,
+        src,
+        nl_pos,
+        text_start,
+        end,
+        AtomKind::Normal,
+        &mut children,
+    );
+
+    Some(Syntax::new_list(
+        arena,
+        "",
+        nl_pos.from_offsets(start, start),
+        children,
+        "",
+        nl_pos.from_offsets(end, end),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1533,14 +2453,14 @@ mod tests {
     #[test]
     fn test_parse() {
         let arena = Arena::new();
-        let css_config = from_language(guess::Language::Css);
+        let css_config = from_language(guess::Language::Css).unwrap();
         parse(&arena, ".foo {}", &css_config, false);
     }
 
     #[test]
     fn test_parse_empty_file() {
         let arena = Arena::new();
-        let config = from_language(guess::Language::EmacsLisp);
+        let config = from_language(guess::Language::EmacsLisp).unwrap();
         let res = parse(&arena, "", &config, false);
 
         let expected: Vec<&Syntax> = vec![];
@@ -1552,7 +2472,7 @@ mod tests {
     #[test]
     fn test_subtrees() {
         let arena = Arena::new();
-        let config = from_language(guess::Language::Html);
+        let config = from_language(guess::Language::Html).unwrap();
         let res = parse(&arena, "<style>.a { color: red; }</style>", &config, false);
 
         match res[0] {
@@ -1587,4 +2507,116 @@ mod tests {
             }
         };
     }
+
+    /// The parsers used for the sub-languages found in `src`.
+    fn sub_language_parsers(src: &str, language: guess::Language) -> Vec<ts::Language> {
+        let config = from_language(language).unwrap();
+        let tree = to_tree(src, &config);
+        parse_subtrees(src, &config, &tree)
+            .into_values()
+            .map(|(_, subconfig, _)| subconfig.language)
+            .collect()
+    }
+
+    #[test]
+    fn test_sub_language_sqlx_macro() {
+        let src = "fn f() { sqlx::query!(\"SELECT id FROM users\"); }";
+        let sql = unsafe { tree_sitter_sql() };
+        assert_eq!(sub_language_parsers(src, guess::Language::Rust), vec![sql]);
+    }
+
+    #[test]
+    fn test_sub_language_annotation() {
+        let src = "# language=sql\nquery = \"SELECT id FROM users\"\n";
+        let sql = unsafe { tree_sitter_sql() };
+        assert_eq!(
+            sub_language_parsers(src, guess::Language::Python),
+            vec![sql]
+        );
+    }
+
+    #[test]
+    fn test_sub_language_tagged_template() {
+        let src = "const q = sql`SELECT id FROM users`;";
+        let sql = unsafe { tree_sitter_sql() };
+        assert_eq!(
+            sub_language_parsers(src, guess::Language::JavaScript),
+            vec![sql]
+        );
+    }
+
+    #[test]
+    fn test_sub_language_bash_heredoc_interpreter() {
+        let src = "psql <<EOF\nSELECT id FROM users;\nEOF\n";
+        let sql = unsafe { tree_sitter_sql() };
+        assert_eq!(sub_language_parsers(src, guess::Language::Bash), vec![sql]);
+    }
+
+    #[test]
+    fn test_sub_language_bash_heredoc_delimiter() {
+        let src = "cat > config.json <<'JSON'\n{\"a\": 1}\nJSON\n";
+        let json = unsafe { tree_sitter_json() };
+        assert_eq!(sub_language_parsers(src, guess::Language::Bash), vec![json]);
+    }
+
+    #[test]
+    fn test_sub_language_bash_command_argument() {
+        let src = "python3 -c 'print(1)'\n";
+        let python = unsafe { tree_sitter_python() };
+        assert_eq!(
+            sub_language_parsers(src, guess::Language::Bash),
+            vec![python]
+        );
+    }
+
+    #[test]
+    fn test_fenced_code_blocks() {
+        let src = "/// Example:\n///\n/// ```js\n/// foo();\n///\n/// bar();\n/// ```\n";
+        let blocks = fenced_code_blocks(src, 0, src.len() - 1, None);
+        assert_eq!(
+            blocks,
+            vec![FencedCodeBlock {
+                language: guess::Language::JavaScript,
+                code_ranges: vec![(31, 38), (41, 42), (46, 53)],
+            }]
+        );
+        assert_eq!(&src[31..38], "foo();\n");
+    }
+
+    #[test]
+    fn test_fenced_code_blocks_unknown_language() {
+        let src = "# ```text\n# foo\n# ```\n# bar";
+        assert_eq!(
+            fenced_code_blocks(src, 0, src.len(), Some(guess::Language::Python)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_fenced_code_blocks_default_language() {
+        let src = "/// ```\n/// foo();\n/// ```\n";
+        assert_eq!(
+            fenced_code_blocks(src, 0, src.len(), Some(guess::Language::JavaScript)),
+            vec![FencedCodeBlock {
+                language: guess::Language::JavaScript,
+                code_ranges: vec![(12, 19)],
+            }]
+        );
+        assert_eq!(fenced_code_blocks(src, 0, src.len(), None), vec![]);
+    }
+
+    #[test]
+    fn test_interpolations() {
+        let src = "Hi {{ user.name }}, {{ {a: 1}.a }}";
+        assert_eq!(
+            interpolations(src, 0, src.len(), "{{", "}}"),
+            vec![(3, 18), (20, 34)]
+        );
+    }
+
+    #[test]
+    fn test_interpolations_svelte_blocks() {
+        let src = "{#if x}{count + 1}{/if}";
+        assert_eq!(interpolations(src, 0, src.len(), "{", "}"), vec![(7, 18)]);
+    }
 }
@@ -0,0 +1,436 @@
+//! Staging individual hunks to the git index, like `git add -p`, but
+//! using difftastic's syntactic hunks.
+//!
+//! Git applies patches by line, so we compute a line-based diff
+//! between the index and the working tree, then stage the line
+//! changes that belong to each hunk the user selects.
+
+use std::{
+    ffi::OsString,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    diff::myers_diff,
+    display::hunks::Hunk,
+    files::read_or_die,
+    git::{self, FileMode},
+    options::{DiffOptions, DisplayOptions, FileArgument},
+    parse::guess_language::Language,
+    summary::DiffResult,
+};
+
+const HELP: &str = "y - stage this hunk
+n - do not stage this hunk
+a - stage this hunk and all later hunks in the file
+d - do not stage this hunk or any later hunks in the file
+q - quit; do not stage this hunk or any remaining ones
+? - print help";
+
+/// A region where the index and the working tree have different
+/// lines. Line numbers are zero-indexed.
+#[derive(Debug, PartialEq, Eq)]
+struct LineChange {
+    lhs_start: usize,
+    lhs_len: usize,
+    rhs_start: usize,
+    rhs_len: usize,
+}
+
+/// Find the regions of lines that differ between `lhs_lines` and
+/// `rhs_lines`.
+fn line_changes(lhs_lines: &[&str], rhs_lines: &[&str]) -> Vec<LineChange> {
+    let mut res: Vec<LineChange> = vec![];
+    let mut lhs_i = 0;
+    let mut rhs_i = 0;
+
+    for diff_res in myers_diff::slice_by_hash(lhs_lines, rhs_lines) {
+        if let myers_diff::DiffResult::Both(_, _) = diff_res {
+            lhs_i += 1;
+            rhs_i += 1;
+            continue;
+        }
+
+        // Extend the previous change if this line is adjacent to it.
+        let is_adjacent = match res.last() {
+            Some(change) => {
+                change.lhs_start + change.lhs_len == lhs_i
+                    && change.rhs_start + change.rhs_len == rhs_i
+            }
+            None => false,
+        };
+        if !is_adjacent {
+            res.push(LineChange {
+                lhs_start: lhs_i,
+                lhs_len: 0,
+                rhs_start: rhs_i,
+                rhs_len: 0,
+            });
+        }
+        let change = res.last_mut().expect("Pushed a change above");
+
+        match diff_res {
+            myers_diff::DiffResult::Left(_) => {
+                change.lhs_len += 1;
+                lhs_i += 1;
+            }
+            myers_diff::DiffResult::Right(_) => {
+                change.rhs_len += 1;
+                rhs_i += 1;
+            }
+            myers_diff::DiffResult::Both(_, _) => unreachable!(),
+        }
+    }
+
+    res
+}
+
+fn distance(x: usize, y: usize) -> usize {
+    if x > y {
+        x - y
+    } else {
+        y - x
+    }
+}
+
+/// The index of the hunk that `change` belongs to.
+///
+/// Some line changes aren't in any hunk, because they have no
+/// syntactic effect (e.g. reindenting). These are grouped with the
+/// nearest hunk.
+fn hunk_for_change(change: &LineChange, hunks: &[Hunk]) -> usize {
+    for (i, hunk) in hunks.iter().enumerate() {
+        let lhs_novel = (change.lhs_start..change.lhs_start + change.lhs_len)
+            .any(|line| hunk.novel_lhs.contains(&(line as u32).into()));
+        let rhs_novel = (change.rhs_start..change.rhs_start + change.rhs_len)
+            .any(|line| hunk.novel_rhs.contains(&(line as u32).into()));
+        if lhs_novel || rhs_novel {
+            return i;
+        }
+    }
+
+    let mut nearest = 0;
+    let mut nearest_distance = usize::MAX;
+    for (i, hunk) in hunks.iter().enumerate() {
+        for (lhs_line, rhs_line) in &hunk.lines {
+            let line_distance = match (lhs_line, rhs_line) {
+                (_, Some(rhs_line)) => distance(rhs_line.as_usize(), change.rhs_start),
+                (Some(lhs_line), None) => distance(lhs_line.as_usize(), change.lhs_start),
+                (None, None) => continue,
+            };
+            if line_distance < nearest_distance {
+                nearest = i;
+                nearest_distance = line_distance;
+            }
+        }
+    }
+    nearest
+}
+
+/// The index of the rendered hunk that each of `changes` belongs to.
+fn change_hunks(
+    changes: &[LineChange],
+    diff_result: &DiffResult,
+    rendered_hunk_count: usize,
+) -> Vec<usize> {
+    if rendered_hunk_count == diff_result.hunks.len() {
+        changes
+            .iter()
+            .map(|change| hunk_for_change(change, &diff_result.hunks))
+            .collect()
+    } else {
+        // Added or removed files are displayed as a single hunk, as
+        // are metadata changes when there are no hunks.
+        vec![0; changes.len()]
+    }
+}
+
+/// Format the range of a hunk in a unified diff header. Empty ranges
+/// refer to the line before the change.
+fn hunk_range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, len)
+    }
+}
+
+fn push_patch_line(patch: &mut String, prefix: char, line: &str) {
+    patch.push(prefix);
+    patch.push_str(line);
+    if !line.ends_with('\n') {
+        patch.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// A unified diff that applies `changes` to the index version of
+/// `path`. Hunks have no context lines.
+fn partial_patch(
+    path: &str,
+    lhs_lines: &[&str],
+    rhs_lines: &[&str],
+    changes: &[&LineChange],
+) -> String {
+    let mut patch = format!("diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", path);
+
+    // The number of lines that earlier changes have added, minus the
+    // number of lines they have removed.
+    let mut offset: isize = 0;
+    for change in changes {
+        let new_start = (change.lhs_start as isize + offset) as usize;
+        patch.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(change.lhs_start, change.lhs_len),
+            hunk_range(new_start, change.rhs_len)
+        ));
+
+        for line in &lhs_lines[change.lhs_start..change.lhs_start + change.lhs_len] {
+            push_patch_line(&mut patch, '-', line);
+        }
+        for line in &rhs_lines[change.rhs_start..change.rhs_start + change.rhs_len] {
+            push_patch_line(&mut patch, '+', line);
+        }
+
+        offset += change.rhs_len as isize - change.lhs_len as isize;
+    }
+
+    patch
+}
+
+/// Step through the hunks in every unstaged file, asking the user
+/// which ones to add to the index.
+pub fn stage_interactively(
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<Language>,
+    pathspecs: &[OsString],
+) {
+    let repo_root = git::repo_root();
+    let stdin = io::stdin();
+    let mut answers = stdin.lock().lines();
+
+    for changed_file in git::unstaged_files(pathspecs) {
+        let path = &changed_file.rhs_path;
+        let lhs_entry = match (&changed_file.lhs, &changed_file.rhs) {
+            (Some(lhs_entry), Some(rhs_entry))
+                if matches!(lhs_entry.mode, FileMode::Regular | FileMode::Executable)
+                    && matches!(rhs_entry.mode, FileMode::Regular | FileMode::Executable) =>
+            {
+                lhs_entry
+            }
+            _ => {
+                println!("Skipping {}: only modified files can be staged.\n", path);
+                continue;
+            }
+        };
+
+        let lhs_bytes = git::read_blob(&lhs_entry.hash);
+        let rhs_bytes = read_or_die(&repo_root.join(path));
+        let (lhs_src, rhs_src) = match (
+            std::str::from_utf8(&lhs_bytes),
+            std::str::from_utf8(&rhs_bytes),
+        ) {
+            (Ok(lhs_src), Ok(rhs_src)) => (lhs_src, rhs_src),
+            _ => {
+                println!("Skipping {}: only UTF-8 files can be staged.\n", path);
+                continue;
+            }
+        };
+
+        let diff_result = crate::diff_file_content(
+            path,
+            path,
+            &FileArgument::NamedPath(path.into()),
+            &FileArgument::NamedPath(path.into()),
+            &lhs_bytes,
+            &rhs_bytes,
+            display_options,
+            diff_options,
+            language_override,
+        );
+        // Changes to the mode or line endings are displayed with the
+        // first hunk, or on their own if there are no hunks.
+        if diff_result.hunks.is_empty() && diff_result.metadata_changes().is_empty() {
+            continue;
+        }
+        let rendered_hunks = crate::render_diff_result(display_options, &diff_result);
+
+        let lhs_lines: Vec<&str> = lhs_src.split_inclusive('\n').collect();
+        let rhs_lines: Vec<&str> = rhs_src.split_inclusive('\n').collect();
+        let changes = line_changes(&lhs_lines, &rhs_lines);
+        let change_hunks = change_hunks(&changes, &diff_result, rendered_hunks.len());
+
+        let mut selected = vec![false; rendered_hunks.len()];
+        let mut quit = false;
+        let mut i = 0;
+        while i < rendered_hunks.len() {
+            for line in &rendered_hunks[i] {
+                print!("{}", line);
+            }
+            print!(
+                "Stage this hunk ({}/{}) [y,n,a,d,q,?]? ",
+                i + 1,
+                rendered_hunks.len()
+            );
+            let _ = io::stdout().flush();
+
+            let answer = answers.next();
+            println!();
+            let answer = match answer {
+                Some(Ok(answer)) => answer,
+                _ => {
+                    quit = true;
+                    break;
+                }
+            };
+
+            match answer.trim() {
+                "y" => selected[i] = true,
+                "n" => {}
+                "a" => {
+                    for hunk_selected in &mut selected[i..] {
+                        *hunk_selected = true;
+                    }
+                    break;
+                }
+                "d" => break,
+                "q" => {
+                    quit = true;
+                    break;
+                }
+                _ => {
+                    println!("{}\n", HELP);
+                    continue;
+                }
+            }
+            i += 1;
+        }
+
+        let staged_changes: Vec<&LineChange> = changes
+            .iter()
+            .zip(change_hunks)
+            .filter(|(_, hunk_i)| selected[*hunk_i])
+            .map(|(change, _)| change)
+            .collect();
+        if !staged_changes.is_empty() {
+            git::apply_to_index(
+                &repo_root,
+                &partial_patch(path, &lhs_lines, &rhs_lines, &staged_changes),
+            );
+        }
+
+        if quit {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_line_changes() {
+        let lhs_lines = ["a\n", "b\n", "c\n", "d\n"];
+        let rhs_lines = ["a\n", "B\n", "c\n", "d\n", "e\n"];
+
+        assert_eq!(
+            line_changes(&lhs_lines, &rhs_lines),
+            vec![
+                LineChange {
+                    lhs_start: 1,
+                    lhs_len: 1,
+                    rhs_start: 1,
+                    rhs_len: 1,
+                },
+                LineChange {
+                    lhs_start: 4,
+                    lhs_len: 0,
+                    rhs_start: 4,
+                    rhs_len: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_patch() {
+        let lhs_lines = ["a\n", "b\n", "c"];
+        let rhs_lines = ["x\n", "a\n", "c\n"];
+        let changes = line_changes(&lhs_lines, &rhs_lines);
+
+        // Only stage the second change, removing `b` and fixing the
+        // missing trailing newline.
+        assert_eq!(
+            partial_patch("foo.txt", &lhs_lines, &rhs_lines, &[&changes[1]]),
+            "diff --git a/foo.txt b/foo.txt
+--- a/foo.txt
++++ b/foo.txt
+@@ -2,2 +2,1 @@
+-b
+-c
+\\ No newline at end of file
++c
+"
+        );
+    }
+
+    #[test]
+    fn test_hunk_for_change_nearest() {
+        let hunks = [
+            Hunk {
+                novel_lhs: [0.into()].iter().copied().collect(),
+                novel_rhs: [0.into()].iter().copied().collect(),
+                lines: vec![(Some(0.into()), Some(0.into()))],
+            },
+            Hunk {
+                novel_lhs: [20.into()].iter().copied().collect(),
+                novel_rhs: [20.into()].iter().copied().collect(),
+                lines: vec![(Some(20.into()), Some(20.into()))],
+            },
+        ];
+        let change = LineChange {
+            lhs_start: 18,
+            lhs_len: 1,
+            rhs_start: 18,
+            rhs_len: 1,
+        };
+
+        assert_eq!(hunk_for_change(&change, &hunks), 1);
+    }
+    #[test]
+    fn test_change_hunks_with_metadata_changes() {
+        let lhs_src: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let rhs_src = lhs_src
+            .replacen("2\n", "two\n", 1)
+            .replacen("19\n", "nineteen\n", 1)
+            .trim_end()
+            .to_owned();
+        let path = FileArgument::NamedPath("foo.txt".into());
+        let display_options = DisplayOptions::default();
+        let diff_result = crate::diff_file_content(
+            "foo.txt",
+            "foo.txt",
+            &path,
+            &path,
+            lhs_src.as_bytes(),
+            rhs_src.as_bytes(),
+            &display_options,
+            &DiffOptions::default(),
+            None,
+        );
+        assert!(!diff_result.metadata_changes().is_empty());
+
+        let rendered_hunks = crate::render_diff_result(&display_options, &diff_result);
+        assert_eq!(rendered_hunks.len(), 2);
+
+        let lhs_lines: Vec<&str> = lhs_src.split_inclusive('\n').collect();
+        let rhs_lines: Vec<&str> = rhs_src.split_inclusive('\n').collect();
+        let changes = line_changes(&lhs_lines, &rhs_lines);
+        assert_eq!(
+            change_hunks(&changes, &diff_result, rendered_hunks.len()),
+            vec![0, 1]
+        );
+    }
+}
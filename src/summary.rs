@@ -1,11 +1,122 @@
 //! Data types summarising the result of diffing content.
 
-use crate::{display::hunks::Hunk, parse::syntax::MatchedPos};
+use crate::{
+    diff::{
+        byte_diff::ByteChange,
+        dependency_changes::{describe_dependency_changes, DependencyChange},
+        renames::Rename,
+        table_changes::{describe_table_changes, TableChange},
+    },
+    display::hunks::Hunk,
+    git::FileMode,
+    parse::syntax::MatchedPos,
+};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum FileContent {
     Text(String),
-    Binary,
+    /// A binary file of `len` bytes. We only keep the bytes if we're
+    /// showing a hex diff of them.
+    Binary {
+        len: usize,
+        bytes: Option<Vec<u8>>,
+    },
+    /// A symlink tracked by git, with the path it points to.
+    Symlink(String),
+    /// A git submodule at this commit, or `None` if the submodule
+    /// doesn't exist on this side.
+    Submodule(Option<String>),
+}
+
+impl FileContent {
+    /// A short description of this kind of content, e.g. `symlink`.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            FileContent::Text(_) => "text file",
+            FileContent::Binary { .. } => "binary file",
+            FileContent::Symlink(_) => "symlink",
+            FileContent::Submodule(_) => "submodule",
+        }
+    }
+}
+
+/// The line breaks used in a text file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineBreaks {
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+impl LineBreaks {
+    fn name(self) -> &'static str {
+        match self {
+            LineBreaks::Lf => "LF",
+            LineBreaks::Crlf => "CRLF",
+            LineBreaks::Mixed => "mixed",
+        }
+    }
+}
+
+/// How the lines end in a text file. Difftastic ignores these when
+/// diffing, so we report changes to them separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndings {
+    /// The line breaks used, or `None` if the file has no line
+    /// breaks.
+    pub line_breaks: Option<LineBreaks>,
+    /// Whether the file ends with a newline, or `None` if the file is
+    /// empty.
+    pub trailing_newline: Option<bool>,
+}
+
+impl LineEndings {
+    pub fn from_src(src: &str) -> Self {
+        let lf_count = src.matches('\n').count();
+        let crlf_count = src.matches("\r\n").count();
+        let line_breaks = if lf_count == 0 {
+            None
+        } else if crlf_count == 0 {
+            Some(LineBreaks::Lf)
+        } else if crlf_count == lf_count {
+            Some(LineBreaks::Crlf)
+        } else {
+            Some(LineBreaks::Mixed)
+        };
+
+        Self {
+            line_breaks,
+            trailing_newline: if src.is_empty() {
+                None
+            } else {
+                Some(src.ends_with('\n'))
+            },
+        }
+    }
+}
+
+/// Describe the differences between the line endings of two versions
+/// of a file, as sentences.
+pub fn describe_line_ending_changes(lhs: &LineEndings, rhs: &LineEndings) -> Vec<String> {
+    let mut res = vec![];
+    if let (Some(lhs_breaks), Some(rhs_breaks)) = (lhs.line_breaks, rhs.line_breaks) {
+        if lhs_breaks != rhs_breaks {
+            res.push(format!(
+                "Line endings changed from {} to {}.",
+                lhs_breaks.name(),
+                rhs_breaks.name()
+            ));
+        }
+    }
+
+    // An added or deleted file doesn't gain or lose a newline, it
+    // gains or loses all its content.
+    match (lhs.trailing_newline, rhs.trailing_newline) {
+        (Some(true), Some(false)) => res.push("Newline at end of file removed.".to_owned()),
+        (Some(false), Some(true)) => res.push("Newline at end of file added.".to_owned()),
+        _ => {}
+    }
+    res
 }
 
 #[derive(Debug)]
@@ -23,12 +134,83 @@ pub struct DiffResult {
 
     pub has_byte_changes: bool,
     pub has_syntactic_changes: bool,
+
+    /// The old and new file modes, if git reported a mode change.
+    pub mode_change: Option<(FileMode, FileMode)>,
+    /// The line endings of each side, for text files.
+    pub line_endings: Option<(LineEndings, LineEndings)>,
+    /// Identifiers that were renamed consistently throughout the
+    /// file.
+    pub renames: Vec<Rename>,
+    /// Changes to the columns and cells of CSV and TSV files.
+    pub table_changes: Vec<TableChange>,
+    /// Dependencies that were added, removed or updated in a lockfile
+    /// or manifest.
+    pub dependency_changes: Vec<DependencyChange>,
+    /// The changed bytes in binary files, if we're showing a hex
+    /// diff.
+    pub byte_changes: Vec<ByteChange>,
 }
 
 impl DiffResult {
+    /// A result with no changes found between `lhs_src` and
+    /// `rhs_src`. Callers override the fields they know about.
+    pub fn new(
+        lhs_display_path: &str,
+        rhs_display_path: &str,
+        lhs_src: FileContent,
+        rhs_src: FileContent,
+    ) -> Self {
+        Self {
+            lhs_display_path: lhs_display_path.into(),
+            rhs_display_path: rhs_display_path.into(),
+            display_language: None,
+            language_used: None,
+            lhs_src,
+            rhs_src,
+            hunks: vec![],
+            lhs_positions: vec![],
+            rhs_positions: vec![],
+            has_byte_changes: false,
+            has_syntactic_changes: false,
+            mode_change: None,
+            line_endings: None,
+            renames: vec![],
+            table_changes: vec![],
+            dependency_changes: vec![],
+            byte_changes: vec![],
+        }
+    }
+
+    /// Describe changes to the file that aren't shown in the diff of
+    /// its content, such as its mode or line endings. Renamed
+    /// identifiers, changed table cells and changed dependencies are
+    /// also described here, as the diff doesn't show them clearly.
+    pub fn metadata_changes(&self) -> Vec<String> {
+        let mut res = vec![];
+        if let Some((lhs_mode, rhs_mode)) = self.mode_change {
+            res.push(format!(
+                "File mode changed from {} to {}.",
+                lhs_mode.as_git(),
+                rhs_mode.as_git()
+            ));
+        }
+        if let Some((lhs, rhs)) = &self.line_endings {
+            res.extend(describe_line_ending_changes(lhs, rhs));
+        }
+        res.extend(self.renames.iter().map(|rename| rename.describe()));
+        res.extend(describe_table_changes(&self.table_changes));
+        res.extend(describe_dependency_changes(&self.dependency_changes));
+        res
+    }
+
     pub fn has_reportable_change(&self) -> bool {
-        if matches!(self.lhs_src, FileContent::Binary)
-            || matches!(self.rhs_src, FileContent::Binary)
+        if !self.metadata_changes().is_empty() {
+            return true;
+        }
+
+        if matches!(self.lhs_src, FileContent::Binary { .. })
+            || matches!(self.rhs_src, FileContent::Binary { .. })
         {
             return self.has_byte_changes;
         }
@@ -36,3 +218,65 @@ impl DiffResult {
         self.has_syntactic_changes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_line_endings_from_src() {
+        assert_eq!(
+            LineEndings::from_src("a\r\nb\r\n"),
+            LineEndings {
+                line_breaks: Some(LineBreaks::Crlf),
+                trailing_newline: Some(true),
+            }
+        );
+        assert_eq!(
+            LineEndings::from_src("a\r\nb\nc"),
+            LineEndings {
+                line_breaks: Some(LineBreaks::Mixed),
+                trailing_newline: Some(false),
+            }
+        );
+        assert_eq!(
+            LineEndings::from_src("a"),
+            LineEndings {
+                line_breaks: None,
+                trailing_newline: Some(false),
+            }
+        );
+        assert_eq!(
+            LineEndings::from_src(""),
+            LineEndings {
+                line_breaks: None,
+                trailing_newline: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_describe_line_ending_changes() {
+        assert_eq!(
+            describe_line_ending_changes(
+                &LineEndings::from_src("a\nb\n"),
+                &LineEndings::from_src("a\r\nb")
+            ),
+            vec![
+                "Line endings changed from LF to CRLF.".to_owned(),
+                "Newline at end of file removed.".to_owned()
+            ]
+        );
+        assert!(describe_line_ending_changes(
+            &LineEndings::from_src("a"),
+            &LineEndings::from_src("a\r\nb")
+        )
+        .is_empty());
+        assert!(describe_line_ending_changes(
+            &LineEndings::from_src(""),
+            &LineEndings::from_src("a\n")
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! Diffing the members of two tar archives.
+//!
+//! We read ustar, GNU and pax archives, which covers the output of
+//! GNU tar, bsdtar and most libraries. Only regular files are
+//! diffed: directories, links and devices are ignored.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rayon::prelude::*;
+
+use crate::{
+    options::{DiffOptions, DisplayOptions, FileArgument},
+    parse::guess_language::Language,
+    summary::DiffResult,
+};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Read a NUL-terminated string from a header field.
+fn field_str(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Read a numeric header field, which is usually octal ASCII. GNU tar
+/// uses big-endian binary for values too large for octal, marked by
+/// setting the high bit of the first byte.
+fn field_num(field: &[u8]) -> Result<usize, String> {
+    if let Some(first) = field.first() {
+        if first & 0x80 != 0 {
+            let mut num: usize = (first & 0x7f) as usize;
+            for byte in &field[1..] {
+                num = num
+                    .checked_mul(256)
+                    .and_then(|num| num.checked_add(*byte as usize))
+                    .ok_or_else(|| "Size too large.".to_owned())?;
+            }
+            return Ok(num);
+        }
+    }
+
+    let s = field_str(field);
+    let s = s.trim_matches(|c| c == ' ' || c == '\0');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(s, 8).map_err(|_| format!("Invalid number in header: {:?}", s))
+}
+
+/// The `path` value in a pax extended header, if any.
+fn pax_path(data: &[u8]) -> Option<String> {
+    // Records have the form "LENGTH KEY=VALUE\n", where LENGTH
+    // includes the whole record.
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        if len <= space || len > rest.len() {
+            return None;
+        }
+
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    None
+}
+
+/// The regular files in the tar archive `bytes`, by path. If a path
+/// occurs more than once, the last member wins, as it does when
+/// extracting.
+fn members(bytes: &[u8]) -> Result<BTreeMap<String, &[u8]>, String> {
+    let mut res = BTreeMap::new();
+    let mut long_path: Option<String> = None;
+
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= bytes.len() {
+        let header = &bytes[offset..offset + BLOCK_SIZE];
+        // The archive ends with blocks of zeros.
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+
+        let size = field_num(&header[124..136])?;
+        let data_start = offset + BLOCK_SIZE;
+        let data_end = data_start
+            .checked_add(size)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| "Archive is truncated.".to_owned())?;
+        let data = &bytes[data_start..data_end];
+        offset = data_start + (size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+
+        let type_flag = header[156];
+        match type_flag {
+            // A GNU long name for the next member.
+            b'L' => {
+                long_path = Some(field_str(data));
+                continue;
+            }
+            // A pax extended header for the next member.
+            b'x' => {
+                if let Some(path) = pax_path(data) {
+                    long_path = Some(path);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let path = match long_path.take() {
+            Some(path) => path,
+            None => {
+                let name = field_str(&header[..100]);
+                // ustar archives split long paths into a prefix and
+                // a name.
+                let prefix = if &header[257..263] == b"ustar\0" {
+                    field_str(&header[345..500])
+                } else {
+                    String::new()
+                };
+                if prefix.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", prefix, name)
+                }
+            }
+        };
+
+        // Regular files, including old-style and contiguous files.
+        if matches!(type_flag, b'0' | b'\0' | b'7') {
+            let path = path.trim_start_matches("./").to_owned();
+            res.insert(path, data);
+        }
+    }
+
+    // Archives may omit the padding after the last member, so offset
+    // can be past the end.
+    let rest = bytes.get(offset..).unwrap_or_default();
+    if rest.iter().any(|b| *b != 0) {
+        return Err("Archive is truncated.".into());
+    }
+    Ok(res)
+}
+
+/// Diff the regular files in two tar archives, as if they were
+/// directories. Returns an error if either archive can't be read.
+pub fn diff_archives(
+    lhs_bytes: &[u8],
+    rhs_bytes: &[u8],
+    display_options: &DisplayOptions,
+    diff_options: &DiffOptions,
+    language_override: Option<Language>,
+) -> Result<impl ParallelIterator<Item = DiffResult>, String> {
+    let lhs_members = members(lhs_bytes)?;
+    let rhs_members = members(rhs_bytes)?;
+
+    let paths: BTreeSet<&String> = lhs_members.keys().chain(rhs_members.keys()).collect();
+    let member_pairs: Vec<(String, Option<Vec<u8>>, Option<Vec<u8>>)> = paths
+        .into_iter()
+        .map(|path| {
+            (
+                path.clone(),
+                lhs_members.get(path).map(|data| data.to_vec()),
+                rhs_members.get(path).map(|data| data.to_vec()),
+            )
+        })
+        .collect();
+
+    let display_options = display_options.clone();
+    let diff_options = diff_options.clone();
+    Ok(member_pairs
+        .into_par_iter()
+        .map(move |(path, lhs_data, rhs_data)| {
+            let file_argument = |data: &Option<Vec<u8>>| match data {
+                Some(_) => FileArgument::NamedPath(path.clone().into()),
+                None => FileArgument::DevNull,
+            };
+
+            let mut diff_result = crate::diff_file_content(
+                &path,
+                &path,
+                &file_argument(&lhs_data),
+                &file_argument(&rhs_data),
+                lhs_data.as_deref().unwrap_or_default(),
+                rhs_data.as_deref().unwrap_or_default(),
+                &display_options,
+                &diff_options,
+                language_override,
+            );
+
+            let display_path = match (&lhs_data, &rhs_data) {
+                (None, _) => Some(format!("{} (added)", path)),
+                (_, None) => Some(format!("{} (removed)", path)),
+                _ => None,
+            };
+            if let Some(display_path) = display_path {
+                diff_result.lhs_display_path = display_path.clone();
+                diff_result.rhs_display_path = display_path;
+            }
+            diff_result
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Build a ustar header block followed by `data`, padded to a
+    /// whole number of blocks.
+    fn member(name: &str, type_flag: u8, data: &[u8]) -> Vec<u8> {
+        let mut header = vec![0; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}", data.len());
+        header[124..135].copy_from_slice(size.as_bytes());
+        header[156] = type_flag;
+        header[257..263].copy_from_slice(b"ustar\0");
+
+        let mut res = header;
+        res.extend(data);
+        res.resize((res.len() + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE, 0);
+        res
+    }
+
+    #[test]
+    fn test_members() {
+        let pax_data = b"18 path=long/name\n";
+        let archive = [
+            member("./dir/", b'5', b""),
+            member("./dir/a.txt", b'0', b"hello\n"),
+            member("PaxHeader", b'x', pax_data),
+            member("short", b'0', b"world"),
+            vec![0; BLOCK_SIZE * 2],
+        ]
+        .concat();
+
+        let members = members(&archive).unwrap();
+        assert_eq!(
+            members.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("dir/a.txt".to_owned(), &b"hello\n"[..]),
+                ("long/name".to_owned(), &b"world"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_members_truncated() {
+        let mut archive = member("a.txt", b'0', &[b'x'; 1000]);
+        archive.truncate(BLOCK_SIZE + 10);
+        assert!(members(&archive).is_err());
+    }
+
+    #[test]
+    fn test_field_num() {
+        assert_eq!(field_num(b"00000000644\0"), Ok(0o644));
+        assert_eq!(field_num(b"\x80\0\0\0\0\0\0\0\0\0\x01\x00"), Ok(256));
+    }
+}